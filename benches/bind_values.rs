@@ -0,0 +1,125 @@
+//! Compares binding the parameters of a bulk insert one host call at a time
+//! (the way `SqliteConnection` used to do it) with a multi-row insert through
+//! diesel, which binds all parameters of a statement at once.
+//!
+//! Run with `cargo bench --bench bind_values`, which uses the lunatic runner
+//! configured in `.cargo/config.toml`. Natively, with the `native-test-host`
+//! feature, only the insert through diesel is measured, as the host functions
+//! can't be called one at a time there.
+
+use std::time::{Duration, Instant};
+
+use lunatic_diesel::connection::SimpleConnection;
+use lunatic_diesel::prelude::*;
+#[cfg(target_arch = "wasm32")]
+use lunatic_sqlite_api::guest_api::sqlite_guest_bindings as ffi;
+#[cfg(target_arch = "wasm32")]
+use lunatic_sqlite_api::wire_format::{BindKey, BindList, BindPair, BindValue};
+
+const ROWS: usize = 10_000;
+// 3,000 parameters per statement, far below SQLite's limit of 32,766
+const ROWS_PER_INSERT: usize = 1_000;
+const CREATE_TABLE: &str =
+    "CREATE TABLE events (id INTEGER PRIMARY KEY, kind TEXT NOT NULL, payload BLOB NOT NULL, score DOUBLE NOT NULL)";
+#[cfg(target_arch = "wasm32")]
+const INSERT: &str = "INSERT INTO events (kind, payload, score) VALUES (?, ?, ?)";
+
+lunatic_diesel::table! {
+    events {
+        id -> Integer,
+        kind -> Text,
+        payload -> Binary,
+        score -> Double,
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = events)]
+struct Event {
+    kind: String,
+    payload: Vec<u8>,
+    score: f64,
+}
+
+fn events() -> Vec<Event> {
+    (0..ROWS)
+        .map(|i| Event {
+            kind: format!("event kind number {}", i % 16),
+            payload: vec![(i % 256) as u8; 256],
+            score: i as f64 / 3.0,
+        })
+        .collect()
+}
+
+// One `bind_value` host call per parameter, each copying the value into
+// an owned single element `BindList`
+#[cfg(target_arch = "wasm32")]
+fn insert_binding_one_value_per_call(events: &[Event]) -> Duration {
+    let mut connection_id = 0u64;
+    let path = ":memory:";
+    unsafe {
+        ffi::open(
+            path.as_ptr(),
+            path.len(),
+            &mut connection_id as *mut u64 as *mut u32,
+        );
+        ffi::execute(
+            connection_id,
+            CREATE_TABLE.as_ptr(),
+            CREATE_TABLE.len() as u32,
+        );
+    }
+    let statement_id =
+        unsafe { ffi::query_prepare(connection_id, INSERT.as_ptr(), INSERT.len() as u32) };
+
+    let start = Instant::now();
+    for event in events {
+        let values = [
+            BindValue::Text(event.kind.as_str().to_owned()),
+            BindValue::Blob(event.payload.as_slice().to_owned()),
+            BindValue::Double(event.score),
+        ];
+        for (idx, value) in (1..).zip(values) {
+            let bind_list = BindList(vec![BindPair(BindKey::Numeric(idx), value)]);
+            let encoded = bincode::serialize(&bind_list).unwrap();
+            unsafe { ffi::bind_value(statement_id, encoded.as_ptr() as u32, encoded.len() as u32) };
+        }
+        unsafe {
+            ffi::sqlite3_step(statement_id);
+            ffi::statement_reset(statement_id);
+        }
+    }
+    let elapsed = start.elapsed();
+
+    unsafe { ffi::sqlite3_finalize(statement_id) };
+    elapsed
+}
+
+// Multi-row inserts through `SqliteConnection`, which binds all parameters
+// of each of them with a single host call
+fn insert_multiple_rows_at_once(events: &[Event]) -> Duration {
+    let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+    connection.batch_execute(CREATE_TABLE).unwrap();
+
+    let start = Instant::now();
+    for chunk in events.chunks(ROWS_PER_INSERT) {
+        lunatic_diesel::insert_into(self::events::table)
+            .values(chunk)
+            .execute(connection)
+            .unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let events = events();
+
+    println!("inserting {ROWS} rows with 3 bind values each");
+    #[cfg(target_arch = "wasm32")]
+    {
+        let one_per_call = insert_binding_one_value_per_call(&events);
+        println!("  one bind call per value:               {one_per_call:?}");
+    }
+    let multi_row = insert_multiple_rows_at_once(&events);
+    println!("  one bind call per {ROWS_PER_INSERT} row insert: {multi_row:?}");
+}
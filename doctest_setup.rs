@@ -0,0 +1,67 @@
+// Shared setup of the doc examples, included by them as
+// `include!("../../doctest_setup.rs")`. Like all tests of this crate they
+// only run natively, with the `native-test-host` feature.
+#[allow(unused_imports)]
+use lunatic_diesel::prelude::*;
+
+fn connection_no_data() -> SqliteConnection {
+    SqliteConnection::establish(":memory:").unwrap()
+}
+
+#[allow(dead_code)]
+fn establish_connection() -> SqliteConnection {
+    let mut connection = connection_no_data();
+
+    diesel::sql_query(
+        "CREATE TABLE users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name VARCHAR NOT NULL
+        )",
+    )
+    .execute(&mut connection)
+    .unwrap();
+    diesel::sql_query("INSERT INTO users (name) VALUES ('Sean'), ('Tess')")
+        .execute(&mut connection)
+        .unwrap();
+
+    diesel::sql_query(
+        "CREATE TABLE animals (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            species VARCHAR NOT NULL,
+            legs INTEGER NOT NULL,
+            name VARCHAR
+        )",
+    )
+    .execute(&mut connection)
+    .unwrap();
+    diesel::sql_query(
+        "INSERT INTO animals (species, legs, name) VALUES
+            ('dog', 4, 'Jack'),
+            ('spider', 8, null)",
+    )
+    .execute(&mut connection)
+    .unwrap();
+
+    connection
+}
+
+#[allow(dead_code)]
+mod schema {
+    use diesel::table;
+
+    table! {
+        users {
+            id -> Integer,
+            name -> Text,
+        }
+    }
+
+    table! {
+        animals {
+            id -> Integer,
+            species -> Text,
+            legs -> Integer,
+            name -> Nullable<Text>,
+        }
+    }
+}
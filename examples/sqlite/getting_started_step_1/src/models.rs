@@ -0,0 +1,9 @@
+use diesel::prelude::*;
+
+#[derive(Queryable)]
+pub struct Post {
+    pub id: i32,
+    pub title: String,
+    pub body: String,
+    pub published: bool,
+}
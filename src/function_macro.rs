@@ -0,0 +1,268 @@
+/// Declares a sql function for use in your code.
+///
+/// This is a drop-in replacement for diesel's [`sql_function!`](diesel::expression::functions::sql_function)
+/// macro. Diesel only generates `register_impl` functions when it is built
+/// with its own sqlite backend, so this macro forwards the declaration to
+/// diesel and additionally generates `register_impl` and
+/// `register_nondeterministic_impl` for [`SqliteConnection`](crate::sqlite::SqliteConnection).
+/// These are only generated with the `host-callbacks` feature, as they need
+/// host functions that no released lunatic runtime provides yet.
+///
+/// Generic functions and functions without a return type are forwarded to
+/// diesel unchanged, as they cannot be implemented on SQLite.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use diesel::sql_types::Integer;
+///
+/// sql_function!(fn my_add(x: Integer, y: Integer) -> Integer);
+///
+/// let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+/// my_add::register_impl(connection, |x: i32, y: i32| x + y).unwrap();
+///
+/// let added = diesel::select(my_add(1, 2)).get_result::<i32>(connection);
+/// assert_eq!(Ok(3), added);
+/// ```
+#[macro_export]
+macro_rules! sql_function {
+    // `#[aggregate]` and `#[sql_name]` are only understood by diesel, so they
+    // are collected separately from any other attribute
+    (@attrs [$($attrs:tt)*] [$($aggregate:tt)*] [$($sql_name:tt)*] #[aggregate] $($rest:tt)*) => {
+        $crate::sql_function!(@attrs [$($attrs)*] [aggregate] [$($sql_name)*] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] [$($aggregate:tt)*] [$($sql_name:tt)*] #[sql_name = $name:literal] $($rest:tt)*) => {
+        $crate::sql_function!(@attrs [$($attrs)*] [$($aggregate)*] [$name] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] [$($aggregate:tt)*] [$($sql_name:tt)*] #[$meta:meta] $($rest:tt)*) => {
+        $crate::sql_function!(@attrs [$($attrs)* #[$meta]] [$($aggregate)*] [$($sql_name)*] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] [$($aggregate:tt)*] [$($sql_name:tt)*]
+        fn $fn_name:ident ($($arg_name:ident : $arg_type:ty),* $(,)?) -> $return_type:ty $(;)?
+    ) => {
+        $crate::sql_function!(@declare [$($attrs)*] [$($aggregate)*] [$($sql_name)*]
+            $fn_name ($($arg_name : $arg_type),*) -> $return_type
+        );
+    };
+    // anything else can't be registered on a `SqliteConnection`
+    (@attrs [$($attrs:tt)*] [$($aggregate:tt)*] [$($sql_name:tt)*] $($rest:tt)*) => {
+        $crate::expression::functions::sql_function! {
+            $($attrs)*
+            $(#[$aggregate])*
+            $(#[sql_name = $sql_name])*
+            $($rest)*
+        }
+    };
+
+    (@declare [$($attrs:tt)*] [$($aggregate:tt)*] [$($sql_name:tt)*]
+        $fn_name:ident ($($arg_name:ident : $arg_type:ty),*) -> $return_type:ty
+    ) => {
+        $($attrs)*
+        #[allow(non_camel_case_types)]
+        pub fn $fn_name<$($arg_name),*>($($arg_name: $arg_name),*) -> $fn_name::HelperType<$($arg_name),*>
+        where
+            $($arg_name: $crate::expression::AsExpression<$arg_type>,)*
+        {
+            $fn_name::__expr::$fn_name($($arg_name),*)
+        }
+
+        #[doc(hidden)]
+        #[allow(non_camel_case_types, non_snake_case, unused_imports)]
+        pub(crate) mod $fn_name {
+            use super::*;
+
+            pub(crate) mod __expr {
+                use super::*;
+
+                $crate::expression::functions::sql_function! {
+                    $(#[$aggregate])*
+                    $(#[sql_name = $sql_name])*
+                    fn $fn_name($($arg_name: $arg_type),*) -> $return_type;
+                }
+            }
+
+            pub use self::__expr::$fn_name::{$fn_name, HelperType};
+
+            $crate::__sqlite_register_impl! {
+                $crate::sql_function!(@register [$($aggregate)*]
+                    ($crate::sql_function!(@sql_name $fn_name $($sql_name)*))
+                    ($($arg_name : $arg_type),*) -> $return_type
+                );
+            }
+        }
+    };
+
+    (@sql_name $fn_name:ident) => {
+        stringify!($fn_name)
+    };
+    (@sql_name $fn_name:ident $sql_name:literal) => {
+        $sql_name
+    };
+
+    (@register [] ($sql_name:expr) () -> $return_type:ty) => {
+        #[allow(dead_code)]
+        /// Registers an implementation for this function on the given connection
+        ///
+        /// This function must be called for every `SqliteConnection` before
+        /// this SQL function can be used on SQLite. The implementation must be
+        /// deterministic (returns the same result given the same arguments). If
+        /// the function is nondeterministic, call
+        /// `register_nondeterministic_impl` instead.
+        pub fn register_impl<F, Ret>(
+            conn: &mut $crate::sqlite::SqliteConnection,
+            f: F,
+        ) -> $crate::QueryResult<()>
+        where
+            F: Fn() -> Ret + std::panic::UnwindSafe + Send + 'static,
+            Ret: $crate::serialize::ToSql<$return_type, $crate::sqlite::Sqlite>,
+        {
+            conn.register_noarg_sql_function::<$return_type, _, _>($sql_name, true, f)
+        }
+
+        #[allow(dead_code)]
+        /// Registers an implementation for this function on the given connection
+        ///
+        /// This function must be called for every `SqliteConnection` before
+        /// this SQL function can be used on SQLite.
+        /// `register_nondeterministic_impl` should only be used if your
+        /// function can return different results with the same arguments (e.g.
+        /// `random`). If your function is deterministic, you should call
+        /// `register_impl` instead.
+        pub fn register_nondeterministic_impl<F, Ret>(
+            conn: &mut $crate::sqlite::SqliteConnection,
+            f: F,
+        ) -> $crate::QueryResult<()>
+        where
+            F: FnMut() -> Ret + std::panic::UnwindSafe + Send + 'static,
+            Ret: $crate::serialize::ToSql<$return_type, $crate::sqlite::Sqlite>,
+        {
+            conn.register_noarg_sql_function::<$return_type, _, _>($sql_name, false, f)
+        }
+    };
+    (@register [] ($sql_name:expr) ($($arg_name:ident : $arg_type:ty),+) -> $return_type:ty) => {
+        #[allow(dead_code)]
+        /// Registers an implementation for this function on the given connection
+        ///
+        /// This function must be called for every `SqliteConnection` before
+        /// this SQL function can be used on SQLite. The implementation must be
+        /// deterministic (returns the same result given the same arguments). If
+        /// the function is nondeterministic, call
+        /// `register_nondeterministic_impl` instead.
+        pub fn register_impl<F, Ret, $($arg_name,)*>(
+            conn: &mut $crate::sqlite::SqliteConnection,
+            f: F,
+        ) -> $crate::QueryResult<()>
+        where
+            F: Fn($($arg_name,)*) -> Ret + std::panic::UnwindSafe + Send + 'static,
+            ($($arg_name,)*): $crate::deserialize::FromSqlRow<($($arg_type,)*), $crate::sqlite::Sqlite>
+                + $crate::deserialize::StaticallySizedRow<($($arg_type,)*), $crate::sqlite::Sqlite>,
+            Ret: $crate::serialize::ToSql<$return_type, $crate::sqlite::Sqlite>,
+        {
+            conn.register_sql_function::<($($arg_type,)*), $return_type, _, _, _>(
+                $sql_name,
+                true,
+                move |($($arg_name,)*)| f($($arg_name,)*),
+            )
+        }
+
+        #[allow(dead_code)]
+        /// Registers an implementation for this function on the given connection
+        ///
+        /// This function must be called for every `SqliteConnection` before
+        /// this SQL function can be used on SQLite.
+        /// `register_nondeterministic_impl` should only be used if your
+        /// function can return different results with the same arguments (e.g.
+        /// `random`). If your function is deterministic, you should call
+        /// `register_impl` instead.
+        pub fn register_nondeterministic_impl<F, Ret, $($arg_name,)*>(
+            conn: &mut $crate::sqlite::SqliteConnection,
+            mut f: F,
+        ) -> $crate::QueryResult<()>
+        where
+            F: FnMut($($arg_name,)*) -> Ret + std::panic::UnwindSafe + Send + 'static,
+            ($($arg_name,)*): $crate::deserialize::FromSqlRow<($($arg_type,)*), $crate::sqlite::Sqlite>
+                + $crate::deserialize::StaticallySizedRow<($($arg_type,)*), $crate::sqlite::Sqlite>,
+            Ret: $crate::serialize::ToSql<$return_type, $crate::sqlite::Sqlite>,
+        {
+            conn.register_sql_function::<($($arg_type,)*), $return_type, _, _, _>(
+                $sql_name,
+                false,
+                move |($($arg_name,)*)| f($($arg_name,)*),
+            )
+        }
+    };
+    (@register [aggregate] ($sql_name:expr) ($arg_name:ident : $arg_type:ty) -> $return_type:ty) => {
+        #[allow(dead_code)]
+        /// Registers an implementation for this aggregate function on the given connection
+        ///
+        /// This function must be called for every `SqliteConnection` before
+        /// this SQL function can be used on SQLite. The implementation must be
+        /// deterministic (returns the same result given the same arguments).
+        pub fn register_impl<A, $arg_name>(
+            conn: &mut $crate::sqlite::SqliteConnection,
+        ) -> $crate::QueryResult<()>
+        where
+            A: $crate::sqlite::SqliteAggregateFunction<$arg_name>
+                + Send
+                + 'static
+                + std::panic::UnwindSafe
+                + std::panic::RefUnwindSafe,
+            A::Output: $crate::serialize::ToSql<$return_type, $crate::sqlite::Sqlite>,
+            $arg_name: $crate::deserialize::FromSqlRow<$arg_type, $crate::sqlite::Sqlite>
+                + $crate::deserialize::StaticallySizedRow<$arg_type, $crate::sqlite::Sqlite>
+                + std::panic::UnwindSafe
+                + 'static,
+        {
+            conn.register_aggregate_function::<$arg_type, $return_type, _, _, A>($sql_name)
+        }
+    };
+    (@register [aggregate] ($sql_name:expr) ($($arg_name:ident : $arg_type:ty),+) -> $return_type:ty) => {
+        #[allow(dead_code)]
+        /// Registers an implementation for this aggregate function on the given connection
+        ///
+        /// This function must be called for every `SqliteConnection` before
+        /// this SQL function can be used on SQLite. The implementation must be
+        /// deterministic (returns the same result given the same arguments).
+        pub fn register_impl<A, $($arg_name,)*>(
+            conn: &mut $crate::sqlite::SqliteConnection,
+        ) -> $crate::QueryResult<()>
+        where
+            A: $crate::sqlite::SqliteAggregateFunction<($($arg_name,)*)>
+                + Send
+                + 'static
+                + std::panic::UnwindSafe
+                + std::panic::RefUnwindSafe,
+            A::Output: $crate::serialize::ToSql<$return_type, $crate::sqlite::Sqlite>,
+            ($($arg_name,)*): $crate::deserialize::FromSqlRow<($($arg_type,)*), $crate::sqlite::Sqlite>
+                + $crate::deserialize::StaticallySizedRow<($($arg_type,)*), $crate::sqlite::Sqlite>
+                + std::panic::UnwindSafe
+                + 'static,
+        {
+            conn.register_aggregate_function::<($($arg_type,)*), $return_type, _, _, A>($sql_name)
+        }
+    };
+    // aggregates without arguments can't be implemented on SQLite
+    (@register [aggregate] ($sql_name:expr) () -> $return_type:ty) => {};
+
+    ($($input:tt)*) => {
+        $crate::sql_function!(@attrs [] [] [] $($input)*);
+    };
+}
+
+/// Expands to the `register_impl` functions of `sql_function!` only if the
+/// host functions registering callbacks are imported
+#[cfg(feature = "host-callbacks")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __sqlite_register_impl {
+    ($($register:tt)*) => {
+        $($register)*
+    };
+}
+
+#[cfg(not(feature = "host-callbacks"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __sqlite_register_impl {
+    ($($register:tt)*) => {};
+}
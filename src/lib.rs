@@ -2,12 +2,87 @@
 extern crate diesel;
 extern crate lunatic_sqlite_api;
 
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "native-test-host")))]
+compile_error!(
+    "lunatic-diesel talks to the databases through the lunatic runtime and has to be built for \
+     wasm32, or natively with the `native-test-host` feature to run the SQLite backend in-process"
+);
+
+// `lunatic::test` falls back to `#[test]` outside of lunatic
+#[cfg(all(test, not(target_arch = "wasm32")))]
+extern crate lunatic_test as lunatic;
+
+#[macro_use]
+mod function_macro;
+
+#[cfg(feature = "mysql")]
+pub mod mysql;
+#[cfg(feature = "postgres")]
+pub mod pg;
 pub mod sqlite;
 
 pub use diesel::*;
 
 pub use sqlite::SqliteConnection;
 
+pub mod dsl {
+    //! Re-exports diesel's dsl together with the SQLite specific helper types
+
+    pub use diesel::dsl::*;
+
+    #[cfg(feature = "serde_json")]
+    pub use crate::sqlite::expression::functions::*;
+    pub use crate::sqlite::expression::helper_types::*;
+}
+
+pub mod sql_types {
+    //! Re-exports diesel's SQL types together with the SQLite specific ones
+
+    pub use diesel::sql_types::*;
+
+    #[doc(inline)]
+    pub use crate::sqlite::sql_types::Timestamptz as TimestamptzSqlite;
+    #[cfg(feature = "uuid")]
+    #[doc(inline)]
+    pub use crate::sqlite::sql_types::Uuid as UuidSqlite;
+}
+
+pub mod prelude {
+    //! Re-exports the items of diesel's prelude that don't belong to one of its
+    //! backends, together with the lunatic specific items
+
+    pub use diesel::associations::{Associations, GroupedBy, Identifiable};
+    pub use diesel::connection::Connection;
+    pub use diesel::deserialize::{Queryable, QueryableByName};
+    pub use diesel::expression::{
+        AppearsOnTable, BoxableExpression, Expression, IntoSql, Selectable, SelectableExpression,
+        SelectableHelper,
+    };
+    pub use diesel::expression_methods::{
+        BoolExpressionMethods, EscapeExpressionMethods, ExpressionMethods,
+        NullableExpressionMethods, TextExpressionMethods,
+    };
+    pub use diesel::insertable::Insertable;
+    pub use diesel::query_builder::{AsChangeset, DecoratableTarget};
+    pub use diesel::query_dsl::{
+        BelongingToDsl, CombineDsl, JoinOnDsl, QueryDsl, RunQueryDsl, SaveChangesDsl,
+    };
+    pub use diesel::query_source::{Column, JoinTo, QuerySource, Table};
+    pub use diesel::result::{ConnectionError, ConnectionResult, OptionalExtension, QueryResult};
+    pub use diesel::{
+        allow_columns_to_appear_in_same_group_by_clause, allow_tables_to_appear_in_same_query,
+        joinable, table,
+    };
+
+    #[cfg(feature = "mysql")]
+    pub use crate::mysql::MysqlConnection;
+    #[cfg(feature = "postgres")]
+    pub use crate::pg::PgConnection;
+    pub use crate::sql_function;
+    pub use crate::sqlite::expression::expression_methods::*;
+    pub use crate::sqlite::SqliteConnection;
+}
+
 #[export_name = "lunatic_alloc"]
 extern "C" fn lunatic_alloc(len: u32) -> *mut u8 {
     let buf = Vec::with_capacity(len as usize);
@@ -0,0 +1,132 @@
+//! The MySQL backend
+
+use diesel::backend::*;
+use diesel::query_builder::bind_collector::RawBytesBindCollector;
+use diesel::sql_types::{
+    BigInt, Binary, Bool, Date, Double, Float, Integer, Numeric, SmallInt, Text, Time, Timestamp,
+    TinyInt, TypeMetadata,
+};
+use serde::{Deserialize, Serialize};
+
+use super::query_builder::MysqlQueryBuilder;
+use super::value::MysqlValue;
+
+/// The MySQL backend
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Default)]
+pub struct Mysql;
+
+/// Represents possible types, that can be transmitted via the MySQL binary protocol
+///
+/// The type is sent to the host together with every bind value and the host
+/// reports it for every column of a result set.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum MysqlType {
+    /// A 8 bit signed integer
+    Tiny,
+    /// A 8 bit unsigned integer
+    UnsignedTiny,
+    /// A 16 bit signed integer
+    Short,
+    /// A 16 bit unsigned integer
+    UnsignedShort,
+    /// A 32 bit signed integer
+    Long,
+    /// A 32 bit unsigned integer
+    UnsignedLong,
+    /// A 64 bit signed integer
+    LongLong,
+    /// A 64 bit unsigned integer
+    UnsignedLongLong,
+    /// A 32 bit floating point number
+    Float,
+    /// A 64 bit floating point number
+    Double,
+    /// A fixed point decimal value, sent as string
+    Numeric,
+    /// A time value
+    Time,
+    /// A date value
+    Date,
+    /// A date time value
+    DateTime,
+    /// A timestamp value
+    Timestamp,
+    /// A string value
+    String,
+    /// A byte array
+    Blob,
+    /// A bit field
+    Bit,
+    /// A set of strings
+    Set,
+    /// An enum value
+    Enum,
+}
+
+impl Backend for Mysql {
+    type QueryBuilder = MysqlQueryBuilder;
+}
+
+impl<'a> HasBindCollector<'a> for Mysql {
+    type BindCollector = RawBytesBindCollector<Mysql>;
+}
+
+impl<'a> HasRawValue<'a> for Mysql {
+    type RawValue = MysqlValue<'a>;
+}
+
+impl TypeMetadata for Mysql {
+    type TypeMetadata = MysqlType;
+    type MetadataLookup = ();
+}
+
+impl SqlDialect for Mysql {
+    type ReturningClause = sql_dialect::returning_clause::DoesNotSupportReturningClause;
+
+    type OnConflictClause = sql_dialect::on_conflict_clause::DoesNotSupportOnConflictClause;
+
+    type InsertWithDefaultKeyword = sql_dialect::default_keyword_for_insert::IsoSqlDefaultKeyword;
+    type BatchInsertSupport = sql_dialect::batch_insert_support::PostgresLikeBatchInsertSupport;
+    type ConcatClause = MysqlConcatClause;
+    type DefaultValueClauseForInsert = MysqlStyleDefaultValueClause;
+
+    type EmptyFromClauseSyntax = sql_dialect::from_clause_syntax::AnsiSqlFromClauseSyntax;
+    type SelectStatementSyntax = sql_dialect::select_statement_syntax::AnsiSqlSelectStatement;
+
+    type ExistsSyntax = sql_dialect::exists_syntax::AnsiSqlExistsSyntax;
+    type ArrayComparison = sql_dialect::array_comparison::AnsiSqlArrayComparison;
+}
+
+impl DieselReserveSpecialization for Mysql {}
+impl TrustedBackend for Mysql {}
+
+#[derive(Debug, Copy, Clone)]
+pub struct MysqlStyleDefaultValueClause;
+
+#[derive(Debug, Copy, Clone)]
+pub struct MysqlConcatClause;
+
+macro_rules! wrap_mysql_type {
+    ($struct_name:ident, $ty:ident) => {
+        impl diesel::sql_types::HasSqlType<$struct_name> for crate::mysql::Mysql {
+            fn metadata(_: &mut ()) -> crate::mysql::MysqlType {
+                crate::mysql::MysqlType::$ty
+            }
+        }
+    };
+}
+
+wrap_mysql_type!(Bool, Tiny);
+wrap_mysql_type!(TinyInt, Tiny);
+wrap_mysql_type!(SmallInt, Short);
+wrap_mysql_type!(Integer, Long);
+wrap_mysql_type!(BigInt, LongLong);
+wrap_mysql_type!(Float, Float);
+wrap_mysql_type!(Double, Double);
+wrap_mysql_type!(Numeric, Numeric);
+wrap_mysql_type!(Text, String);
+wrap_mysql_type!(Binary, Blob);
+wrap_mysql_type!(Date, Date);
+wrap_mysql_type!(Time, Time);
+wrap_mysql_type!(Timestamp, DateTime);
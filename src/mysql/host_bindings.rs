@@ -0,0 +1,154 @@
+use diesel::{result::Error, QueryResult};
+use lunatic::LunaticError;
+
+use super::wire_format::{MysqlBindValue, MysqlError, MysqlResult};
+
+/// Host functions of the MySQL driver.
+///
+/// Functions returning a `u32` status use `0` for success, any other value
+/// means that the error can be retrieved through `last_error`. Buffers handed
+/// to the guest are allocated by the host via `lunatic_alloc`.
+pub mod mysql_guest_bindings {
+    #[link(wasm_import_module = "lunatic::mysql")]
+    extern "C" {
+        /// opens a connection to the database at `url`
+        ///
+        /// On success the connection id is written to `id_ptr`, otherwise the id
+        /// of a lunatic error describing the failure.
+        pub fn connect(url_ptr: *const u8, url_len: u32, id_ptr: *mut u64) -> u32;
+
+        pub fn close(connection_id: u64);
+
+        /// executes one or more sql statements without any bind parameters
+        pub fn execute(connection_id: u64, query_ptr: *const u8, query_len: u32) -> u32;
+
+        /// prepares `query` and writes the id of the new statement to `statement_id_ptr`
+        pub fn query_prepare(
+            connection_id: u64,
+            query_ptr: *const u8,
+            query_len: u32,
+            statement_id_ptr: *mut u64,
+        ) -> u32;
+
+        /// runs the statement with the bincode encoded list of bind values and
+        /// writes the number of affected rows to `rows_affected_ptr`
+        pub fn statement_execute(
+            statement_id: u64,
+            binds_ptr: *const u8,
+            binds_len: u32,
+            rows_affected_ptr: *mut u64,
+        ) -> u32;
+
+        /// runs the statement with the bincode encoded list of bind values and
+        /// hands out the complete encoded result set via `result_ptr`/`result_len_ptr`
+        pub fn statement_query(
+            statement_id: u64,
+            binds_ptr: *const u8,
+            binds_len: u32,
+            result_ptr: *mut u32,
+            result_len_ptr: *mut u32,
+        ) -> u32;
+
+        pub fn statement_finalize(statement_id: u64);
+
+        pub fn last_error(connection_id: u64, len_ptr: *mut u32) -> u32;
+    }
+}
+
+pub fn connect(url: &str) -> Result<u64, LunaticError> {
+    let mut id = 0u64;
+    let status = unsafe { mysql_guest_bindings::connect(url.as_ptr(), url.len() as u32, &mut id) };
+    match status {
+        0 => Ok(id),
+        // 2 means that permission to a resource was denied
+        2 => Err(LunaticError::PermissionDenied),
+        _ => Err(LunaticError::Error(id)),
+    }
+}
+
+pub fn close(connection_id: u64) {
+    unsafe { mysql_guest_bindings::close(connection_id) }
+}
+
+pub fn execute(connection_id: u64, query: &str) -> u32 {
+    unsafe { mysql_guest_bindings::execute(connection_id, query.as_ptr(), query.len() as u32) }
+}
+
+pub fn query_prepare(connection_id: u64, query: &str) -> Result<u64, u32> {
+    let mut statement_id = 0u64;
+    let status = unsafe {
+        mysql_guest_bindings::query_prepare(
+            connection_id,
+            query.as_ptr(),
+            query.len() as u32,
+            &mut statement_id,
+        )
+    };
+    match status {
+        0 => Ok(statement_id),
+        status => Err(status),
+    }
+}
+
+pub fn statement_execute(statement_id: u64, binds: &[MysqlBindValue]) -> Result<u64, u32> {
+    let encoded = bincode::serialize(binds).unwrap();
+    let mut rows_affected = 0u64;
+    let status = unsafe {
+        mysql_guest_bindings::statement_execute(
+            statement_id,
+            encoded.as_ptr(),
+            encoded.len() as u32,
+            &mut rows_affected,
+        )
+    };
+    match status {
+        0 => Ok(rows_affected),
+        status => Err(status),
+    }
+}
+
+/// returns `Ok(Err(status))` if the host reported an error running the statement
+pub fn statement_query(
+    statement_id: u64,
+    binds: &[MysqlBindValue],
+) -> QueryResult<Result<MysqlResult, u32>> {
+    let encoded = bincode::serialize(binds).unwrap();
+    let mut result_ptr = 0u32;
+    let mut result_len = 0u32;
+    let status = unsafe {
+        mysql_guest_bindings::statement_query(
+            statement_id,
+            encoded.as_ptr(),
+            encoded.len() as u32,
+            &mut result_ptr,
+            &mut result_len,
+        )
+    };
+    if status != 0 {
+        return Ok(Err(status));
+    }
+    let encoded_result = unroll_vec(result_ptr, result_len);
+    bincode::deserialize(encoded_result.as_slice())
+        .map(Ok)
+        .map_err(|_| Error::DeserializationError("Failed to deserialize mysql result".into()))
+}
+
+pub fn statement_finalize(statement_id: u64) {
+    unsafe { mysql_guest_bindings::statement_finalize(statement_id) }
+}
+
+pub fn last_error(connection_id: u64) -> QueryResult<MysqlError> {
+    let mut len_ptr = 0u32;
+    unsafe {
+        let ptr = mysql_guest_bindings::last_error(connection_id, &mut len_ptr);
+        let encoded_error = unroll_vec(ptr, len_ptr);
+        bincode::deserialize(encoded_error.as_slice())
+            .map_err(|_| Error::DeserializationError("Failed to deserialize mysql error".into()))
+    }
+}
+
+// helper function to unwrap byte slice that was allocated during host call
+fn unroll_vec(ptr: u32, len: u32) -> Vec<u8> {
+    let len = len as usize;
+    unsafe { Vec::from_raw_parts(ptr as *mut u8, len, len) }
+}
@@ -0,0 +1,24 @@
+//! Provides types and functions related to working with MySQL
+//!
+//! Much of this module is re-exported from database agnostic locations.
+//! However, if you are writing code specifically to extend Diesel on
+//! MySQL, you may need to work with this module directly.
+
+mod diesel_backend;
+mod diesel_connection;
+#[cfg(target_arch = "wasm32")]
+mod host_bindings;
+#[cfg(not(target_arch = "wasm32"))]
+mod native_host;
+#[cfg(not(target_arch = "wasm32"))]
+use native_host as host_bindings;
+mod query_builder;
+mod stmt;
+mod types;
+mod value;
+mod wire_format;
+
+pub use diesel_backend::{Mysql, MysqlType};
+pub use diesel_connection::*;
+pub use query_builder::MysqlQueryBuilder;
+pub use value::MysqlValue;
@@ -0,0 +1,132 @@
+use crate::mysql::Mysql;
+use crate::result::QueryResult;
+use diesel::query_builder::{
+    AstPass, BoxedLimitOffsetClause, IntoBoxedClause, LimitClause, LimitOffsetClause,
+    NoLimitClause, NoOffsetClause, OffsetClause, QueryFragment,
+};
+
+// MySQL has no way to express an offset without a limit, the documentation proposes:
+// > To retrieve all rows from a certain offset up to the end of the
+// > result set, you can use some large number for the second parameter.
+// https://dev.mysql.com/doc/refman/8.0/en/select.html
+const NO_LIMIT: &str = "18446744073709551615";
+
+impl QueryFragment<Mysql> for LimitOffsetClause<NoLimitClause, NoOffsetClause> {
+    fn walk_ast<'b>(&'b self, _out: AstPass<'_, 'b, Mysql>) -> QueryResult<()> {
+        Ok(())
+    }
+}
+
+impl<L> QueryFragment<Mysql> for LimitOffsetClause<LimitClause<L>, NoOffsetClause>
+where
+    LimitClause<L>: QueryFragment<Mysql>,
+{
+    fn walk_ast<'b>(&'b self, out: AstPass<'_, 'b, Mysql>) -> QueryResult<()> {
+        self.limit_clause.walk_ast(out)?;
+        Ok(())
+    }
+}
+
+impl<O> QueryFragment<Mysql> for LimitOffsetClause<NoLimitClause, OffsetClause<O>>
+where
+    O: QueryFragment<Mysql>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Mysql>) -> QueryResult<()> {
+        out.push_sql(" LIMIT ");
+        self.offset_clause.0.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        out.push_sql(NO_LIMIT);
+        Ok(())
+    }
+}
+
+impl<L, O> QueryFragment<Mysql> for LimitOffsetClause<LimitClause<L>, OffsetClause<O>>
+where
+    L: QueryFragment<Mysql>,
+    O: QueryFragment<Mysql>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Mysql>) -> QueryResult<()> {
+        out.push_sql(" LIMIT ");
+        self.offset_clause.0.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        self.limit_clause.0.walk_ast(out.reborrow())?;
+        Ok(())
+    }
+}
+
+// Boxed queries only store the already wrapped `LimitClause` and `OffsetClause`,
+// so they use the equivalent `LIMIT count OFFSET offset` syntax instead
+impl<'a> QueryFragment<Mysql> for BoxedLimitOffsetClause<'a, Mysql> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Mysql>) -> QueryResult<()> {
+        match (self.limit.as_ref(), self.offset.as_ref()) {
+            (Some(limit), Some(offset)) => {
+                limit.walk_ast(out.reborrow())?;
+                offset.walk_ast(out.reborrow())?;
+            }
+            (Some(limit), None) => {
+                limit.walk_ast(out.reborrow())?;
+            }
+            (None, Some(offset)) => {
+                out.push_sql(" LIMIT ");
+                out.push_sql(NO_LIMIT);
+                offset.walk_ast(out.reborrow())?;
+            }
+            (None, None) => {}
+        }
+        Ok(())
+    }
+}
+
+impl<'a> IntoBoxedClause<'a, Mysql> for LimitOffsetClause<NoLimitClause, NoOffsetClause> {
+    type BoxedClause = BoxedLimitOffsetClause<'a, Mysql>;
+
+    fn into_boxed(self) -> Self::BoxedClause {
+        BoxedLimitOffsetClause {
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
+impl<'a, L> IntoBoxedClause<'a, Mysql> for LimitOffsetClause<LimitClause<L>, NoOffsetClause>
+where
+    L: QueryFragment<Mysql> + Send + 'a,
+{
+    type BoxedClause = BoxedLimitOffsetClause<'a, Mysql>;
+
+    fn into_boxed(self) -> Self::BoxedClause {
+        BoxedLimitOffsetClause {
+            limit: Some(Box::new(self.limit_clause)),
+            offset: None,
+        }
+    }
+}
+
+impl<'a, O> IntoBoxedClause<'a, Mysql> for LimitOffsetClause<NoLimitClause, OffsetClause<O>>
+where
+    O: QueryFragment<Mysql> + Send + 'a,
+{
+    type BoxedClause = BoxedLimitOffsetClause<'a, Mysql>;
+
+    fn into_boxed(self) -> Self::BoxedClause {
+        BoxedLimitOffsetClause {
+            limit: None,
+            offset: Some(Box::new(self.offset_clause)),
+        }
+    }
+}
+
+impl<'a, L, O> IntoBoxedClause<'a, Mysql> for LimitOffsetClause<LimitClause<L>, OffsetClause<O>>
+where
+    L: QueryFragment<Mysql> + Send + 'a,
+    O: QueryFragment<Mysql> + Send + 'a,
+{
+    type BoxedClause = BoxedLimitOffsetClause<'a, Mysql>;
+
+    fn into_boxed(self) -> Self::BoxedClause {
+        BoxedLimitOffsetClause {
+            limit: Some(Box::new(self.limit_clause)),
+            offset: Some(Box::new(self.offset_clause)),
+        }
+    }
+}
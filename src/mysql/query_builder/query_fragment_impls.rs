@@ -0,0 +1,28 @@
+use diesel::expression::Concat;
+use diesel::query_builder::{AstPass, DefaultValues, QueryFragment};
+
+use crate::mysql::diesel_backend::{MysqlConcatClause, MysqlStyleDefaultValueClause};
+use crate::mysql::Mysql;
+use crate::result::QueryResult;
+
+impl QueryFragment<Mysql, MysqlStyleDefaultValueClause> for DefaultValues {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Mysql>) -> QueryResult<()> {
+        out.push_sql("() VALUES ()");
+        Ok(())
+    }
+}
+
+impl<L, R> QueryFragment<Mysql, MysqlConcatClause> for Concat<L, R>
+where
+    L: QueryFragment<Mysql>,
+    R: QueryFragment<Mysql>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Mysql>) -> QueryResult<()> {
+        out.push_sql("CONCAT(");
+        self.left.walk_ast(out.reborrow())?;
+        out.push_sql(",");
+        self.right.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::Write;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types;
+
+use super::value::NumericRepresentation;
+use super::{Mysql, MysqlValue};
+
+fn write_bytes(out: &mut Output<'_, '_, Mysql>, bytes: &[u8]) -> serialize::Result {
+    out.write_all(bytes)
+        .map(|_| IsNull::No)
+        .map_err(|e| Box::new(e) as Box<_>)
+}
+
+fn decimal_to_str(bytes: &[u8]) -> deserialize::Result<&str> {
+    std::str::from_utf8(bytes).map_err(Into::into)
+}
+
+macro_rules! integer_from_sql {
+    ($ty:ty, $sql_type:ident) => {
+        impl FromSql<sql_types::$sql_type, Mysql> for $ty {
+            fn from_sql(value: MysqlValue<'_>) -> deserialize::Result<Self> {
+                let value = match value.numeric_value()? {
+                    NumericRepresentation::Tiny(x) => <$ty>::try_from(x)?,
+                    NumericRepresentation::Small(x) => <$ty>::try_from(x)?,
+                    NumericRepresentation::Medium(x) => <$ty>::try_from(x)?,
+                    NumericRepresentation::Big(x) => <$ty>::try_from(x)?,
+                    NumericRepresentation::Decimal(bytes) => decimal_to_str(bytes)?.parse()?,
+                    NumericRepresentation::Float(_) | NumericRepresentation::Double(_) => {
+                        return Err(concat!(
+                            "Received a floating point number for a column of type ",
+                            stringify!($sql_type)
+                        )
+                        .into())
+                    }
+                };
+                Ok(value)
+            }
+        }
+
+        impl ToSql<sql_types::$sql_type, Mysql> for $ty {
+            fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Mysql>) -> serialize::Result {
+                write_bytes(out, &self.to_le_bytes())
+            }
+        }
+    };
+}
+
+integer_from_sql!(i8, TinyInt);
+integer_from_sql!(i16, SmallInt);
+integer_from_sql!(i32, Integer);
+integer_from_sql!(i64, BigInt);
+
+impl FromSql<sql_types::Float, Mysql> for f32 {
+    fn from_sql(value: MysqlValue<'_>) -> deserialize::Result<Self> {
+        match value.numeric_value()? {
+            NumericRepresentation::Float(x) => Ok(x),
+            NumericRepresentation::Double(x) => Ok(x as f32),
+            NumericRepresentation::Tiny(x) => Ok(x.into()),
+            NumericRepresentation::Small(x) => Ok(x.into()),
+            NumericRepresentation::Medium(x) => Ok(x as f32),
+            NumericRepresentation::Big(x) => Ok(x as f32),
+            NumericRepresentation::Decimal(bytes) => Ok(decimal_to_str(bytes)?.parse()?),
+        }
+    }
+}
+
+impl ToSql<sql_types::Float, Mysql> for f32 {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Mysql>) -> serialize::Result {
+        write_bytes(out, &self.to_le_bytes())
+    }
+}
+
+impl FromSql<sql_types::Double, Mysql> for f64 {
+    fn from_sql(value: MysqlValue<'_>) -> deserialize::Result<Self> {
+        match value.numeric_value()? {
+            NumericRepresentation::Float(x) => Ok(x.into()),
+            NumericRepresentation::Double(x) => Ok(x),
+            NumericRepresentation::Tiny(x) => Ok(x.into()),
+            NumericRepresentation::Small(x) => Ok(x.into()),
+            NumericRepresentation::Medium(x) => Ok(x.into()),
+            NumericRepresentation::Big(x) => Ok(x as f64),
+            NumericRepresentation::Decimal(bytes) => Ok(decimal_to_str(bytes)?.parse()?),
+        }
+    }
+}
+
+impl ToSql<sql_types::Double, Mysql> for f64 {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Mysql>) -> serialize::Result {
+        write_bytes(out, &self.to_le_bytes())
+    }
+}
+
+impl FromSql<sql_types::Bool, Mysql> for bool {
+    fn from_sql(value: MysqlValue<'_>) -> deserialize::Result<Self> {
+        Ok(value.as_bytes().iter().any(|x| *x != 0))
+    }
+}
+
+impl ToSql<sql_types::Bool, Mysql> for bool {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Mysql>) -> serialize::Result {
+        write_bytes(out, &[*self as u8])
+    }
+}
+
+/// The returned pointer is *only* valid for the lifetime to the argument of
+/// `from_sql`. This impl is intended for uses where you want to write a new
+/// impl in terms of `String`, but don't want to allocate. We have to return a
+/// raw pointer instead of a reference with a lifetime due to the structure of
+/// `FromSql`
+impl FromSql<sql_types::Text, Mysql> for *const str {
+    fn from_sql(value: MysqlValue<'_>) -> deserialize::Result<Self> {
+        let string = std::str::from_utf8(value.as_bytes())?;
+        Ok(string as *const _)
+    }
+}
+
+/// The returned pointer is *only* valid for the lifetime to the argument of
+/// `from_sql`. This impl is intended for uses where you want to write a new
+/// impl in terms of `Vec<u8>`, but don't want to allocate. We have to return a
+/// raw pointer instead of a reference with a lifetime due to the structure of
+/// `FromSql`
+impl FromSql<sql_types::Binary, Mysql> for *const [u8] {
+    fn from_sql(value: MysqlValue<'_>) -> deserialize::Result<Self> {
+        Ok(value.as_bytes() as *const _)
+    }
+}
@@ -0,0 +1,86 @@
+use diesel::deserialize;
+
+use super::MysqlType;
+
+/// Raw MySQL value as received from the host
+///
+/// Values use the encoding of the MySQL binary protocol, which means that
+/// numbers are stored in little endian and decimals as strings.
+#[derive(Clone, Copy, Debug)]
+pub struct MysqlValue<'a> {
+    raw: &'a [u8],
+    tpe: MysqlType,
+}
+
+impl<'a> MysqlValue<'a> {
+    pub(in crate::mysql) fn new(raw: &'a [u8], tpe: MysqlType) -> Self {
+        Self { raw, tpe }
+    }
+
+    /// Get the underlying raw byte representation
+    pub fn as_bytes(&self) -> &[u8] {
+        self.raw
+    }
+
+    /// Get the mysql type of the current value
+    pub fn value_type(&self) -> MysqlType {
+        self.tpe
+    }
+
+    fn read_array<const N: usize>(&self) -> deserialize::Result<[u8; N]> {
+        self.raw.try_into().map_err(|_| {
+            format!(
+                "Received {} bytes for a value of type {:?}, expected {N}",
+                self.raw.len(),
+                self.tpe
+            )
+            .into()
+        })
+    }
+
+    /// Checks that the type code is valid, and interprets the data as a
+    /// `NumericRepresentation`
+    ///
+    /// MySQL chooses the width of a number by the column type, so a value
+    /// can't be decoded without looking at its type (`COUNT(*)` is always
+    /// a `BIGINT` for example).
+    pub(in crate::mysql) fn numeric_value(&self) -> deserialize::Result<NumericRepresentation<'_>> {
+        use self::NumericRepresentation::*;
+
+        Ok(match self.tpe {
+            MysqlType::UnsignedTiny | MysqlType::Tiny => Tiny(self.read_array::<1>()?[0] as i8),
+            MysqlType::UnsignedShort | MysqlType::Short => {
+                Small(i16::from_le_bytes(self.read_array()?))
+            }
+            MysqlType::UnsignedLong | MysqlType::Long => {
+                Medium(i32::from_le_bytes(self.read_array()?))
+            }
+            MysqlType::UnsignedLongLong | MysqlType::LongLong => {
+                Big(i64::from_le_bytes(self.read_array()?))
+            }
+            MysqlType::Float => Float(f32::from_le_bytes(self.read_array()?)),
+            MysqlType::Double => Double(f64::from_le_bytes(self.read_array()?)),
+            MysqlType::Numeric => Decimal(self.raw),
+            tpe => return Err(format!("Unsupported type {tpe:?} for a numeric value").into()),
+        })
+    }
+}
+
+/// Represents all possible forms MySQL transmits integers
+#[derive(Debug, Clone, Copy)]
+pub(in crate::mysql) enum NumericRepresentation<'a> {
+    /// Corresponds to `MYSQL_TYPE_TINY`
+    Tiny(i8),
+    /// Corresponds to `MYSQL_TYPE_SHORT`
+    Small(i16),
+    /// Corresponds to `MYSQL_TYPE_LONG`
+    Medium(i32),
+    /// Corresponds to `MYSQL_TYPE_LONGLONG`
+    Big(i64),
+    /// Corresponds to `MYSQL_TYPE_FLOAT`
+    Float(f32),
+    /// Corresponds to `MYSQL_TYPE_DOUBLE`
+    Double(f64),
+    /// Corresponds to `MYSQL_TYPE_DECIMAL` and `MYSQL_TYPE_NEWDECIMAL`
+    Decimal(&'a [u8]),
+}
@@ -0,0 +1,36 @@
+//! Types exchanged with the host, encoded with bincode
+//!
+//! All values use the encoding of the MySQL binary protocol.
+
+use serde::{Deserialize, Serialize};
+
+use crate::mysql::MysqlType;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MysqlBindValue {
+    pub tpe: MysqlType,
+    pub value: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MysqlColumn {
+    pub name: String,
+    pub tpe: MysqlType,
+}
+
+/// The complete result set of a query
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MysqlResult {
+    pub columns: Vec<MysqlColumn>,
+    pub rows: Vec<Vec<Option<Vec<u8>>>>,
+}
+
+/// Error reported by the MySQL server (or the host driver)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MysqlError {
+    /// The MySQL error code, `None` for errors raised by the driver itself
+    pub code: Option<u16>,
+    /// The SQLSTATE code of the error
+    pub sql_state: Option<String>,
+    pub message: String,
+}
@@ -0,0 +1,27 @@
+//! The binary representations of PostgreSQL's date and time types
+//!
+//! These are the same dumb wrappers as the ones of diesel's PostgreSQL
+//! backend, which only indicate the meaning of the integer the value is sent
+//! as.
+
+use diesel::deserialize::FromSqlRow;
+use diesel::expression::AsExpression;
+use diesel::sql_types::{Date, Time, Timestamp};
+
+/// Timestamps are represented in Postgres as a 64 bit signed integer
+/// representing the number of microseconds since January 1st 2000
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Timestamp)]
+pub struct PgTimestamp(pub i64);
+
+/// Dates are represented in Postgres as a 32 bit signed integer representing
+/// the number of julian days since January 1st 2000
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Date)]
+pub struct PgDate(pub i32);
+
+/// Time is represented in Postgres as a 64 bit signed integer representing
+/// the number of microseconds since midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Time)]
+pub struct PgTime(pub i64);
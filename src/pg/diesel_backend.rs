@@ -0,0 +1,115 @@
+//! The PostgreSQL backend
+
+use diesel::backend::*;
+use diesel::query_builder::bind_collector::RawBytesBindCollector;
+use diesel::sql_types::{
+    BigInt, Binary, Bool, Date, Double, Float, Integer, Numeric, SmallInt, Text, Time, Timestamp,
+    TypeMetadata,
+};
+
+use super::query_builder::PgQueryBuilder;
+use super::value::PgValue;
+
+/// The PostgreSQL backend
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Default)]
+pub struct Pg;
+
+/// The [OIDs] for a SQL type
+///
+/// Unlike diesel's own PostgreSQL backend this only knows about the builtin
+/// types, as the host does not support looking up the OIDs of custom types yet.
+///
+/// [OIDs]: https://www.postgresql.org/docs/current/static/datatype-oid.html
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct PgTypeMetadata {
+    oid: u32,
+    array_oid: u32,
+}
+
+impl PgTypeMetadata {
+    /// Create a new instance of this type based on known constant [OIDs].
+    ///
+    /// [OIDs]: https://www.postgresql.org/docs/current/static/datatype-oid.html
+    pub fn new(oid: u32, array_oid: u32) -> Self {
+        Self { oid, array_oid }
+    }
+
+    /// The [OID] of `T`
+    ///
+    /// [OID]: https://www.postgresql.org/docs/current/static/datatype-oid.html
+    pub fn oid(&self) -> u32 {
+        self.oid
+    }
+
+    /// The [OID] of `T[]`
+    ///
+    /// [OID]: https://www.postgresql.org/docs/current/static/datatype-oid.html
+    pub fn array_oid(&self) -> u32 {
+        self.array_oid
+    }
+}
+
+impl Backend for Pg {
+    type QueryBuilder = PgQueryBuilder;
+}
+
+impl<'a> HasBindCollector<'a> for Pg {
+    type BindCollector = RawBytesBindCollector<Pg>;
+}
+
+impl<'a> HasRawValue<'a> for Pg {
+    type RawValue = PgValue<'a>;
+}
+
+impl TypeMetadata for Pg {
+    type TypeMetadata = PgTypeMetadata;
+    type MetadataLookup = ();
+}
+
+impl SqlDialect for Pg {
+    type ReturningClause = sql_dialect::returning_clause::PgLikeReturningClause;
+
+    type OnConflictClause = PgOnConflictClause;
+
+    type InsertWithDefaultKeyword = sql_dialect::default_keyword_for_insert::IsoSqlDefaultKeyword;
+    type BatchInsertSupport = sql_dialect::batch_insert_support::PostgresLikeBatchInsertSupport;
+    type ConcatClause = sql_dialect::concat_clause::ConcatWithPipesClause;
+    type DefaultValueClauseForInsert = sql_dialect::default_value_clause::AnsiDefaultValueClause;
+
+    type EmptyFromClauseSyntax = sql_dialect::from_clause_syntax::AnsiSqlFromClauseSyntax;
+    type SelectStatementSyntax = sql_dialect::select_statement_syntax::AnsiSqlSelectStatement;
+
+    type ExistsSyntax = sql_dialect::exists_syntax::AnsiSqlExistsSyntax;
+    type ArrayComparison = sql_dialect::array_comparison::AnsiSqlArrayComparison;
+}
+
+impl DieselReserveSpecialization for Pg {}
+impl TrustedBackend for Pg {}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PgOnConflictClause;
+
+impl sql_dialect::on_conflict_clause::SupportsOnConflictClause for PgOnConflictClause {}
+
+macro_rules! wrap_pg_type {
+    ($struct_name:ident, $oid:expr, $array_oid:expr) => {
+        impl diesel::sql_types::HasSqlType<$struct_name> for crate::pg::Pg {
+            fn metadata(_: &mut ()) -> crate::pg::PgTypeMetadata {
+                crate::pg::PgTypeMetadata::new($oid, $array_oid)
+            }
+        }
+    };
+}
+
+wrap_pg_type!(Bool, 16, 1000);
+wrap_pg_type!(SmallInt, 21, 1005);
+wrap_pg_type!(Integer, 23, 1007);
+wrap_pg_type!(BigInt, 20, 1016);
+wrap_pg_type!(Float, 700, 1021);
+wrap_pg_type!(Double, 701, 1022);
+wrap_pg_type!(Numeric, 1700, 1231);
+wrap_pg_type!(Text, 25, 1009);
+wrap_pg_type!(Binary, 17, 1001);
+wrap_pg_type!(Date, 1082, 1182);
+wrap_pg_type!(Time, 1083, 1183);
+wrap_pg_type!(Timestamp, 1114, 1115);
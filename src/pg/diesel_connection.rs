@@ -0,0 +1,694 @@
+use std::rc::Rc;
+
+use diesel::{
+    connection::{
+        statement_cache::StatementCache, AnsiTransactionManager, ConnectionGatWorkaround,
+        DefaultLoadingMode, LoadConnection, LoadRowIter, SimpleConnection,
+    },
+    expression::QueryMetadata,
+    query_builder::{bind_collector::RawBytesBindCollector, Query, QueryFragment, QueryId},
+    result::{DatabaseErrorInformation, DatabaseErrorKind, Error},
+    row::{Field, PartialRow, Row, RowGatWorkaround, RowIndex},
+    Connection, ConnectionError, ConnectionResult, QueryResult,
+};
+
+use super::{
+    diesel_backend::Pg,
+    host_bindings,
+    stmt::Statement,
+    value::PgValue,
+    wire_format::{PgBindValue, PgError, PgResult},
+};
+
+pub(crate) struct RawConnection {
+    pub(crate) connection_id: u64,
+}
+
+impl RawConnection {
+    fn establish(database_url: &str) -> ConnectionResult<RawConnection> {
+        match host_bindings::connect(database_url) {
+            Ok(connection_id) => Ok(RawConnection { connection_id }),
+            Err(e) => Err(ConnectionError::BadConnection(e.to_string())),
+        }
+    }
+
+    fn exec(&self, query: &str) -> QueryResult<()> {
+        match host_bindings::execute(self.connection_id, query) {
+            0 => Ok(()),
+            _ => Err(last_error(self.connection_id)),
+        }
+    }
+}
+
+impl Drop for RawConnection {
+    fn drop(&mut self) {
+        host_bindings::close(self.connection_id);
+    }
+}
+
+/// Detailed information about an error returned by the PostgreSQL server
+#[derive(Debug)]
+pub struct PgErrorInformation(PgError);
+
+impl DatabaseErrorInformation for PgErrorInformation {
+    fn message(&self) -> &str {
+        &self.0.message
+    }
+
+    fn details(&self) -> Option<&str> {
+        self.0.detail.as_deref()
+    }
+
+    fn hint(&self) -> Option<&str> {
+        self.0.hint.as_deref()
+    }
+
+    fn table_name(&self) -> Option<&str> {
+        self.0.table.as_deref()
+    }
+
+    fn column_name(&self) -> Option<&str> {
+        self.0.column.as_deref()
+    }
+
+    fn constraint_name(&self) -> Option<&str> {
+        self.0.constraint.as_deref()
+    }
+
+    fn statement_position(&self) -> Option<i32> {
+        self.0.position
+    }
+}
+
+// see https://www.postgresql.org/docs/current/errcodes-appendix.html
+fn error_kind(code: Option<&str>) -> DatabaseErrorKind {
+    match code {
+        Some("23505") => DatabaseErrorKind::UniqueViolation,
+        Some("23503") => DatabaseErrorKind::ForeignKeyViolation,
+        Some("23502") => DatabaseErrorKind::NotNullViolation,
+        Some("23514") => DatabaseErrorKind::CheckViolation,
+        Some("40001") => DatabaseErrorKind::SerializationFailure,
+        Some("25006") => DatabaseErrorKind::ReadOnlyTransaction,
+        Some(code) if code.starts_with("08") => DatabaseErrorKind::ClosedConnection,
+        _ => DatabaseErrorKind::Unknown,
+    }
+}
+
+impl From<PgError> for Error {
+    fn from(error: PgError) -> Self {
+        Error::DatabaseError(
+            error_kind(error.code.as_deref()),
+            Box::new(PgErrorInformation(error)),
+        )
+    }
+}
+
+pub(super) fn last_error(connection_id: u64) -> Error {
+    match host_bindings::last_error(connection_id) {
+        Ok(error) => error.into(),
+        Err(e) => e,
+    }
+}
+
+/// Connections for the PostgreSQL backend
+///
+/// The connection is handled by the host, the `database_url` is passed on as is.
+/// It should be a [PostgreSQL connection string](https://www.postgresql.org/docs/current/libpq-connect.html#LIBPQ-CONNSTRING).
+///
+/// # Supported loading model implementations
+///
+/// * [`DefaultLoadingMode`]
+///
+/// The host always sends the complete result set of a query, so rows are loaded
+/// into guest memory all at once before the iterator yields the first one.
+#[allow(missing_debug_implementations)]
+pub struct PgConnection {
+    // statement_cache needs to be before raw_connection
+    // otherwise the statements would outlive the connection
+    statement_cache: StatementCache<Pg, Statement>,
+    raw_connection: RawConnection,
+    transaction_state: AnsiTransactionManager,
+}
+
+impl SimpleConnection for PgConnection {
+    fn batch_execute(&mut self, query: &str) -> QueryResult<()> {
+        self.raw_connection.exec(query)
+    }
+}
+
+impl<'conn, 'query> ConnectionGatWorkaround<'conn, 'query, Pg> for PgConnection {
+    type Cursor = Cursor;
+    type Row = PgRow;
+}
+
+impl Connection for PgConnection {
+    type Backend = Pg;
+    type TransactionManager = AnsiTransactionManager;
+
+    fn establish(database_url: &str) -> ConnectionResult<Self> {
+        let raw_connection = RawConnection::establish(database_url)?;
+        Ok(Self {
+            statement_cache: StatementCache::new(),
+            raw_connection,
+            transaction_state: AnsiTransactionManager::default(),
+        })
+    }
+
+    fn execute_returning_count<T>(&mut self, source: &T) -> QueryResult<usize>
+    where
+        T: QueryFragment<Self::Backend> + QueryId,
+    {
+        self.with_prepared_query(source, |statement, binds| statement.execute(&binds))
+    }
+
+    fn transaction_state(&mut self) -> &mut AnsiTransactionManager
+    where
+        Self: Sized,
+    {
+        &mut self.transaction_state
+    }
+}
+
+impl LoadConnection<DefaultLoadingMode> for PgConnection {
+    fn load<'conn, 'query, T>(
+        &'conn mut self,
+        source: T,
+    ) -> QueryResult<LoadRowIter<'conn, 'query, Self, Self::Backend>>
+    where
+        T: Query + QueryFragment<Self::Backend> + QueryId + 'query,
+        Self::Backend: QueryMetadata<T::SqlType>,
+    {
+        let result =
+            self.with_prepared_query(&source, |statement, binds| statement.query(&binds))?;
+
+        Ok(Cursor::new(result))
+    }
+}
+
+impl PgConnection {
+    fn with_prepared_query<T, R>(
+        &mut self,
+        source: &T,
+        f: impl FnOnce(&Statement, Vec<PgBindValue>) -> QueryResult<R>,
+    ) -> QueryResult<R>
+    where
+        T: QueryFragment<Pg> + QueryId,
+    {
+        let mut bind_collector = RawBytesBindCollector::<Pg>::new();
+        source.collect_binds(&mut bind_collector, &mut (), &Pg)?;
+        let RawBytesBindCollector {
+            metadata, binds, ..
+        } = bind_collector;
+
+        let param_types = metadata.iter().map(|m| m.oid()).collect::<Vec<_>>();
+        let binds = param_types
+            .iter()
+            .zip(binds)
+            .map(|(&type_oid, value)| PgBindValue { type_oid, value })
+            .collect();
+
+        let raw_connection = &self.raw_connection;
+        let statement =
+            self.statement_cache
+                .cached_statement(source, &Pg, &metadata, |sql, _is_cached| {
+                    Statement::prepare(raw_connection, sql, &param_types)
+                })?;
+
+        f(&statement, binds)
+    }
+}
+
+/// Iterator over the rows of a query result
+#[allow(missing_debug_implementations)]
+pub struct Cursor {
+    result: Rc<PgResult>,
+    next_row: usize,
+}
+
+impl Cursor {
+    fn new(result: PgResult) -> Self {
+        Self {
+            result: Rc::new(result),
+            next_row: 0,
+        }
+    }
+}
+
+impl Iterator for Cursor {
+    type Item = QueryResult<PgRow>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_row >= self.result.rows.len() {
+            return None;
+        }
+        let row = PgRow {
+            result: Rc::clone(&self.result),
+            row_idx: self.next_row,
+        };
+        self.next_row += 1;
+        Some(Ok(row))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.result.rows.len() - self.next_row;
+        (len, Some(len))
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct PgRow {
+    result: Rc<PgResult>,
+    row_idx: usize,
+}
+
+impl<'field> RowGatWorkaround<'field, Pg> for PgRow {
+    type Field = PgField<'field>;
+}
+
+impl<'a> Row<'a, Pg> for PgRow {
+    type InnerPartialRow = Self;
+
+    fn field_count(&self) -> usize {
+        self.result.columns.len()
+    }
+
+    fn get<'field, I>(&'field self, idx: I) -> Option<<Self as RowGatWorkaround<'field, Pg>>::Field>
+    where
+        'a: 'field,
+        Self: RowIndex<I>,
+    {
+        let idx = self.idx(idx)?;
+        let column = &self.result.columns[idx];
+        Some(PgField {
+            name: &column.name,
+            type_oid: column.type_oid,
+            value: self.result.rows[self.row_idx][idx].as_deref(),
+        })
+    }
+
+    fn partial_row(&self, range: std::ops::Range<usize>) -> PartialRow<'_, Self::InnerPartialRow> {
+        PartialRow::new(self, range)
+    }
+}
+
+impl RowIndex<usize> for PgRow {
+    fn idx(&self, idx: usize) -> Option<usize> {
+        if idx < self.field_count() {
+            Some(idx)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'idx> RowIndex<&'idx str> for PgRow {
+    fn idx(&self, field_name: &'idx str) -> Option<usize> {
+        self.result
+            .columns
+            .iter()
+            .position(|column| column.name == field_name)
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct PgField<'a> {
+    name: &'a str,
+    type_oid: u32,
+    value: Option<&'a [u8]>,
+}
+
+impl<'a> Field<'a, Pg> for PgField<'a> {
+    fn field_name(&self) -> Option<&str> {
+        Some(self.name)
+    }
+
+    fn is_null(&self) -> bool {
+        self.value.is_none()
+    }
+
+    fn value(&self) -> Option<diesel::backend::RawValue<'_, Pg>> {
+        self.value.map(|raw| PgValue::new(raw, self.type_oid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pg::PgQueryBuilder;
+    use crate::prelude::*;
+    use diesel::query_builder::QueryBuilder;
+    use diesel::sql_types::{BigInt, Bool, Double, Integer, Text};
+    use lunatic::test;
+
+    table! {
+        users {
+            id -> Integer,
+            name -> Text,
+        }
+    }
+
+    #[test]
+    fn query_builder_uses_numbered_binds_and_quoted_identifiers() {
+        let query = users::table
+            .filter(users::id.eq(1))
+            .filter(users::name.ne("Sean"))
+            .limit(5)
+            .offset(2);
+        assert_eq!(
+            "SELECT \"users\".\"id\", \"users\".\"name\" FROM \"users\" \
+             WHERE ((\"users\".\"id\" = $1) AND (\"users\".\"name\" != $2)) \
+             LIMIT $3 OFFSET $4 -- binds: [1, \"Sean\", 5, 2]",
+            diesel::debug_query::<Pg, _>(&query).to_string()
+        );
+    }
+
+    #[test]
+    fn query_builder_escapes_identifiers() {
+        let mut query_builder = PgQueryBuilder::new();
+        query_builder.push_identifier("my \"table\"").unwrap();
+        assert_eq!("\"my \"\"table\"\"\"", query_builder.finish());
+    }
+
+    #[test]
+    fn insert_uses_default_keyword_and_returning() {
+        let new_users = vec![users::name.eq("Sean"), users::name.eq("Tess")];
+        let query = diesel::insert_into(users::table)
+            .values(&new_users)
+            .returning(users::id);
+        assert_eq!(
+            "INSERT INTO \"users\" (\"name\") VALUES ($1), ($2) RETURNING \"users\".\"id\" \
+             -- binds: [\"Sean\", \"Tess\"]",
+            diesel::debug_query::<Pg, _>(&query).to_string()
+        );
+    }
+
+    // Encodes `value` the same way as it is sent to the host
+    fn to_wire<ST, T>(value: &T) -> PgBindValue
+    where
+        Pg: diesel::sql_types::HasSqlType<ST>,
+        T: diesel::serialize::ToSql<ST, Pg>,
+    {
+        use diesel::query_builder::BindCollector;
+
+        let mut collector = RawBytesBindCollector::<Pg>::new();
+        collector.push_bound_value::<ST, T>(value, &mut ()).unwrap();
+        PgBindValue {
+            type_oid: collector.metadata[0].oid(),
+            value: collector.binds.pop().unwrap(),
+        }
+    }
+
+    fn from_wire<ST, T>(bind: &PgBindValue) -> T
+    where
+        T: diesel::deserialize::FromSql<ST, Pg>,
+    {
+        let raw = bind.value.as_deref().unwrap();
+        T::from_sql(PgValue::new(raw, bind.type_oid)).unwrap()
+    }
+
+    #[test]
+    fn core_types_use_the_postgres_binary_format() {
+        let int = to_wire::<Integer, _>(&-2_i32);
+        assert_eq!(23, int.type_oid);
+        assert_eq!(Some(vec![0xff, 0xff, 0xff, 0xfe]), int.value);
+        assert_eq!(-2, from_wire::<Integer, i32>(&int));
+
+        let big_int = to_wire::<BigInt, _>(&(1_i64 << 40));
+        assert_eq!(20, big_int.type_oid);
+        assert_eq!(Some(vec![0, 0, 1, 0, 0, 0, 0, 0]), big_int.value);
+        assert_eq!(1 << 40, from_wire::<BigInt, i64>(&big_int));
+
+        let boolean = to_wire::<Bool, _>(&true);
+        assert_eq!(16, boolean.type_oid);
+        assert_eq!(Some(vec![1]), boolean.value);
+        assert!(from_wire::<Bool, bool>(&boolean));
+
+        let double = to_wire::<Double, _>(&1.5_f64);
+        assert_eq!(701, double.type_oid);
+        assert_eq!(1.5, from_wire::<Double, f64>(&double));
+
+        let text = to_wire::<Text, _>(&"hello");
+        assert_eq!(25, text.type_oid);
+        assert_eq!(Some(b"hello".to_vec()), text.value);
+        assert_eq!("hello", from_wire::<Text, String>(&text));
+
+        let null = to_wire::<diesel::sql_types::Nullable<Text>, _>(&None::<String>);
+        assert_eq!(None, null.value);
+    }
+
+    #[test]
+    fn date_time_and_numeric_types_use_the_postgres_binary_format() {
+        use crate::pg::data_types::{PgDate, PgTime, PgTimestamp};
+        use bigdecimal::BigDecimal;
+        use diesel::sql_types::{Date, Numeric, Time, Timestamp};
+        use std::str::FromStr;
+
+        let date = to_wire::<Date, _>(&PgDate(-1));
+        assert_eq!(1082, date.type_oid);
+        assert_eq!(Some(vec![0xff, 0xff, 0xff, 0xff]), date.value);
+        assert_eq!(PgDate(-1), from_wire::<Date, PgDate>(&date));
+
+        let time = to_wire::<Time, _>(&PgTime(1_000_000));
+        assert_eq!(1083, time.type_oid);
+        assert_eq!(PgTime(1_000_000), from_wire::<Time, PgTime>(&time));
+
+        let timestamp = to_wire::<Timestamp, _>(&PgTimestamp(1 << 40));
+        assert_eq!(1114, timestamp.type_oid);
+        assert_eq!(Some(vec![0, 0, 1, 0, 0, 0, 0, 0]), timestamp.value);
+        assert_eq!(
+            PgTimestamp(1 << 40),
+            from_wire::<Timestamp, PgTimestamp>(&timestamp)
+        );
+
+        // digit count, weight, sign and scale, followed by base 10000 digits
+        let numeric = to_wire::<Numeric, _>(&BigDecimal::from_str("-100000000.0001").unwrap());
+        assert_eq!(1700, numeric.type_oid);
+        assert_eq!(
+            Some(vec![0, 4, 0, 2, 0x40, 0, 0, 4, 0, 1, 0, 0, 0, 0, 0, 1]),
+            numeric.value
+        );
+        for decimal in [
+            "0",
+            "0.00",
+            "1",
+            "10000",
+            "1.10",
+            "0.1",
+            "0.00001",
+            "-12345.678",
+        ] {
+            let decimal = BigDecimal::from_str(decimal).unwrap();
+            let wire = to_wire::<Numeric, _>(&decimal);
+            let read = from_wire::<Numeric, BigDecimal>(&wire);
+            assert_eq!(decimal, read);
+            assert_eq!(decimal.to_string(), read.to_string());
+        }
+        let scientific = BigDecimal::from_str("1e5").unwrap();
+        let wire = to_wire::<Numeric, _>(&scientific);
+        assert_eq!(Some(vec![0, 1, 0, 1, 0, 0, 0, 0, 0, 10]), wire.value);
+        assert_eq!(scientific, from_wire::<Numeric, BigDecimal>(&wire));
+
+        let nan = [0, 0, 0, 0, 0xc0, 0, 0, 0];
+        let result = <BigDecimal as diesel::deserialize::FromSql<Numeric, Pg>>::from_sql(
+            PgValue::new(&nan, 1700),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn decoding_a_value_of_the_wrong_size_fails() {
+        let result =
+            <i32 as diesel::deserialize::FromSql<Integer, Pg>>::from_sql(PgValue::new(&[0, 1], 23));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn sqlstate_codes_are_mapped_to_error_kinds() {
+        let error: Error = PgError {
+            code: Some("23505".into()),
+            message: "duplicate key value violates unique constraint".into(),
+            constraint: Some("users_pkey".into()),
+            ..Default::default()
+        }
+        .into();
+        match error {
+            Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                assert_eq!(Some("users_pkey"), info.constraint_name());
+            }
+            e => panic!("unexpected error {e:?}"),
+        }
+    }
+
+    // the statements run against the scripted host of `native_host.rs`
+    #[cfg(not(target_arch = "wasm32"))]
+    mod host {
+        use lunatic::test;
+
+        use super::*;
+        use crate::pg::native_host::{self, Received, Response};
+        use crate::pg::wire_format::PgColumn;
+
+        fn connection() -> PgConnection {
+            PgConnection::establish("postgres://localhost/lunatic_diesel").unwrap()
+        }
+
+        fn rows(columns: &[(&str, u32)], rows: Vec<Vec<PgBindValue>>) -> Response {
+            Response::Rows(PgResult {
+                columns: columns
+                    .iter()
+                    .map(|&(name, type_oid)| PgColumn {
+                        name: name.to_owned(),
+                        type_oid,
+                    })
+                    .collect(),
+                rows: rows
+                    .into_iter()
+                    .map(|row| row.into_iter().map(|bind| bind.value).collect())
+                    .collect(),
+            })
+        }
+
+        fn int(value: i32) -> PgBindValue {
+            to_wire::<Integer, _>(&value)
+        }
+
+        fn text(value: &str) -> PgBindValue {
+            to_wire::<Text, _>(&value)
+        }
+
+        #[test]
+        fn connecting_fails_for_invalid_urls() {
+            assert!(matches!(
+                PgConnection::establish("mysql://localhost/lunatic_diesel"),
+                Err(ConnectionError::BadConnection(_))
+            ));
+        }
+
+        #[test]
+        fn load_and_insert_through_the_host() {
+            let connection = &mut connection();
+            native_host::respond(rows(&[("id", 23)], vec![vec![int(1)], vec![int(2)]]));
+            let ids = diesel::insert_into(users::table)
+                .values(&vec![users::name.eq("Sean"), users::name.eq("Tess")])
+                .returning(users::id)
+                .get_results::<i32>(connection)
+                .unwrap();
+            assert_eq!(vec![1, 2], ids);
+
+            native_host::respond(rows(
+                &[("name", 25)],
+                vec![vec![text("Sean")], vec![text("Tess")]],
+            ));
+            let names = users::table
+                .select(users::name)
+                .order(users::id)
+                .load::<String>(connection);
+            assert_eq!(Ok(vec!["Sean".to_owned(), "Tess".to_owned()]), names);
+
+            assert_eq!(
+                vec![
+                    Received::new(
+                        "INSERT INTO \"users\" (\"name\") VALUES ($1), ($2) \
+                         RETURNING \"users\".\"id\"",
+                        vec![text("Sean"), text("Tess")]
+                    ),
+                    Received::new(
+                        "SELECT \"users\".\"name\" FROM \"users\" ORDER BY \"users\".\"id\"",
+                        vec![]
+                    ),
+                ],
+                native_host::received()
+            );
+        }
+
+        #[test]
+        fn null_values_are_loaded_as_none() {
+            let connection = &mut connection();
+            native_host::respond(rows(
+                &[("name", 25)],
+                vec![vec![to_wire::<diesel::sql_types::Nullable<Text>, _>(
+                    &None::<String>,
+                )]],
+            ));
+            let names = users::table
+                .select(users::name.nullable())
+                .load::<Option<String>>(connection);
+            assert_eq!(Ok(vec![None]), names);
+        }
+
+        #[test]
+        fn transactions_are_rolled_back_on_error() {
+            let connection = &mut connection();
+            let result = connection.transaction::<(), _, _>(|conn| {
+                diesel::insert_into(users::table)
+                    .values(users::name.eq("Sean"))
+                    .execute(conn)?;
+                Err(Error::RollbackTransaction)
+            });
+            assert_eq!(Err(Error::RollbackTransaction), result);
+
+            assert_eq!(
+                vec![
+                    Received::new("BEGIN", vec![]),
+                    Received::new(
+                        "INSERT INTO \"users\" (\"name\") VALUES ($1)",
+                        vec![text("Sean")]
+                    ),
+                    Received::new("ROLLBACK", vec![]),
+                ],
+                native_host::received()
+            );
+        }
+
+        #[test]
+        fn errors_of_the_server_are_reported() {
+            let connection = &mut connection();
+            native_host::respond(Response::Executed(1));
+            native_host::respond(Response::Failed(PgError {
+                code: Some("23505".into()),
+                message: "duplicate key value violates unique constraint".into(),
+                constraint: Some("users_pkey".into()),
+                ..Default::default()
+            }));
+
+            let inserted = diesel::insert_into(users::table)
+                .values((users::id.eq(1), users::name.eq("Sean")))
+                .execute(connection);
+            assert_eq!(Ok(1), inserted);
+            let result = diesel::insert_into(users::table)
+                .values((users::id.eq(1), users::name.eq("Tess")))
+                .execute(connection);
+            match result {
+                Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info)) => {
+                    assert_eq!(Some("users_pkey"), info.constraint_name());
+                }
+                result => panic!("unexpected result {result:?}"),
+            }
+
+            native_host::respond(Response::Failed(PgError {
+                code: Some("42P01".into()),
+                message: "relation \"posts\" does not exist".into(),
+                ..Default::default()
+            }));
+            match connection.batch_execute("DELETE FROM posts") {
+                Err(Error::DatabaseError(DatabaseErrorKind::Unknown, info)) => {
+                    assert_eq!("relation \"posts\" does not exist", info.message());
+                }
+                result => panic!("unexpected result {result:?}"),
+            }
+        }
+
+        #[test]
+        fn prepared_statements_are_cached() {
+            let connection = &mut connection();
+            for name in ["Sean", "Tess"] {
+                diesel::insert_into(users::table)
+                    .values(users::name.eq(name))
+                    .execute(connection)
+                    .unwrap();
+            }
+            assert_eq!(1, connection.statement_cache.len());
+            assert_eq!(2, native_host::received().len());
+        }
+    }
+}
@@ -0,0 +1,25 @@
+//! Provides types and functions related to working with PostgreSQL
+//!
+//! Much of this module is re-exported from database agnostic locations.
+//! However, if you are writing code specifically to extend Diesel on
+//! PostgreSQL, you may need to work with this module directly.
+
+pub mod data_types;
+mod diesel_backend;
+mod diesel_connection;
+#[cfg(target_arch = "wasm32")]
+mod host_bindings;
+#[cfg(not(target_arch = "wasm32"))]
+mod native_host;
+#[cfg(not(target_arch = "wasm32"))]
+use native_host as host_bindings;
+mod query_builder;
+mod stmt;
+mod types;
+mod value;
+mod wire_format;
+
+pub use diesel_backend::{Pg, PgTypeMetadata};
+pub use diesel_connection::*;
+pub use query_builder::PgQueryBuilder;
+pub use value::PgValue;
@@ -0,0 +1,248 @@
+//! A scripted stand-in for the PostgreSQL host functions of lunatic.
+//!
+//! Built instead of `host_bindings.rs` on targets other than wasm32, so that
+//! the backend can be tested natively without a PostgreSQL server. Every
+//! function has the signature of its counterpart in `host_bindings.rs`, and
+//! bind values, result sets and errors cross to the `host` module bincode
+//! encoded. The host runs no SQL: it records every statement it runs together
+//! with its bind values, see [`received`], and answers with the responses
+//! queued by [`respond`]. Statements without a queued response succeed without
+//! rows.
+
+use diesel::{result::Error, QueryResult};
+
+use super::wire_format::{PgBindValue, PgError, PgResult};
+
+#[cfg(test)]
+pub use self::host::{received, respond, Received, Response};
+
+pub fn connect(url: &str) -> Result<u64, String> {
+    host::connect(url)
+}
+
+pub fn close(connection_id: u64) {
+    host::close(connection_id)
+}
+
+pub fn execute(connection_id: u64, query: &str) -> u32 {
+    host::execute(connection_id, query)
+}
+
+pub fn query_prepare(connection_id: u64, query: &str, param_types: &[u32]) -> Result<u64, u32> {
+    let encoded = bincode::serialize(param_types).unwrap();
+    host::query_prepare(connection_id, query, &encoded)
+}
+
+pub fn statement_execute(statement_id: u64, binds: &[PgBindValue]) -> Result<u64, u32> {
+    let encoded = bincode::serialize(binds).unwrap();
+    host::statement_execute(statement_id, &encoded)
+}
+
+/// returns `Ok(Err(status))` if the host reported an error running the statement
+pub fn statement_query(
+    statement_id: u64,
+    binds: &[PgBindValue],
+) -> QueryResult<Result<PgResult, u32>> {
+    let encoded = bincode::serialize(binds).unwrap();
+    let encoded_result = match host::statement_query(statement_id, &encoded) {
+        Ok(encoded_result) => encoded_result,
+        Err(status) => return Ok(Err(status)),
+    };
+    bincode::deserialize(encoded_result.as_slice())
+        .map(Ok)
+        .map_err(|_| Error::DeserializationError("Failed to deserialize postgres result".into()))
+}
+
+pub fn statement_finalize(statement_id: u64) {
+    host::statement_finalize(statement_id)
+}
+
+pub fn last_error(connection_id: u64) -> QueryResult<PgError> {
+    let encoded_error = host::last_error(connection_id);
+    bincode::deserialize(encoded_error.as_slice())
+        .map_err(|_| Error::DeserializationError("Failed to deserialize postgres error".into()))
+}
+
+/// The host side of the PostgreSQL functions
+///
+/// Connections, statements and the script of responses are resources of the
+/// calling thread, like they are resources of the calling process in lunatic.
+/// Panics in here mirror the traps of the runtime, e.g. for ids that don't
+/// exist.
+mod host {
+    use std::cell::{Cell, RefCell};
+    use std::collections::{HashMap, VecDeque};
+
+    use crate::pg::wire_format::{PgBindValue, PgError, PgResult};
+
+    const STATUS_ERROR: u32 = 1;
+
+    /// The answer of the host to a statement it runs
+    // only the tests queue responses
+    #[cfg_attr(not(test), allow(dead_code))]
+    #[derive(Debug, Clone)]
+    pub enum Response {
+        /// The statement succeeds and affects the given number of rows
+        Executed(u64),
+        /// The statement succeeds and returns the given rows
+        Rows(PgResult),
+        /// The statement fails with the given error
+        Failed(PgError),
+    }
+
+    /// A statement run by the host, together with its bind values
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Received {
+        pub sql: String,
+        pub binds: Vec<PgBindValue>,
+    }
+
+    impl Received {
+        pub fn new(sql: &str, binds: Vec<PgBindValue>) -> Self {
+            Received {
+                sql: sql.to_owned(),
+                binds,
+            }
+        }
+    }
+
+    struct Statement {
+        sql: String,
+        param_types: Vec<u32>,
+        connection_id: u64,
+    }
+
+    thread_local! {
+        static NEXT_RESOURCE_ID: Cell<u64> = const { Cell::new(1) };
+        static CONNECTIONS: RefCell<HashMap<u64, Option<PgError>>> = RefCell::new(HashMap::new());
+        static STATEMENTS: RefCell<HashMap<u64, Statement>> = RefCell::new(HashMap::new());
+        static RESPONSES: RefCell<VecDeque<Response>> = const { RefCell::new(VecDeque::new()) };
+        static RECEIVED: RefCell<Vec<Received>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Queues the answer to the next statement that has none yet
+    #[cfg(test)]
+    pub fn respond(response: Response) {
+        RESPONSES.with(|responses| responses.borrow_mut().push_back(response))
+    }
+
+    /// Takes the statements run since the last call
+    #[cfg(test)]
+    pub fn received() -> Vec<Received> {
+        RECEIVED.with(|received| received.take())
+    }
+
+    fn next_resource_id() -> u64 {
+        NEXT_RESOURCE_ID.with(|id| {
+            let resource_id = id.get();
+            id.set(resource_id + 1);
+            resource_id
+        })
+    }
+
+    /// Records the statement and returns its response, failing ones are
+    /// stored as the last error of the connection
+    fn run(connection_id: u64, sql: &str, binds: Vec<PgBindValue>) -> Result<Response, u32> {
+        RECEIVED.with(|received| received.borrow_mut().push(Received::new(sql, binds)));
+        let response = RESPONSES
+            .with(|responses| responses.borrow_mut().pop_front())
+            .unwrap_or(Response::Executed(0));
+        match response {
+            Response::Failed(error) => {
+                CONNECTIONS.with(|connections| {
+                    *connections
+                        .borrow_mut()
+                        .get_mut(&connection_id)
+                        .expect("connection does not exist") = Some(error)
+                });
+                Err(STATUS_ERROR)
+            }
+            response => Ok(response),
+        }
+    }
+
+    fn run_statement(statement_id: u64, binds: &[u8]) -> Result<Response, u32> {
+        let binds: Vec<PgBindValue> = bincode::deserialize(binds).unwrap();
+        let (connection_id, sql) = STATEMENTS.with(|statements| {
+            let statements = statements.borrow();
+            let statement = statements
+                .get(&statement_id)
+                .expect("statement does not exist");
+            assert_eq!(
+                statement.param_types,
+                binds.iter().map(|bind| bind.type_oid).collect::<Vec<_>>(),
+                "bind values don't match the parameter types of the statement"
+            );
+            (statement.connection_id, statement.sql.clone())
+        });
+        run(connection_id, &sql, binds)
+    }
+
+    pub fn connect(url: &str) -> Result<u64, String> {
+        if !url.starts_with("postgres://") && !url.starts_with("postgresql://") {
+            return Err(format!("invalid connection string `{url}`"));
+        }
+        let connection_id = next_resource_id();
+        CONNECTIONS.with(|connections| connections.borrow_mut().insert(connection_id, None));
+        Ok(connection_id)
+    }
+
+    pub fn close(connection_id: u64) {
+        CONNECTIONS.with(|connections| connections.borrow_mut().remove(&connection_id));
+    }
+
+    pub fn execute(connection_id: u64, query: &str) -> u32 {
+        match run(connection_id, query, Vec::new()) {
+            Ok(_) => 0,
+            Err(status) => status,
+        }
+    }
+
+    pub fn query_prepare(connection_id: u64, query: &str, param_types: &[u8]) -> Result<u64, u32> {
+        assert!(
+            CONNECTIONS.with(|connections| connections.borrow().contains_key(&connection_id)),
+            "connection does not exist"
+        );
+        let statement = Statement {
+            sql: query.to_owned(),
+            param_types: bincode::deserialize(param_types).unwrap(),
+            connection_id,
+        };
+        let statement_id = next_resource_id();
+        STATEMENTS.with(|statements| statements.borrow_mut().insert(statement_id, statement));
+        Ok(statement_id)
+    }
+
+    pub fn statement_execute(statement_id: u64, binds: &[u8]) -> Result<u64, u32> {
+        match run_statement(statement_id, binds)? {
+            Response::Executed(rows_affected) => Ok(rows_affected),
+            Response::Rows(result) => Ok(result.rows.len() as u64),
+            Response::Failed(_) => unreachable!(),
+        }
+    }
+
+    pub fn statement_query(statement_id: u64, binds: &[u8]) -> Result<Vec<u8>, u32> {
+        let result = match run_statement(statement_id, binds)? {
+            Response::Executed(_) => PgResult::default(),
+            Response::Rows(result) => result,
+            Response::Failed(_) => unreachable!(),
+        };
+        Ok(bincode::serialize(&result).unwrap())
+    }
+
+    pub fn statement_finalize(statement_id: u64) {
+        STATEMENTS.with(|statements| statements.borrow_mut().remove(&statement_id));
+    }
+
+    pub fn last_error(connection_id: u64) -> Vec<u8> {
+        let error = CONNECTIONS.with(|connections| {
+            connections
+                .borrow_mut()
+                .get_mut(&connection_id)
+                .expect("connection does not exist")
+                .take()
+                .unwrap_or_default()
+        });
+        bincode::serialize(&error).unwrap()
+    }
+}
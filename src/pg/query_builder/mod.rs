@@ -0,0 +1,50 @@
+//! The PostgreSQL query builder
+
+use crate::query_builder::QueryBuilder;
+use crate::result::QueryResult;
+
+use super::Pg;
+
+mod limit_offset;
+
+/// Constructs SQL queries for use with the PostgreSQL backend
+#[allow(missing_debug_implementations)]
+#[derive(Default)]
+pub struct PgQueryBuilder {
+    sql: String,
+    bind_idx: u32,
+}
+
+impl PgQueryBuilder {
+    /// Construct a new query builder with an empty query
+    pub fn new() -> Self {
+        PgQueryBuilder::default()
+    }
+}
+
+impl QueryBuilder<Pg> for PgQueryBuilder {
+    fn push_sql(&mut self, sql: &str) {
+        self.sql.push_str(sql);
+    }
+
+    fn push_identifier(&mut self, identifier: &str) -> QueryResult<()> {
+        self.push_sql("\"");
+        self.push_sql(&identifier.replace('"', "\"\""));
+        self.push_sql("\"");
+        Ok(())
+    }
+
+    fn push_bind_param(&mut self) {
+        self.push_bind_param_value_only();
+        self.sql += "$";
+        self.sql += &self.bind_idx.to_string();
+    }
+
+    fn push_bind_param_value_only(&mut self) {
+        self.bind_idx += 1;
+    }
+
+    fn finish(self) -> String {
+        self.sql
+    }
+}
@@ -0,0 +1,43 @@
+use diesel::result::QueryResult;
+
+use super::diesel_connection::{last_error, RawConnection};
+use super::host_bindings as ffi;
+use super::wire_format::{PgBindValue, PgResult};
+
+pub(crate) struct Statement {
+    statement_id: u64,
+    connection_id: u64,
+}
+
+impl Statement {
+    pub(super) fn prepare(
+        raw_connection: &RawConnection,
+        sql: &str,
+        param_types: &[u32],
+    ) -> QueryResult<Self> {
+        let statement_id = ffi::query_prepare(raw_connection.connection_id, sql, param_types)
+            .map_err(|_| last_error(raw_connection.connection_id))?;
+        Ok(Statement {
+            statement_id,
+            connection_id: raw_connection.connection_id,
+        })
+    }
+
+    /// Runs the statement and returns the number of affected rows
+    pub(super) fn execute(&self, binds: &[PgBindValue]) -> QueryResult<usize> {
+        ffi::statement_execute(self.statement_id, binds)
+            .map(|rows_affected| rows_affected as usize)
+            .map_err(|_| last_error(self.connection_id))
+    }
+
+    /// Runs the statement and loads the complete result set
+    pub(super) fn query(&self, binds: &[PgBindValue]) -> QueryResult<PgResult> {
+        ffi::statement_query(self.statement_id, binds)?.map_err(|_| last_error(self.connection_id))
+    }
+}
+
+impl Drop for Statement {
+    fn drop(&mut self) {
+        ffi::statement_finalize(self.statement_id);
+    }
+}
@@ -0,0 +1,265 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types;
+
+use super::data_types::{PgDate, PgTime, PgTimestamp};
+use super::{Pg, PgValue};
+
+// All values are sent in the binary format of PostgreSQL, which stores
+// numbers in network byte order (big endian)
+fn read_array<const N: usize>(value: PgValue<'_>, type_name: &str) -> deserialize::Result<[u8; N]> {
+    value.as_bytes().try_into().map_err(|_| {
+        format!(
+            "Received {} bytes while decoding {type_name}, expected {N}. \
+             Was an expression of a different type misidentified as {type_name}?",
+            value.as_bytes().len()
+        )
+        .into()
+    })
+}
+
+fn write_bytes(out: &mut Output<'_, '_, Pg>, bytes: &[u8]) -> serialize::Result {
+    out.write_all(bytes)
+        .map(|_| IsNull::No)
+        .map_err(|e| Box::new(e) as Box<_>)
+}
+
+/// The returned pointer is *only* valid for the lifetime to the argument of
+/// `from_sql`. This impl is intended for uses where you want to write a new
+/// impl in terms of `String`, but don't want to allocate. We have to return a
+/// raw pointer instead of a reference with a lifetime due to the structure of
+/// `FromSql`
+impl FromSql<sql_types::Text, Pg> for *const str {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        let string = std::str::from_utf8(value.as_bytes())?;
+        Ok(string as *const _)
+    }
+}
+
+/// The returned pointer is *only* valid for the lifetime to the argument of
+/// `from_sql`. This impl is intended for uses where you want to write a new
+/// impl in terms of `Vec<u8>`, but don't want to allocate. We have to return a
+/// raw pointer instead of a reference with a lifetime due to the structure of
+/// `FromSql`
+impl FromSql<sql_types::Binary, Pg> for *const [u8] {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        Ok(value.as_bytes() as *const _)
+    }
+}
+
+impl FromSql<sql_types::Bool, Pg> for bool {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        let [byte] = read_array::<1>(value, "Bool")?;
+        Ok(byte != 0)
+    }
+}
+
+impl ToSql<sql_types::Bool, Pg> for bool {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        write_bytes(out, &[*self as u8])
+    }
+}
+
+impl FromSql<sql_types::SmallInt, Pg> for i16 {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        read_array(value, "SmallInt").map(i16::from_be_bytes)
+    }
+}
+
+impl ToSql<sql_types::SmallInt, Pg> for i16 {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        write_bytes(out, &self.to_be_bytes())
+    }
+}
+
+impl FromSql<sql_types::Integer, Pg> for i32 {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        read_array(value, "Integer").map(i32::from_be_bytes)
+    }
+}
+
+impl ToSql<sql_types::Integer, Pg> for i32 {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        write_bytes(out, &self.to_be_bytes())
+    }
+}
+
+impl FromSql<sql_types::BigInt, Pg> for i64 {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        read_array(value, "BigInt").map(i64::from_be_bytes)
+    }
+}
+
+impl ToSql<sql_types::BigInt, Pg> for i64 {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        write_bytes(out, &self.to_be_bytes())
+    }
+}
+
+impl FromSql<sql_types::Float, Pg> for f32 {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        read_array(value, "Float").map(f32::from_be_bytes)
+    }
+}
+
+impl ToSql<sql_types::Float, Pg> for f32 {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        write_bytes(out, &self.to_be_bytes())
+    }
+}
+
+impl FromSql<sql_types::Double, Pg> for f64 {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        read_array(value, "Double").map(f64::from_be_bytes)
+    }
+}
+
+impl ToSql<sql_types::Double, Pg> for f64 {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        write_bytes(out, &self.to_be_bytes())
+    }
+}
+
+impl FromSql<sql_types::Timestamp, Pg> for PgTimestamp {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        read_array(value, "Timestamp")
+            .map(i64::from_be_bytes)
+            .map(PgTimestamp)
+    }
+}
+
+impl ToSql<sql_types::Timestamp, Pg> for PgTimestamp {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        write_bytes(out, &self.0.to_be_bytes())
+    }
+}
+
+impl FromSql<sql_types::Date, Pg> for PgDate {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        read_array(value, "Date")
+            .map(i32::from_be_bytes)
+            .map(PgDate)
+    }
+}
+
+impl ToSql<sql_types::Date, Pg> for PgDate {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        write_bytes(out, &self.0.to_be_bytes())
+    }
+}
+
+impl FromSql<sql_types::Time, Pg> for PgTime {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        read_array(value, "Time")
+            .map(i64::from_be_bytes)
+            .map(PgTime)
+    }
+}
+
+impl ToSql<sql_types::Time, Pg> for PgTime {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        write_bytes(out, &self.0.to_be_bytes())
+    }
+}
+
+// A numeric is sent as its number of digits, the weight of the first digit,
+// its sign and its display scale, followed by the digits, all of them 16 bit
+// integers. Digits are base 10000, so every one of them holds four decimal
+// digits, and the weight is the power of 10000 of the first one.
+const NUMERIC_POSITIVE: u16 = 0x0000;
+const NUMERIC_NEGATIVE: u16 = 0x4000;
+const NUMERIC_NAN: u16 = 0xC000;
+
+impl FromSql<sql_types::Numeric, Pg> for BigDecimal {
+    fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+        let bytes = value.as_bytes();
+        let words = bytes
+            .chunks(2)
+            .map(|word| <[u8; 2]>::try_from(word).map(u16::from_be_bytes))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| "Received an odd number of bytes while decoding Numeric")?;
+        let [digit_count, weight, sign, scale, ref digits @ ..] = words[..] else {
+            return Err("Received less than 8 bytes while decoding Numeric".into());
+        };
+        if digits.len() != digit_count as usize {
+            return Err(format!(
+                "Received {} digits while decoding Numeric, expected {digit_count}",
+                digits.len()
+            )
+            .into());
+        }
+        let sign = match sign {
+            NUMERIC_POSITIVE => "",
+            NUMERIC_NEGATIVE => "-",
+            NUMERIC_NAN => return Err("NaN can't be represented as BigDecimal".into()),
+            sign => return Err(format!("{sign:#x} is no valid sign of a Numeric").into()),
+        };
+        if digits.is_empty() {
+            return Ok(BigDecimal::from(0).with_scale(scale.into()));
+        }
+        let digits: String = digits.iter().map(|digit| format!("{digit:04}")).collect();
+        // the last digit is multiplied by 10000^(weight - digit_count + 1)
+        let exponent = 4 * (i64::from(weight as i16) - i64::from(digit_count) + 1);
+        let decimal = BigDecimal::from_str(&format!("{sign}{digits}e{exponent}"))?;
+        Ok(decimal.with_scale(scale.into()))
+    }
+}
+
+impl ToSql<sql_types::Numeric, Pg> for BigDecimal {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        let (integer, scale) = self.as_bigint_and_exponent();
+        let integer = integer.to_string();
+        let (sign, mut decimal_digits) = match integer.strip_prefix('-') {
+            Some(digits) => (NUMERIC_NEGATIVE, digits.to_owned()),
+            None => (NUMERIC_POSITIVE, integer),
+        };
+        // a negative scale stands for trailing zeros of the integer part
+        let scale = if scale < 0 {
+            decimal_digits.push_str(&"0".repeat(scale.unsigned_abs() as usize));
+            0
+        } else {
+            u16::try_from(scale).map_err(|_| format!("The scale of {self} is too large"))?
+        };
+
+        // aligns the decimal point to a digit boundary, with at least one
+        // digit before it
+        let fraction_length = usize::from(scale);
+        let integer_length = decimal_digits.len().saturating_sub(fraction_length);
+        let leading_zeros =
+            (4 - integer_length % 4) % 4 + fraction_length.saturating_sub(decimal_digits.len());
+        let trailing_zeros = (4 - fraction_length % 4) % 4;
+        let decimal_digits = format!(
+            "{}{decimal_digits}{}",
+            "0".repeat(leading_zeros),
+            "0".repeat(trailing_zeros)
+        );
+        let mut digits = decimal_digits
+            .as_bytes()
+            .chunks(4)
+            .map(|digit| std::str::from_utf8(digit).unwrap().parse::<u16>().unwrap())
+            .collect::<Vec<_>>();
+        let mut weight = integer_length.div_ceil(4) as i16 - 1;
+
+        // zero digits at both ends are implied by the weight
+        let leading_zero_digits = digits.iter().take_while(|&&digit| digit == 0).count();
+        digits.drain(..leading_zero_digits);
+        weight -= leading_zero_digits as i16;
+        let trailing_zero_digits = digits.iter().rev().take_while(|&&digit| digit == 0).count();
+        digits.truncate(digits.len() - trailing_zero_digits);
+        if digits.is_empty() {
+            weight = 0;
+        }
+
+        let digit_count = u16::try_from(digits.len())
+            .map_err(|_| format!("{self} has too many digits for a Numeric"))?;
+        let header = [digit_count, weight as u16, sign, scale];
+        for word in header.into_iter().chain(digits) {
+            out.write_all(&word.to_be_bytes())?;
+        }
+        Ok(IsNull::No)
+    }
+}
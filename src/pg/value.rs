@@ -0,0 +1,27 @@
+/// Raw PostgreSQL value as received from the host
+///
+/// Values are always transferred in the binary format of PostgreSQL.
+#[derive(Clone, Copy, Debug)]
+pub struct PgValue<'a> {
+    raw_value: &'a [u8],
+    type_oid: u32,
+}
+
+impl<'a> PgValue<'a> {
+    pub(in crate::pg) fn new(raw_value: &'a [u8], type_oid: u32) -> Self {
+        Self {
+            raw_value,
+            type_oid,
+        }
+    }
+
+    /// Get the underlying raw byte representation
+    pub fn as_bytes(&self) -> &[u8] {
+        self.raw_value
+    }
+
+    /// Get the type oid of this value
+    pub fn get_oid(&self) -> u32 {
+        self.type_oid
+    }
+}
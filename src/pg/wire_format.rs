@@ -0,0 +1,38 @@
+//! Types exchanged with the host, encoded with bincode
+//!
+//! All values use the binary format of PostgreSQL.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PgBindValue {
+    pub type_oid: u32,
+    pub value: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PgColumn {
+    pub name: String,
+    pub type_oid: u32,
+}
+
+/// The complete result set of a query
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PgResult {
+    pub columns: Vec<PgColumn>,
+    pub rows: Vec<Vec<Option<Vec<u8>>>>,
+}
+
+/// Error reported by the PostgreSQL server (or the host driver)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PgError {
+    /// The SQLSTATE code of the error
+    pub code: Option<String>,
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+    pub table: Option<String>,
+    pub column: Option<String>,
+    pub constraint: Option<String>,
+    pub position: Option<i32>,
+}
@@ -2,7 +2,10 @@ use diesel::query_builder::BindCollector;
 use diesel::serialize::{IsNull, Output};
 use diesel::sql_types::HasSqlType;
 use diesel::QueryResult;
-use lunatic_sqlite_api::wire_format::{BindKey, BindPair, BindValue};
+#[cfg(any(target_arch = "wasm32", feature = "host-callbacks"))]
+use lunatic_sqlite_api::wire_format::BindValue;
+use lunatic_sqlite_api::wire_format::{BindKey, BindList};
+use serde::ser::{Serialize, SerializeSeq, SerializeTupleStruct, Serializer};
 
 use super::diesel_backend::{Sqlite, SqliteType};
 
@@ -109,25 +112,113 @@ pub(crate) enum InternalSqliteBindValue<'a> {
 }
 
 impl<'a> InternalSqliteBindValue<'a> {
-    pub fn to_ffi_struct(idx: i32, src: InternalSqliteBindValue<'a>) -> BindPair {
-        let key = BindKey::Numeric(idx as usize);
-        match src {
-            InternalSqliteBindValue::BorrowedString(s) => {
-                BindPair(key, BindValue::Text(s.to_owned()))
+    /// Copies the value into a `BindValue` that outlives the borrowed buffers,
+    /// for the results of SQL functions and the queries of other processes
+    #[cfg(any(target_arch = "wasm32", feature = "host-callbacks"))]
+    pub(in crate::sqlite) fn into_bind_value(self) -> BindValue {
+        match self {
+            InternalSqliteBindValue::BorrowedString(s) => BindValue::Text(s.to_owned()),
+            InternalSqliteBindValue::String(s) => BindValue::Text(s.into()),
+            InternalSqliteBindValue::BorrowedBinary(blob) => BindValue::Blob(blob.to_owned()),
+            InternalSqliteBindValue::Binary(blob) => BindValue::Blob(blob.into()),
+            InternalSqliteBindValue::I32(int) => BindValue::Int(int),
+            InternalSqliteBindValue::I64(int) => BindValue::Int64(int),
+            InternalSqliteBindValue::F64(double) => BindValue::Double(double),
+            InternalSqliteBindValue::Null => BindValue::Null,
+        }
+    }
+}
+
+// Serializes the value the same way as the corresponding `BindValue`, without
+// copying strings or blobs into an owned value first. bincode encodes `&str`
+// and `&[u8]` exactly like `String` and `Vec<u8>`, so the host can't tell
+// the difference.
+impl Serialize for InternalSqliteBindValue<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // variant indices of `BindValue`
+        match self {
+            InternalSqliteBindValue::Null => {
+                serializer.serialize_unit_variant("BindValue", 0, "Null")
             }
-            InternalSqliteBindValue::String(s) => BindPair(key, BindValue::Text(s.to_string())),
             InternalSqliteBindValue::BorrowedBinary(blob) => {
-                BindPair(key, BindValue::Blob(blob.to_owned()))
+                serializer.serialize_newtype_variant("BindValue", 1, "Blob", blob)
+            }
+            InternalSqliteBindValue::Binary(blob) => {
+                serializer.serialize_newtype_variant("BindValue", 1, "Blob", &**blob)
+            }
+            InternalSqliteBindValue::BorrowedString(s) => {
+                serializer.serialize_newtype_variant("BindValue", 2, "Text", s)
+            }
+            InternalSqliteBindValue::String(s) => {
+                serializer.serialize_newtype_variant("BindValue", 2, "Text", &**s)
+            }
+            InternalSqliteBindValue::F64(double) => {
+                serializer.serialize_newtype_variant("BindValue", 3, "Double", double)
+            }
+            InternalSqliteBindValue::I32(int) => {
+                serializer.serialize_newtype_variant("BindValue", 4, "Int", int)
+            }
+            InternalSqliteBindValue::I64(int) => {
+                serializer.serialize_newtype_variant("BindValue", 5, "Int64", int)
             }
-            InternalSqliteBindValue::Binary(blob) => BindPair(key, BindValue::Blob(blob.to_vec())),
-            InternalSqliteBindValue::I32(int) => BindPair(key, BindValue::Int(int)),
-            InternalSqliteBindValue::I64(int) => BindPair(key, BindValue::Int64(int)),
-            InternalSqliteBindValue::F64(double) => BindPair(key, BindValue::Double(double)),
-            InternalSqliteBindValue::Null => BindPair(key, BindValue::Null),
         }
     }
 }
 
+/// All binds of a statement, encoded like a `BindList` of numeric `BindPair`s
+/// so that they can be handed to the host in a single call
+pub(in crate::sqlite) struct WireBindList<'b, 'a>(
+    pub(in crate::sqlite) &'b [(InternalSqliteBindValue<'a>, SqliteType)],
+);
+
+impl Serialize for WireBindList<'_, '_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("BindList", &WireBindPairs(self.0))
+    }
+}
+
+/// Binds encoded like a `BindList`, which are handed to the host as they are
+pub(in crate::sqlite) trait BindValues: Serialize {}
+
+impl BindValues for WireBindList<'_, '_> {}
+
+impl BindValues for BindList {}
+
+struct WireBindPairs<'b, 'a>(&'b [(InternalSqliteBindValue<'a>, SqliteType)]);
+
+impl Serialize for WireBindPairs<'_, '_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for (bind_idx, (value, _)) in (1..).zip(self.0) {
+            seq.serialize_element(&WireBindPair(bind_idx, value))?;
+        }
+        seq.end()
+    }
+}
+
+struct WireBindPair<'b, 'a>(usize, &'b InternalSqliteBindValue<'a>);
+
+impl Serialize for WireBindPair<'_, '_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut pair = serializer.serialize_tuple_struct("BindPair", 2)?;
+        pair.serialize_field(&BindKey::Numeric(self.0))?;
+        pair.serialize_field(self.1)?;
+        pair.end()
+    }
+}
+
 impl std::fmt::Display for InternalSqliteBindValue<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let n = match self {
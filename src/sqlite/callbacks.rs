@@ -0,0 +1,324 @@
+//! Registry of guest callbacks that SQLite calls back into.
+//!
+//! Custom functions can't be passed to the host as function pointers. Every
+//! callback is stored here under a `callback_id` instead, and the host invokes
+//! it through one of the exported trampolines below.
+
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::mem::ManuallyDrop;
+use std::thread::LocalKey;
+
+use diesel::result::Error;
+use lunatic_sqlite_api::wire_format::{BindValue, SqliteError, SqliteRow, SqliteValue};
+use serde::Serialize;
+
+use super::diesel_connection::RawConnection;
+#[cfg(target_arch = "wasm32")]
+use super::host_bindings::unroll_vec;
+
+/// Name of the guest export the host calls to run a custom scalar function
+pub(crate) const FUNCTION_TRAMPOLINE: &str = "lunatic_sqlite_call_function";
+/// Name of the guest export the host calls for every row of a custom aggregate
+pub(crate) const AGGREGATE_STEP_TRAMPOLINE: &str = "lunatic_sqlite_aggregate_step";
+/// Name of the guest export the host calls to compute the result of a custom aggregate
+pub(crate) const AGGREGATE_FINAL_TRAMPOLINE: &str = "lunatic_sqlite_aggregate_final";
+/// Name of the guest export the host calls to compare two strings with a custom collation
+pub(crate) const COLLATION_TRAMPOLINE: &str = "lunatic_sqlite_call_collation";
+
+static NULL_DATA_ERR: &str = "An unknown error occurred. The host called a callback that is not registered. This should never happen.";
+static ARGS_DECODE_ERR: &str =
+    "An unknown error occurred. Failed to deserialize the callback arguments sent by the host.";
+static INVALID_UTF8_ERR: &str = "Got an invalid UTF-8 string passed to a collation function.";
+
+type FunctionCallback =
+    dyn FnMut(&RawConnection, &[SqliteValue]) -> Result<BindValue, SqliteCallbackError>;
+
+type CollationCallback = dyn Fn(&str, &str) -> Ordering;
+
+/// The host side state of a custom aggregate function
+///
+/// SQLite may evaluate the same aggregate several times at once (e.g. for
+/// different groups), so the host identifies each evaluation with its own
+/// `context_id`.
+pub(crate) trait AggregateCallback {
+    fn step(&mut self, context_id: u64, args: &[SqliteValue]) -> Result<(), SqliteCallbackError>;
+
+    fn finalize(&mut self, context_id: u64) -> Result<BindValue, SqliteCallbackError>;
+}
+
+struct CallbackUserPtr<C: ?Sized> {
+    callback: Box<C>,
+    function_name: String,
+    connection_id: u64,
+}
+
+type Registry<C> = RefCell<HashMap<u64, CallbackUserPtr<C>>>;
+
+thread_local! {
+    static NEXT_CALLBACK_ID: Cell<u64> = const { Cell::new(1) };
+    static FUNCTIONS: Registry<FunctionCallback> = RefCell::new(HashMap::new());
+    static AGGREGATES: Registry<dyn AggregateCallback> = RefCell::new(HashMap::new());
+    static COLLATIONS: Registry<CollationCallback> = RefCell::new(HashMap::new());
+    // The encoded result of the last callback. It needs to outlive the trampoline
+    // call because the host reads it after the trampoline has returned.
+    #[cfg(target_arch = "wasm32")]
+    static LAST_RESULT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+pub(crate) enum SqliteCallbackError {
+    Abort(&'static str),
+    DieselError(Error),
+    Panic(String),
+}
+
+impl SqliteCallbackError {
+    fn into_sqlite_error(self) -> SqliteError {
+        let message = match self {
+            SqliteCallbackError::Abort(msg) => msg.to_owned(),
+            SqliteCallbackError::DieselError(e) => e.to_string(),
+            SqliteCallbackError::Panic(name) => format!("`{name}` panicked"),
+        };
+        SqliteError {
+            code: None,
+            message: Some(message),
+        }
+    }
+}
+
+impl From<Error> for SqliteCallbackError {
+    fn from(e: Error) -> Self {
+        Self::DieselError(e)
+    }
+}
+
+fn next_callback_id() -> u64 {
+    NEXT_CALLBACK_ID.with(|id| {
+        let callback_id = id.get();
+        id.set(callback_id + 1);
+        callback_id
+    })
+}
+
+fn insert<C: ?Sized>(
+    registry: &'static LocalKey<Registry<C>>,
+    connection_id: u64,
+    function_name: &str,
+    callback: Box<C>,
+) -> u64 {
+    let callback_id = next_callback_id();
+    registry.with(|registry| {
+        registry.borrow_mut().insert(
+            callback_id,
+            CallbackUserPtr {
+                callback,
+                function_name: function_name.to_owned(),
+                connection_id,
+            },
+        )
+    });
+    callback_id
+}
+
+pub(crate) fn register_function<F>(connection_id: u64, function_name: &str, callback: F) -> u64
+where
+    F: FnMut(&RawConnection, &[SqliteValue]) -> Result<BindValue, SqliteCallbackError> + 'static,
+{
+    insert(&FUNCTIONS, connection_id, function_name, Box::new(callback))
+}
+
+pub(crate) fn register_aggregate<A>(connection_id: u64, function_name: &str, aggregate: A) -> u64
+where
+    A: AggregateCallback + 'static,
+{
+    insert(
+        &AGGREGATES,
+        connection_id,
+        function_name,
+        Box::new(aggregate),
+    )
+}
+
+pub(crate) fn register_collation<F>(connection_id: u64, collation_name: &str, collation: F) -> u64
+where
+    F: Fn(&str, &str) -> Ordering + 'static,
+{
+    insert(
+        &COLLATIONS,
+        connection_id,
+        collation_name,
+        Box::new(collation),
+    )
+}
+
+pub(crate) fn unregister(callback_id: u64) {
+    FUNCTIONS.with(|functions| functions.borrow_mut().remove(&callback_id));
+    AGGREGATES.with(|aggregates| aggregates.borrow_mut().remove(&callback_id));
+    COLLATIONS.with(|collations| collations.borrow_mut().remove(&callback_id));
+}
+
+/// Drops every callback that was registered for the given connection
+pub(crate) fn unregister_connection(connection_id: u64) {
+    FUNCTIONS.with(|functions| {
+        functions
+            .borrow_mut()
+            .retain(|_, f| f.connection_id != connection_id)
+    });
+    AGGREGATES.with(|aggregates| {
+        aggregates
+            .borrow_mut()
+            .retain(|_, a| a.connection_id != connection_id)
+    });
+    COLLATIONS.with(|collations| {
+        collations
+            .borrow_mut()
+            .retain(|_, c| c.connection_id != connection_id)
+    });
+}
+
+fn decode_args(encoded_args: &[u8]) -> Result<SqliteRow, SqliteCallbackError> {
+    bincode::deserialize(encoded_args).map_err(|_| SqliteCallbackError::Abort(ARGS_DECODE_ERR))
+}
+
+fn decode_str(bytes: Vec<u8>) -> Result<String, SqliteCallbackError> {
+    String::from_utf8(bytes).map_err(|_| SqliteCallbackError::Abort(INVALID_UTF8_ERR))
+}
+
+fn encode_result<T: Serialize>(result: Result<T, SqliteCallbackError>) -> Vec<u8> {
+    let result = result.map_err(SqliteCallbackError::into_sqlite_error);
+    bincode::serialize(&result).expect("Failed to serialize callback result")
+}
+
+// Keeps the encoded result of a callback alive and hands out a composite of
+// length (upper half) and pointer (lower half) to the host
+#[cfg(target_arch = "wasm32")]
+fn write_result(encoded: Vec<u8>) -> u64 {
+    LAST_RESULT.with(|last_result| {
+        let mut last_result = last_result.borrow_mut();
+        *last_result = encoded;
+        ((last_result.len() as u64) << 32) | (last_result.as_ptr() as u64 & 0xFFFF_FFFF)
+    })
+}
+// Runs the callback registered under `callback_id` and turns panics into errors
+fn run_callback<C: ?Sized, T>(
+    registry: &'static LocalKey<Registry<C>>,
+    callback_id: u64,
+    f: impl FnOnce(&mut C, &RawConnection) -> Result<T, SqliteCallbackError>,
+) -> Result<T, SqliteCallbackError> {
+    // The callback is taken out of the registry while it runs so that it is free
+    // to use the connection (and therefore the registry) itself
+    let mut data = registry
+        .with(|registry| registry.borrow_mut().remove(&callback_id))
+        .ok_or(SqliteCallbackError::Abort(NULL_DATA_ERR))?;
+    // We use `ManuallyDrop` here because we do not want to run the
+    // Drop impl of `RawConnection` as this would drop all callbacks
+    // of the connection
+    let conn = ManuallyDrop::new(RawConnection {
+        connection_id: data.connection_id,
+    });
+
+    // We need this to move the reference into the catch_unwind part
+    // this is sound as all callbacks are required to be `UnwindSafe`
+    let wrapped = std::panic::AssertUnwindSafe((&mut *data.callback, f));
+    let result = std::panic::catch_unwind(move || {
+        // bind the wrapper as a whole, otherwise the closure would
+        // only capture its (not unwind safe) fields
+        let wrapped = wrapped;
+        let (callback, f) = wrapped.0;
+        f(callback, &conn)
+    })
+    .unwrap_or_else(|_| Err(SqliteCallbackError::Panic(data.function_name.clone())));
+
+    registry.with(|registry| registry.borrow_mut().insert(callback_id, data));
+    result
+}
+
+/// Runs the scalar function registered under `callback_id` with the bincode
+/// encoded `SqliteRow` of its arguments and returns its encoded result
+pub(crate) fn call_function(callback_id: u64, encoded_args: &[u8]) -> Vec<u8> {
+    let result = decode_args(encoded_args).and_then(|args| {
+        run_callback(&FUNCTIONS, callback_id, |callback, conn| {
+            callback(conn, &args.0)
+        })
+    });
+    encode_result(result)
+}
+
+/// Feeds one row of arguments into the evaluation `context_id` of an aggregate
+pub(crate) fn aggregate_step(callback_id: u64, context_id: u64, encoded_args: &[u8]) -> Vec<u8> {
+    let result = decode_args(encoded_args).and_then(|args| {
+        run_callback(&AGGREGATES, callback_id, |aggregate, _| {
+            aggregate.step(context_id, &args.0)
+        })
+    });
+    encode_result(result)
+}
+
+/// Computes the encoded result of the evaluation `context_id` of an aggregate
+pub(crate) fn aggregate_final(callback_id: u64, context_id: u64) -> Vec<u8> {
+    let result = run_callback(&AGGREGATES, callback_id, |aggregate, _| {
+        aggregate.finalize(context_id)
+    });
+    encode_result(result)
+}
+
+/// Compares two strings with a collation and returns the encoded ordering
+///
+/// SQLite has no way to report a failing collation, so the host is expected to
+/// abort the running statement with the returned error instead.
+pub(crate) fn call_collation(callback_id: u64, lhs: Vec<u8>, rhs: Vec<u8>) -> Vec<u8> {
+    let result = decode_str(lhs).and_then(|lhs| {
+        let rhs = decode_str(rhs)?;
+        run_callback(&COLLATIONS, callback_id, |collation, _| {
+            Ok(collation(&lhs, &rhs) as i32)
+        })
+    });
+    encode_result(result)
+}
+
+// The trampolines exported to the host. Argument buffers were allocated by the
+// host through `lunatic_alloc`, so they are owned by the guest from here on.
+
+#[cfg(target_arch = "wasm32")]
+#[export_name = "lunatic_sqlite_call_function"]
+extern "C" fn run_custom_function(callback_id: u64, args_ptr: u32, args_len: u32) -> u64 {
+    write_result(call_function(callback_id, &unroll_vec(args_ptr, args_len)))
+}
+
+#[cfg(target_arch = "wasm32")]
+#[export_name = "lunatic_sqlite_aggregate_step"]
+extern "C" fn run_aggregator_step_function(
+    callback_id: u64,
+    context_id: u64,
+    args_ptr: u32,
+    args_len: u32,
+) -> u64 {
+    write_result(aggregate_step(
+        callback_id,
+        context_id,
+        &unroll_vec(args_ptr, args_len),
+    ))
+}
+
+#[cfg(target_arch = "wasm32")]
+#[export_name = "lunatic_sqlite_aggregate_final"]
+extern "C" fn run_aggregator_final_function(callback_id: u64, context_id: u64) -> u64 {
+    write_result(aggregate_final(callback_id, context_id))
+}
+
+#[cfg(target_arch = "wasm32")]
+#[export_name = "lunatic_sqlite_call_collation"]
+extern "C" fn run_collation_function(
+    callback_id: u64,
+    lhs_ptr: u32,
+    lhs_len: u32,
+    rhs_ptr: u32,
+    rhs_len: u32,
+) -> u64 {
+    write_result(call_collation(
+        callback_id,
+        unroll_vec(lhs_ptr, lhs_len),
+        unroll_vec(rhs_ptr, rhs_len),
+    ))
+}
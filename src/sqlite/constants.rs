@@ -1,3 +1,4 @@
+pub(crate) const SQLITE_OK: u32 = 0;
 pub(crate) const SQLITE_ROW: u32 = 100;
 pub(crate) const SQLITE_DONE: u32 = 101;
 pub(crate) const SQLITE_CONSTRAINT_UNIQUE: u32 = 2067;
@@ -3,15 +3,19 @@
 use diesel::backend::SqlDialect;
 
 use diesel::backend::*;
+#[cfg(feature = "serde_json")]
+use diesel::sql_types::Json;
 use diesel::sql_types::{
     BigInt, Binary, Bool, Date, Double, Float, Integer, Numeric, SmallInt, Text, Time, Timestamp,
     TypeMetadata,
 };
 
-use lunatic_sqlite_api::wire_format::SqliteValue;
-
 use super::bind_collector::SqliteBindCollector;
 use super::query_builder::SqliteQueryBuilder;
+use super::sqlite_value::SqliteValue;
+use super::types::TimestamptzSqlite;
+#[cfg(feature = "uuid")]
+use super::types::UuidSqlite;
 
 /// The SQLite backend
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Default)]
@@ -53,7 +57,7 @@ impl<'a> HasBindCollector<'a> for Sqlite {
 }
 
 impl<'a> HasRawValue<'a> for Sqlite {
-    type RawValue = &'a SqliteValue;
+    type RawValue = SqliteValue<'a, 'a, 'a>;
 }
 
 impl TypeMetadata for Sqlite {
@@ -62,14 +66,12 @@ impl TypeMetadata for Sqlite {
 }
 
 impl SqlDialect for Sqlite {
-    // #[cfg(not(feature = "returning_clauses_for_sqlite_3_35"))]
-    // type ReturningClause = sql_dialect::returning_clause::DoesNotSupportReturningClause;
-    // #[cfg(feature = "returning_clauses_for_sqlite_3_35")]
     type ReturningClause = SqliteReturningClause;
 
     type OnConflictClause = SqliteOnConflictClause;
 
-    type InsertWithDefaultKeyword = sql_dialect::default_keyword_for_insert::IsoSqlDefaultKeyword;
+    type InsertWithDefaultKeyword =
+        sql_dialect::default_keyword_for_insert::DoesNotSupportDefaultKeyword;
     type BatchInsertSupport = SqliteBatchInsert;
     type ConcatClause = sql_dialect::concat_clause::ConcatWithPipesClause;
     type DefaultValueClauseForInsert = sql_dialect::default_value_clause::AnsiDefaultValueClause;
@@ -89,6 +91,8 @@ pub struct SqliteOnConflictClause;
 
 impl sql_dialect::on_conflict_clause::SupportsOnConflictClause for SqliteOnConflictClause {}
 
+/// SQLite has no `DEFAULT` keyword, so batch inserts do not implement
+/// `QueryFragment` for this but are executed row by row where required
 #[derive(Debug, Copy, Clone)]
 pub struct SqliteBatchInsert;
 
@@ -113,9 +117,14 @@ wrap_sqlite_type!(SmallInt, SmallInt);
 wrap_sqlite_type!(Bool, Integer);
 wrap_sqlite_type!(Binary, Binary);
 wrap_sqlite_type!(Text, Text);
-wrap_sqlite_type!(Numeric, Double);
+wrap_sqlite_type!(Numeric, Text);
 wrap_sqlite_type!(Double, Double);
 wrap_sqlite_type!(Integer, Integer);
 wrap_sqlite_type!(Date, Text);
 wrap_sqlite_type!(Time, Text);
 wrap_sqlite_type!(Timestamp, Text);
+wrap_sqlite_type!(TimestamptzSqlite, Text);
+#[cfg(feature = "serde_json")]
+wrap_sqlite_type!(Json, Text);
+#[cfg(feature = "uuid")]
+wrap_sqlite_type!(UuidSqlite, Binary);
@@ -1,26 +1,37 @@
+use std::collections::HashMap;
+use std::ops::Deref;
 use std::path::Path;
+use std::rc::Rc;
 
 use diesel::{
     connection::{
         statement_cache::StatementCache, AnsiTransactionManager, ConnectionGatWorkaround,
         DefaultLoadingMode, LoadConnection, LoadRowIter, SimpleConnection, TransactionManager,
     },
+    deserialize,
     expression::QueryMetadata,
+    migration::{MigrationConnection, CREATE_MIGRATIONS_TABLE},
     query_builder::{Query, QueryFragment, QueryId},
     result::{DatabaseErrorKind, Error},
     row::{Field, PartialRow, Row, RowGatWorkaround, RowIndex},
     Connection, ConnectionError, ConnectionResult, QueryResult,
 };
-use lunatic_sqlite_api::{
-    guest_api::sqlite_guest_bindings::sqlite3_changes,
-    wire_format::{SqliteError, SqliteValue},
+#[cfg(feature = "host-callbacks")]
+use diesel::{
+    deserialize::{FromSqlRow, StaticallySizedRow},
+    serialize::ToSql,
+    sql_types::HasSqlType,
 };
+use lunatic_sqlite_api::wire_format::{SqliteError, SqliteValue};
 
+#[cfg(feature = "host-callbacks")]
+use super::{callbacks, functions, SqliteAggregateFunction};
 use super::{
     constants::*,
     diesel_backend::Sqlite,
     host_bindings,
     stmt::{Statement, StatementUse},
+    DeserializationMode, FromSqlRef, QueryableByNameRef,
 };
 
 pub(crate) struct RawConnection {
@@ -28,7 +39,7 @@ pub(crate) struct RawConnection {
 }
 
 impl RawConnection {
-    fn exec(&mut self, query: &str) -> QueryResult<()> {
+    fn exec(&self, query: &str) -> QueryResult<()> {
         match host_bindings::execute(self.connection_id, query) {
             0 => Ok(()),
             _ => Err(last_error(self.connection_id)),
@@ -44,71 +55,143 @@ impl RawConnection {
     }
 
     pub(super) fn rows_affected_by_last_query(&self) -> usize {
-        unsafe { sqlite3_changes(self.connection_id) as usize }
-    }
-
-    // TODO: in order for this to work there needs to be a proper way of sending functions to the host
-    // which could be done by sending a wasm functions name, so that the callback stored by the sqlite
-    // instance in the host will actually point to a host function which calls the provided guest function.
-    // However, this will require quite a bit of engineering and since it doesn't have a high priority at
-    // the moment we'll keep the functionality out for now.
-    //
-    // pub(super) fn register_collation_function<F>(
-    //     &self,
-    //     collation_name: &str,
-    //     collation: F,
-    // ) -> QueryResult<()>
-    // where
-    //     F: Fn(&str, &str) -> std::cmp::Ordering + std::panic::UnwindSafe + Send + 'static,
-    // {
-    //     let callback_fn = Box::into_raw(Box::new(CollationUserPtr {
-    //         callback: collation,
-    //         collation_name: collation_name.to_owned(),
-    //     }));
-    //     let collation_name = Self::get_fn_name(collation_name)?;
-
-    //     let result = unsafe {
-    //         ffi::sqlite3_create_collation_v2(
-    //             self.internal_connection.as_ptr(),
-    //             collation_name.as_ptr(),
-    //             ffi::SQLITE_UTF8,
-    //             callback_fn as *mut _,
-    //             Some(run_collation_function::<F>),
-    //             Some(destroy_boxed::<CollationUserPtr<F>>),
-    //         )
-    //     };
-
-    //     let result = Self::process_sql_function_result(result);
-    //     if result.is_err() {
-    //         destroy_boxed::<CollationUserPtr<F>>(callback_fn as *mut _);
-    //     }
-    //     result
-    // }
-}
-
-fn last_error(connection_id: u64) -> Error {
-    if let Ok(SqliteError {
-        code: Some(error_code),
-        message,
-    }) = host_bindings::last_error(connection_id)
+        host_bindings::sqlite3_changes(self.connection_id) as usize
+    }
+}
+
+#[cfg(feature = "host-callbacks")]
+impl RawConnection {
+    pub(super) fn register_sql_function<F, Ret, RetSqlType>(
+        &self,
+        fn_name: &str,
+        num_args: usize,
+        deterministic: bool,
+        mut f: F,
+    ) -> QueryResult<()>
+    where
+        F: FnMut(&Self, &[SqliteValue]) -> QueryResult<Ret>
+            + std::panic::UnwindSafe
+            + Send
+            + 'static,
+        Ret: ToSql<RetSqlType, Sqlite>,
+        Sqlite: HasSqlType<RetSqlType>,
     {
-        let error_kind = match error_code {
-            SQLITE_CONSTRAINT_UNIQUE | SQLITE_CONSTRAINT_PRIMARYKEY => {
-                DatabaseErrorKind::UniqueViolation
-            }
-            SQLITE_CONSTRAINT_FOREIGNKEY => DatabaseErrorKind::ForeignKeyViolation,
-            SQLITE_CONSTRAINT_NOTNULL => DatabaseErrorKind::NotNullViolation,
-            SQLITE_CONSTRAINT_CHECK => DatabaseErrorKind::CheckViolation,
-            _ => DatabaseErrorKind::Unknown,
-        };
-        return Error::DatabaseError(
-            error_kind,
-            Box::new(message.unwrap_or_else(|| "sqlite error".to_string())),
+        let callback_id =
+            callbacks::register_function(self.connection_id, fn_name, move |conn, args| {
+                let res = f(conn, args)?;
+                let value = functions::process_sql_function_result(&res)?;
+                Ok(value.into_bind_value())
+            });
+
+        let result = host_bindings::create_function(
+            self.connection_id,
+            fn_name,
+            num_args as u32,
+            deterministic,
+            callbacks::FUNCTION_TRAMPOLINE,
+            callback_id,
+        );
+
+        let result = self.process_sql_function_result(result);
+        if result.is_err() {
+            callbacks::unregister(callback_id);
+        }
+        result
+    }
+
+    pub(super) fn register_aggregate_function<ArgsSqlType, RetSqlType, Args, Ret, A>(
+        &self,
+        fn_name: &str,
+        num_args: usize,
+    ) -> QueryResult<()>
+    where
+        A: SqliteAggregateFunction<Args, Output = Ret> + 'static + Send + std::panic::UnwindSafe,
+        Args: FromSqlRow<ArgsSqlType, Sqlite> + 'static,
+        Ret: ToSql<RetSqlType, Sqlite>,
+        Sqlite: HasSqlType<RetSqlType>,
+        ArgsSqlType: 'static,
+        RetSqlType: 'static,
+    {
+        let callback_id = callbacks::register_aggregate(
+            self.connection_id,
+            fn_name,
+            functions::AggregatorStates::<ArgsSqlType, RetSqlType, Args, A>::new(),
+        );
+
+        let result = host_bindings::create_aggregate_function(
+            self.connection_id,
+            fn_name,
+            num_args as u32,
+            callbacks::AGGREGATE_STEP_TRAMPOLINE,
+            callbacks::AGGREGATE_FINAL_TRAMPOLINE,
+            callback_id,
         );
+
+        let result = self.process_sql_function_result(result);
+        if result.is_err() {
+            callbacks::unregister(callback_id);
+        }
+        result
+    }
+
+    pub(super) fn register_collation_function<F>(
+        &self,
+        collation_name: &str,
+        collation: F,
+    ) -> QueryResult<()>
+    where
+        F: Fn(&str, &str) -> std::cmp::Ordering + std::panic::UnwindSafe + Send + 'static,
+    {
+        let callback_id =
+            callbacks::register_collation(self.connection_id, collation_name, collation);
+
+        let result = host_bindings::create_collation(
+            self.connection_id,
+            collation_name,
+            callbacks::COLLATION_TRAMPOLINE,
+            callback_id,
+        );
+
+        let result = self.process_sql_function_result(result);
+        if result.is_err() {
+            callbacks::unregister(callback_id);
+        }
+        result
+    }
+
+    fn process_sql_function_result(&self, result: u32) -> QueryResult<()> {
+        if result == SQLITE_OK {
+            Ok(())
+        } else {
+            Err(last_error(self.connection_id))
+        }
+    }
+}
+
+#[cfg(feature = "host-callbacks")]
+impl Drop for RawConnection {
+    fn drop(&mut self) {
+        callbacks::unregister_connection(self.connection_id);
     }
+}
+
+pub(super) fn last_error(connection_id: u64) -> Error {
+    let SqliteError { code, message } = match host_bindings::last_error(connection_id) {
+        Ok(error) => error,
+        Err(e) => return e,
+    };
+    let error_kind = match code {
+        Some(SQLITE_CONSTRAINT_UNIQUE | SQLITE_CONSTRAINT_PRIMARYKEY) => {
+            DatabaseErrorKind::UniqueViolation
+        }
+        Some(SQLITE_CONSTRAINT_FOREIGNKEY) => DatabaseErrorKind::ForeignKeyViolation,
+        Some(SQLITE_CONSTRAINT_NOTNULL) => DatabaseErrorKind::NotNullViolation,
+        Some(SQLITE_CONSTRAINT_CHECK) => DatabaseErrorKind::CheckViolation,
+        _ => DatabaseErrorKind::Unknown,
+    };
     Error::DatabaseError(
-        DatabaseErrorKind::Unknown,
-        Box::new("unknown error code".to_string()),
+        error_kind,
+        Box::new(message.unwrap_or_else(|| "sqlite error".to_string())),
     )
 }
 
@@ -203,6 +286,7 @@ pub struct SqliteConnection {
     statement_cache: diesel::connection::statement_cache::StatementCache<Sqlite, Statement>,
     raw_connection: RawConnection,
     transaction_state: AnsiTransactionManager,
+    deserialization_mode: DeserializationMode,
 }
 
 // This relies on the invariant that RawConnection or Statement are never
@@ -212,8 +296,88 @@ unsafe impl Send for SqliteConnection {}
 
 impl SimpleConnection for SqliteConnection {
     fn batch_execute(&mut self, query: &str) -> QueryResult<()> {
-        self.raw_connection.exec(query)
+        match expand_diesel_manage_updated_at(query) {
+            Some(query) => self.raw_connection.exec(&query),
+            None => self.raw_connection.exec(query),
+        }
+    }
+}
+
+const DIESEL_MANAGE_UPDATED_AT: &str = "diesel_manage_updated_at";
+
+/// Replaces every `SELECT diesel_manage_updated_at('table')` statement of
+/// `query` by the trigger that function installs with upstream diesel
+///
+/// Registering SQL functions needs the `host-callbacks` feature, so migrations
+/// calling it are expanded on the guest instead. Returns `None` if `query`
+/// doesn't call it.
+fn expand_diesel_manage_updated_at(query: &str) -> Option<String> {
+    if !query.contains(DIESEL_MANAGE_UPDATED_AT) {
+        return None;
+    }
+
+    let mut expanded = String::with_capacity(query.len());
+    let mut rest = query;
+    let mut found = false;
+    let mut at_word_start = true;
+    while !rest.is_empty() {
+        if let Some((table_name, remaining)) = at_word_start
+            .then(|| parse_diesel_manage_updated_at(rest))
+            .flatten()
+        {
+            expanded.push_str(&format!(
+                include_str!("diesel_manage_updated_at.sql"),
+                table_name = table_name
+            ));
+            rest = remaining;
+            found = true;
+            continue;
+        }
+        // string literals and quoted identifiers are copied as they are
+        let len = match rest.as_bytes()[0] {
+            quote @ (b'\'' | b'"') => rest[1..]
+                .find(quote as char)
+                .map_or(rest.len(), |end| end + 2),
+            _ => rest.chars().next().map_or(1, char::len_utf8),
+        };
+        expanded.push_str(&rest[..len]);
+        at_word_start = !rest[..len].ends_with(|c: char| c.is_alphanumeric() || c == '_');
+        rest = &rest[len..];
+    }
+    found.then_some(expanded)
+}
+
+/// Parses `SELECT diesel_manage_updated_at('table')` at the start of `sql`,
+/// returning the table name and the remaining SQL
+fn parse_diesel_manage_updated_at(sql: &str) -> Option<(String, &str)> {
+    fn keyword<'a>(sql: &'a str, keyword: &str) -> Option<&'a str> {
+        let prefix = sql.get(..keyword.len())?;
+        prefix
+            .eq_ignore_ascii_case(keyword)
+            .then(|| &sql[keyword.len()..])
     }
+
+    let sql = keyword(sql, "SELECT")?;
+    if !sql.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let sql = keyword(sql.trim_start(), DIESEL_MANAGE_UPDATED_AT)?;
+    let sql = sql.trim_start().strip_prefix('(')?;
+    let sql = sql.trim_start().strip_prefix('\'')?;
+    let mut table_name = String::new();
+    let mut chars = sql.char_indices();
+    let sql = loop {
+        match chars.next()? {
+            (idx, '\'') if sql[idx + 1..].starts_with('\'') => {
+                table_name.push('\'');
+                chars.next();
+            }
+            (idx, '\'') => break &sql[idx + 1..],
+            (_, c) => table_name.push(c),
+        }
+    };
+    let sql = sql.trim_start().strip_prefix(')')?;
+    Some((table_name, sql))
 }
 
 impl<'conn, 'query> ConnectionGatWorkaround<'conn, 'query, Sqlite> for SqliteConnection {
@@ -232,16 +396,13 @@ impl Connection for SqliteConnection {
     /// If the database does not exist, this method will try to
     /// create a new database and then establish a connection to it.
     fn establish(database_url: &str) -> ConnectionResult<Self> {
-        // use diesel::result::ConnectionError::CouldntSetupConfiguration;
-
         let raw_connection = RawConnection::establish(database_url)?;
         let conn = Self {
             statement_cache: StatementCache::new(),
             raw_connection,
             transaction_state: AnsiTransactionManager::default(),
+            deserialization_mode: DeserializationMode::default(),
         };
-        // conn.register_diesel_sql_functions()
-        //     .map_err(diesel::ConnectionError::CouldntSetupConfiguration)?;
         Ok(conn)
     }
 
@@ -272,40 +433,80 @@ impl LoadConnection<DefaultLoadingMode> for SqliteConnection {
         T: Query + QueryFragment<Self::Backend> + QueryId + 'query,
         Self::Backend: QueryMetadata<T::SqlType>,
     {
+        let deserialization_mode = self.deserialization_mode;
         let statement_use = self.prepared_query(source)?;
 
-        Ok(StatementIterator::new(statement_use))
-        // Ok(StatementIterator {})
+        Ok(StatementIterator::new(statement_use, deserialization_mode))
+    }
+}
+
+// backs diesel_migrations' `MigrationHarness`, which is implemented for every
+// connection that can set up `__diesel_schema_migrations`
+impl MigrationConnection for SqliteConnection {
+    fn setup(&mut self) -> QueryResult<usize> {
+        use diesel::RunQueryDsl;
+        diesel::sql_query(CREATE_MIGRATIONS_TABLE).execute(self)
     }
 }
 
 pub struct StatementIterator<'stmt, 'query> {
     is_first: bool,
     statement_use: StatementUse<'stmt, 'query>,
+    deserialization_mode: DeserializationMode,
 }
 
 impl<'stmt, 'query> StatementIterator<'stmt, 'query> {
-    pub fn new(statement_use: StatementUse<'stmt, 'query>) -> Self {
+    pub fn new(
+        statement_use: StatementUse<'stmt, 'query>,
+        deserialization_mode: DeserializationMode,
+    ) -> Self {
         Self {
             is_first: true,
             statement_use,
+            deserialization_mode,
         }
     }
 }
 
 pub struct SqliteRow {
-    pub inner_row: lunatic_sqlite_api::wire_format::SqliteRow,
-    pub statement_id: u64,
-    pub field_names: Vec<String>,
+    pub(crate) inner_row: lunatic_sqlite_api::wire_format::SqliteRow,
+    pub(crate) field_names: Rc<ColumnNames>,
+    pub(crate) deserialization_mode: DeserializationMode,
+}
+
+/// The column names of a result set, shared by all of its rows
+#[derive(Debug)]
+pub(crate) struct ColumnNames {
+    names: Box<[String]>,
+    indices: HashMap<String, usize>,
+}
+
+impl ColumnNames {
+    pub(super) fn new(names: Vec<String>) -> Self {
+        let mut indices = HashMap::with_capacity(names.len());
+        for (idx, name) in names.iter().enumerate() {
+            // like SQLite itself, resolve duplicated names to the first column
+            indices.entry(name.clone()).or_insert(idx);
+        }
+        Self {
+            names: names.into_boxed_slice(),
+            indices,
+        }
+    }
+
+    /// Returns the index of the first column called `name`
+    pub(crate) fn index_of(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
 }
 
-// impl Deref for SqliteRow {
-//     type Target = lunatic_sqlite_api::SqliteRow;
+impl Deref for ColumnNames {
+    type Target = [String];
 
-//     fn deref(&self) -> &Self::Target {
-//         &self.0
-//     }
-// }
+    fn deref(&self) -> &[String] {
+        &self.names
+    }
+}
 
 impl<'field> RowGatWorkaround<'field, Sqlite> for SqliteRow {
     type Field = SqliteField<'field>;
@@ -327,19 +528,15 @@ impl<'stmt> Row<'stmt, Sqlite> for SqliteRow {
         Self: RowIndex<I>,
     {
         if let Some(column_index) = self.idx(idx) {
-            if let Some(original_column) = self.inner_row.get_column(column_index as i32) {
+            if let Some(original_column) = self.inner_row.0.get(column_index) {
                 return Some(SqliteField {
                     inner_field: original_column,
-                    field_name: self.field_names.get(column_index).cloned(),
+                    field_name: self.field_names.get(column_index).map(String::as_str),
+                    deserialization_mode: self.deserialization_mode,
                 });
             }
         }
         None
-        // let idx = self.idx(idx)?;
-        // Some(SqliteField {
-        //     row: self.inner.borrow(),
-        //     col_idx: i32::try_from(idx).ok()?,
-        // })
     }
 
     fn partial_row(&self, range: std::ops::Range<usize>) -> PartialRow<'_, Self::InnerPartialRow> {
@@ -347,6 +544,36 @@ impl<'stmt> Row<'stmt, Sqlite> for SqliteRow {
     }
 }
 
+impl SqliteRow {
+    /// Deserializes the value of a column without copying it out of the row
+    ///
+    /// `idx` is either the index or the name of the column. Unlike with
+    /// `Row::get` and `NamedRow::get`, the returned value can borrow from the
+    /// row, see [`FromSqlRef`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// use diesel::sql_types::Text;
+    ///
+    /// for row in connection.load(diesel::sql_query("SELECT body FROM posts"))? {
+    ///     let row = row?;
+    ///     let body: &str = row.get_ref::<Text, _, _>("body")?;
+    ///     scan(body);
+    /// }
+    /// ```
+    pub fn get_ref<'a, ST, T, I>(&'a self, idx: I) -> deserialize::Result<T>
+    where
+        T: FromSqlRef<'a, ST>,
+        Self: RowIndex<I>,
+        I: std::fmt::Display + Copy,
+    {
+        let field = Row::get(self, idx)
+            .ok_or_else(|| format!("Column `{idx}` was not present in query"))?;
+        T::from_nullable_sql_ref(field.sqlite_value())
+    }
+}
+
 impl RowIndex<usize> for SqliteRow {
     fn idx(&self, idx: usize) -> Option<usize> {
         if idx < self.field_count() {
@@ -359,22 +586,20 @@ impl RowIndex<usize> for SqliteRow {
 
 impl<'idx> RowIndex<&'idx str> for SqliteRow {
     fn idx(&self, field_name: &'idx str) -> Option<usize> {
-        self.field_names.iter().position(|s| s == field_name)
+        self.field_names.index_of(field_name)
     }
 }
 
 #[allow(missing_debug_implementations)]
 pub struct SqliteField<'a> {
     pub(super) inner_field: &'a SqliteValue,
-    pub(super) field_name: Option<String>,
+    pub(super) field_name: Option<&'a str>,
+    pub(super) deserialization_mode: DeserializationMode,
 }
 
 impl<'stmt> Field<'stmt, Sqlite> for SqliteField<'stmt> {
     fn field_name(&self) -> Option<&str> {
-        if let Some(name) = &self.field_name {
-            return Some(name.as_str());
-        }
-        None
+        self.field_name
     }
 
     fn is_null(&self) -> bool {
@@ -382,7 +607,22 @@ impl<'stmt> Field<'stmt, Sqlite> for SqliteField<'stmt> {
     }
 
     fn value(&self) -> Option<diesel::backend::RawValue<'_, Sqlite>> {
-        Some(self.inner_field)
+        self.sqlite_value()
+    }
+}
+
+impl<'a> SqliteField<'a> {
+    /// The value of the field for the whole lifetime of the row
+    fn sqlite_value(&self) -> Option<super::SqliteValue<'a, 'a, 'a>> {
+        // NULL is a storage class of its own, diesel expects it as a missing value
+        match self.inner_field {
+            SqliteValue::Null => None,
+            value => Some(super::SqliteValue::new(
+                value,
+                self.field_name,
+                self.deserialization_mode,
+            )),
+        }
     }
 }
 
@@ -397,32 +637,103 @@ impl<'stmt, 'query> Iterator for StatementIterator<'stmt, 'query> {
             Ok(false) => None,
             Ok(true) => {
                 let statement_id = self.statement_use.statement.statement.statement_id;
-                Some(
+                let deserialization_mode = self.deserialization_mode;
+                let row = self.statement_use.column_names().and_then(|field_names| {
                     host_bindings::read_row(statement_id).map(|inner_row| SqliteRow {
                         inner_row,
-                        statement_id,
-                        field_names: host_bindings::column_names(statement_id).unwrap(),
-                    }),
-                )
+                        field_names,
+                        deserialization_mode,
+                    })
+                });
+                Some(row)
             }
         }
     }
 }
 
-#[cfg(feature = "r2d2")]
-impl crate::r2d2::R2D2Connection for crate::sqlite::SqliteConnection {
-    fn ping(&mut self) -> QueryResult<()> {
-        use crate::RunQueryDsl;
-
-        crate::r2d2::CheckConnectionQuery.execute(self).map(|_| ())
+impl SqliteConnection {
+    /// Checks that the connection still answers queries
+    ///
+    /// Used by the [connection pool](super::pool) as health check.
+    pub fn ping(&mut self) -> QueryResult<()> {
+        self.batch_execute("SELECT 1")
     }
 
-    fn is_broken(&mut self) -> bool {
+    /// Whether a failed transaction left the connection in an unusable state
+    pub fn is_broken(&mut self) -> bool {
         AnsiTransactionManager::is_broken_transaction_manager(self)
     }
-}
 
-impl SqliteConnection {
+    /// Sets how the values of loaded rows are converted into Rust types
+    ///
+    /// Connections start out in [`DeserializationMode::Tolerant`]. Arguments
+    /// of custom SQL functions are always converted tolerantly.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # include!("../../doctest_setup.rs");
+    /// #
+    /// # fn main() {
+    /// #     run_test().unwrap();
+    /// # }
+    /// #
+    /// # fn run_test() -> QueryResult<()> {
+    /// #     use diesel::dsl::sql;
+    /// #     use diesel::sql_types::Integer;
+    /// #     let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// use lunatic_diesel::sqlite::DeserializationMode;
+    ///
+    /// conn.set_deserialization_mode(DeserializationMode::Strict);
+    /// // text is not read as an integer anymore
+    /// assert!(diesel::select(sql::<Integer>("'42'"))
+    ///     .get_result::<i32>(&mut conn)
+    ///     .is_err());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn set_deserialization_mode(&mut self, mode: DeserializationMode) {
+        self.deserialization_mode = mode;
+    }
+
+    /// The mode set by [`SqliteConnection::set_deserialization_mode`]
+    pub fn deserialization_mode(&self) -> DeserializationMode {
+        self.deserialization_mode
+    }
+
+    /// Loads the rows of `query` into `rows` and builds a `T` from each of them
+    ///
+    /// The text and blobs of the returned values borrow from `rows`, see
+    /// [`QueryableByNameRef`]. `rows` is cleared first, so that it can be
+    /// reused for the next query.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let mut rows = Vec::new();
+    /// let posts: Vec<PostRef<'_>> = connection
+    ///     .load_by_name_ref(diesel::sql_query("SELECT id, title FROM posts"), &mut rows)?;
+    /// ```
+    pub fn load_by_name_ref<'a, Q, T>(
+        &mut self,
+        query: Q,
+        rows: &'a mut Vec<SqliteRow>,
+    ) -> QueryResult<Vec<T>>
+    where
+        Q: Query + QueryFragment<Sqlite> + QueryId,
+        Sqlite: QueryMetadata<Q::SqlType>,
+        T: QueryableByNameRef<'a>,
+    {
+        rows.clear();
+        for row in LoadConnection::load(self, query)? {
+            rows.push(row?);
+        }
+        let rows: &'a Vec<SqliteRow> = rows;
+        rows.iter()
+            .map(|row| T::build(row).map_err(Error::DeserializationError))
+            .collect()
+    }
+
     /// Run a transaction with `BEGIN IMMEDIATE`
     ///
     /// This method will return an error if a transaction is already open.
@@ -512,124 +823,188 @@ impl SqliteConnection {
         StatementUse::bind(statement, source)
     }
 
-    // #[doc(hidden)]
-    // pub fn register_sql_function<ArgsSqlType, RetSqlType, Args, Ret, F>(
-    //     &mut self,
-    //     fn_name: &str,
-    //     deterministic: bool,
-    //     mut f: F,
-    // ) -> QueryResult<()>
-    // where
-    //     F: FnMut(Args) -> Ret + std::panic::UnwindSafe + Send + 'static,
-    //     Args: FromSqlRow<ArgsSqlType, Sqlite> + StaticallySizedRow<ArgsSqlType, Sqlite>,
-    //     Ret: ToSql<RetSqlType, Sqlite>,
-    //     Sqlite: HasSqlType<RetSqlType>,
-    // {
-    //     functions::register(
-    //         &self.raw_connection,
-    //         fn_name,
-    //         deterministic,
-    //         move |_, args| f(args),
-    //     )
-    // }
-
-    // #[doc(hidden)]
-    // pub fn register_noarg_sql_function<RetSqlType, Ret, F>(
-    //     &self,
-    //     fn_name: &str,
-    //     deterministic: bool,
-    //     f: F,
-    // ) -> QueryResult<()>
-    // where
-    //     F: FnMut() -> Ret + std::panic::UnwindSafe + Send + 'static,
-    //     Ret: ToSql<RetSqlType, Sqlite>,
-    //     Sqlite: HasSqlType<RetSqlType>,
-    // {
-    //     functions::register_noargs(&self.raw_connection, fn_name, deterministic, f)
-    // }
-
-    // #[doc(hidden)]
-    // pub fn register_aggregate_function<ArgsSqlType, RetSqlType, Args, Ret, A>(
-    //     &mut self,
-    //     fn_name: &str,
-    // ) -> QueryResult<()>
-    // where
-    //     A: SqliteAggregateFunction<Args, Output = Ret> + 'static + Send + std::panic::UnwindSafe,
-    //     Args: FromSqlRow<ArgsSqlType, Sqlite> + StaticallySizedRow<ArgsSqlType, Sqlite>,
-    //     Ret: ToSql<RetSqlType, Sqlite>,
-    //     Sqlite: HasSqlType<RetSqlType>,
-    // {
-    //     functions::register_aggregate::<_, _, _, _, A>(&self.raw_connection, fn_name)
-    // }
-
-    // /// Register a collation function.
-    // ///
-    // /// `collation` must always return the same answer given the same inputs.
-    // /// If `collation` panics and unwinds the stack, the process is aborted, since it is used
-    // /// across a C FFI boundary, which cannot be unwound across and there is no way to
-    // /// signal failures via the SQLite interface in this case..
-    // ///
-    // /// If the name is already registered it will be overwritten.
-    // ///
-    // /// This method will return an error if registering the function fails, either due to an
-    // /// out-of-memory situation or because a collation with that name already exists and is
-    // /// currently being used in parallel by a query.
-    // ///
-    // /// The collation needs to be specified when creating a table:
-    // /// `CREATE TABLE my_table ( str TEXT COLLATE MY_COLLATION )`,
-    // /// where `MY_COLLATION` corresponds to name passed as `collation_name`.
-    // ///
-    // /// # Example
-    // ///
-    // /// ```rust
-    // /// # include!("../../doctest_setup.rs");
-    // /// #
-    // /// # fn main() {
-    // /// #     run_test().unwrap();
-    // /// # }
-    // /// #
-    // /// # fn run_test() -> QueryResult<()> {
-    // /// #     let mut conn = SqliteConnection::establish(":memory:").unwrap();
-    // /// // sqlite NOCASE only works for ASCII characters,
-    // /// // this collation allows handling UTF-8 (barring locale differences)
-    // /// conn.register_collation("RUSTNOCASE", |rhs, lhs| {
-    // ///     rhs.to_lowercase().cmp(&lhs.to_lowercase())
-    // /// })
-    // /// # }
-    // /// ```
-    // pub fn register_collation<F>(&mut self, collation_name: &str, collation: F) -> QueryResult<()>
-    // where
-    //     F: Fn(&str, &str) -> std::cmp::Ordering + Send + 'static + std::panic::UnwindSafe,
-    // {
-    //     self.raw_connection
-    //         .register_collation_function(collation_name, collation)
-    // }
-
-    // fn register_diesel_sql_functions(&self) -> QueryResult<()> {
-    //     use diesel::sql_types::{Integer, Text};
-
-    //     functions::register::<Text, Integer, _, _, _>(
-    //         &self.raw_connection,
-    //         "diesel_manage_updated_at",
-    //         false,
-    //         |conn, table_name: String| {
-    //             conn.exec(&format!(
-    //                 include_str!("diesel_manage_updated_at.sql"),
-    //                 table_name = table_name
-    //             ))
-    //             .expect("Failed to create trigger");
-    //             0 // have to return *something*
-    //         },
-    //     )
-    // }
+    /// Runs a query built by another process, see [`DbServer`](super::server::DbServer)
+    #[cfg(target_arch = "wasm32")]
+    pub(super) fn execute_serialized(
+        &mut self,
+        sql: &str,
+        binds: &lunatic_sqlite_api::wire_format::BindList,
+        is_safe_to_cache_prepared: bool,
+    ) -> QueryResult<usize> {
+        let mut statement_use = self.prepared_query(SerializedSql {
+            sql,
+            is_safe_to_cache_prepared,
+        })?;
+        statement_use.bind_list(binds)?;
+        statement_use.run()?;
+        Ok(self.raw_connection.rows_affected_by_last_query())
+    }
+
+    /// Loads the rows of a query built by another process, together with
+    /// the names of their columns
+    #[cfg(target_arch = "wasm32")]
+    pub(super) fn load_serialized(
+        &mut self,
+        sql: &str,
+        binds: &lunatic_sqlite_api::wire_format::BindList,
+        is_safe_to_cache_prepared: bool,
+    ) -> QueryResult<(Vec<String>, Vec<lunatic_sqlite_api::wire_format::SqliteRow>)> {
+        let mut statement_use = self.prepared_query(SerializedSql {
+            sql,
+            is_safe_to_cache_prepared,
+        })?;
+        statement_use.bind_list(binds)?;
+        let mut column_names = Vec::new();
+        let mut rows = Vec::new();
+        // `step` has to be told whether it runs the statement for the first
+        // time, the column names are read once the first row is there
+        let mut first_step = true;
+        while unsafe { statement_use.step(first_step)? } {
+            if first_step {
+                column_names = statement_use.column_names()?.to_vec();
+                first_step = false;
+            }
+            let statement_id = statement_use.statement.statement.statement_id;
+            rows.push(host_bindings::read_row(statement_id)?);
+        }
+        Ok((column_names, rows))
+    }
+}
+
+#[cfg(feature = "host-callbacks")]
+impl SqliteConnection {
+    #[doc(hidden)]
+    pub fn register_sql_function<ArgsSqlType, RetSqlType, Args, Ret, F>(
+        &mut self,
+        fn_name: &str,
+        deterministic: bool,
+        mut f: F,
+    ) -> QueryResult<()>
+    where
+        F: FnMut(Args) -> Ret + std::panic::UnwindSafe + Send + 'static,
+        Args: FromSqlRow<ArgsSqlType, Sqlite> + StaticallySizedRow<ArgsSqlType, Sqlite>,
+        Ret: ToSql<RetSqlType, Sqlite>,
+        Sqlite: HasSqlType<RetSqlType>,
+    {
+        functions::register(
+            &self.raw_connection,
+            fn_name,
+            deterministic,
+            move |_, args| f(args),
+        )
+    }
+
+    #[doc(hidden)]
+    pub fn register_noarg_sql_function<RetSqlType, Ret, F>(
+        &mut self,
+        fn_name: &str,
+        deterministic: bool,
+        f: F,
+    ) -> QueryResult<()>
+    where
+        F: FnMut() -> Ret + std::panic::UnwindSafe + Send + 'static,
+        Ret: ToSql<RetSqlType, Sqlite>,
+        Sqlite: HasSqlType<RetSqlType>,
+    {
+        functions::register_noargs(&self.raw_connection, fn_name, deterministic, f)
+    }
+
+    #[doc(hidden)]
+    pub fn register_aggregate_function<ArgsSqlType, RetSqlType, Args, Ret, A>(
+        &mut self,
+        fn_name: &str,
+    ) -> QueryResult<()>
+    where
+        A: SqliteAggregateFunction<Args, Output = Ret> + 'static + Send + std::panic::UnwindSafe,
+        Args: FromSqlRow<ArgsSqlType, Sqlite> + StaticallySizedRow<ArgsSqlType, Sqlite> + 'static,
+        Ret: ToSql<RetSqlType, Sqlite>,
+        Sqlite: HasSqlType<RetSqlType>,
+        ArgsSqlType: 'static,
+        RetSqlType: 'static,
+    {
+        functions::register_aggregate::<_, _, _, _, A>(&self.raw_connection, fn_name)
+    }
+
+    /// Register a collation function.
+    ///
+    /// `collation` must always return the same answer given the same inputs.
+    /// If `collation` panics, the panic is caught in the guest and the statement
+    /// that used the collation fails with an error instead.
+    ///
+    /// If the name is already registered it will be overwritten.
+    ///
+    /// This method will return an error if registering the function fails, either due to an
+    /// out-of-memory situation or because a collation with that name already exists and is
+    /// currently being used in parallel by a query.
+    ///
+    /// The collation needs to be specified when creating a table:
+    /// `CREATE TABLE my_table ( str TEXT COLLATE MY_COLLATION )`,
+    /// where `MY_COLLATION` corresponds to name passed as `collation_name`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # include!("../../doctest_setup.rs");
+    /// #
+    /// # fn main() {
+    /// #     run_test().unwrap();
+    /// # }
+    /// #
+    /// # fn run_test() -> QueryResult<()> {
+    /// #     let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    /// // sqlite NOCASE only works for ASCII characters,
+    /// // this collation allows handling UTF-8 (barring locale differences)
+    /// conn.register_collation("RUSTNOCASE", |rhs, lhs| {
+    ///     rhs.to_lowercase().cmp(&lhs.to_lowercase())
+    /// })
+    /// # }
+    /// ```
+    pub fn register_collation<F>(&mut self, collation_name: &str, collation: F) -> QueryResult<()>
+    where
+        F: Fn(&str, &str) -> std::cmp::Ordering + Send + 'static + std::panic::UnwindSafe,
+    {
+        self.raw_connection
+            .register_collation_function(collation_name, collation)
+    }
+}
+
+/// The SQL of a query built by another process, whose binds are bound
+/// separately
+#[cfg(target_arch = "wasm32")]
+struct SerializedSql<'a> {
+    sql: &'a str,
+    is_safe_to_cache_prepared: bool,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl QueryFragment<Sqlite> for SerializedSql<'_> {
+    fn walk_ast<'b>(
+        &'b self,
+        mut pass: diesel::query_builder::AstPass<'_, 'b, Sqlite>,
+    ) -> QueryResult<()> {
+        if !self.is_safe_to_cache_prepared {
+            pass.unsafe_to_cache_prepared();
+        }
+        pass.push_sql(self.sql);
+        Ok(())
+    }
+}
+
+// statements are cached by their SQL, if the query they were built from
+// could be cached
+#[cfg(target_arch = "wasm32")]
+impl QueryId for SerializedSql<'_> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::prelude::*;
     use diesel::dsl::sql;
-    use diesel::prelude::*;
-    use diesel::sql_types::Integer;
+    use diesel::sql_types::{Integer, Nullable};
     use lunatic::test;
 
     #[test]
@@ -681,293 +1056,2980 @@ mod tests {
         assert_eq!(1, connection.statement_cache.len());
     }
 
-    // use diesel::sql_types::Text;
-    // sql_function!(fn fun_case(x: Text) -> Text);
+    use diesel::sql_types::Text;
+    #[test]
+    fn diesel_manage_updated_at_installs_a_trigger() {
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        connection
+            .batch_execute(
+                "CREATE TABLE notes (
+                    id INTEGER PRIMARY KEY,
+                    body TEXT NOT NULL,
+                    updated_at TIMESTAMP NOT NULL DEFAULT '2000-01-01 00:00:00'
+                );
+                SELECT diesel_manage_updated_at('notes');
+                INSERT INTO notes (id, body) VALUES (1, 'first'), (2, 'second');",
+            )
+            .unwrap();
+        connection
+            .batch_execute(
+                "UPDATE notes SET body = 'edited' WHERE id = 1;
+                UPDATE notes SET body = 'set', updated_at = '2001-01-01 00:00:00' WHERE id = 2;",
+            )
+            .unwrap();
+
+        let updated_at = diesel::sql_query("SELECT updated_at FROM notes ORDER BY id")
+            .load::<UpdatedAt>(connection)
+            .unwrap();
+        assert_ne!("2000-01-01 00:00:00", updated_at[0].updated_at);
+        assert_eq!("2001-01-01 00:00:00", updated_at[1].updated_at);
+    }
+
+    #[derive(QueryableByName)]
+    struct UpdatedAt {
+        #[diesel(sql_type = Text)]
+        updated_at: String,
+    }
+
+    #[test]
+    fn diesel_manage_updated_at_is_only_expanded_as_a_statement() {
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        connection
+            .batch_execute(
+                "CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT NOT NULL);
+                INSERT INTO notes (id, body) VALUES (1, 'SELECT diesel_manage_updated_at(''notes'')');
+                select  Diesel_Manage_Updated_At ( 'notes' ) ;",
+            )
+            .unwrap();
+
+        let body = diesel::sql_query("SELECT body AS updated_at FROM notes")
+            .get_result::<UpdatedAt>(connection)
+            .unwrap();
+        assert_eq!("SELECT diesel_manage_updated_at('notes')", body.updated_at);
+        let triggers = diesel::select(sql::<Integer>(
+            "(SELECT COUNT(*) FROM sqlite_master WHERE name = '__diesel_manage_updated_at_notes')",
+        ))
+        .get_result::<i32>(connection);
+        assert_eq!(Ok(1), triggers);
+    }
+
+    #[test]
+    fn diesel_manage_updated_at_fails_for_missing_tables() {
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        let result = connection.batch_execute("SELECT diesel_manage_updated_at('missing')");
+        assert!(result.is_err());
+    }
+
+    /// SQL functions and collations, which are backed by guest callbacks
+    #[cfg(feature = "host-callbacks")]
+    mod functions {
+        use super::*;
+        use lunatic::test;
+
+        sql_function!(fn fun_case(x: Text) -> Text);
+
+        #[test]
+        fn register_custom_function() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            fun_case::register_impl(connection, |x: String| {
+                x.chars()
+                    .enumerate()
+                    .map(|(i, c)| {
+                        if i % 2 == 0 {
+                            c.to_lowercase().to_string()
+                        } else {
+                            c.to_uppercase().to_string()
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .unwrap();
+
+            let mapped_string = diesel::select(fun_case("foobar"))
+                .get_result::<String>(connection)
+                .unwrap();
+            assert_eq!("fOoBaR", mapped_string);
+        }
+
+        sql_function!(fn my_add(x: Integer, y: Integer) -> Integer);
 
-    // #[test]
-    // fn register_custom_function() {
-    //     let connection = &mut SqliteConnection::establish(":memory:").unwrap();
-    //     fun_case::register_impl(connection, |x: String| {
-    //         x.chars()
-    //             .enumerate()
-    //             .map(|(i, c)| {
-    //                 if i % 2 == 0 {
-    //                     c.to_lowercase().to_string()
-    //                 } else {
-    //                     c.to_uppercase().to_string()
-    //                 }
-    //             })
-    //             .collect::<String>()
-    //     })
-    //     .unwrap();
+        #[test]
+        fn register_multiarg_function() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            my_add::register_impl(connection, |x: i32, y: i32| x + y).unwrap();
 
-    //     let mapped_string = diesel::select(fun_case("foobar"))
-    //         .get_result::<String>(connection)
-    //         .unwrap();
-    //     assert_eq!("fOoBaR", mapped_string);
-    // }
+            let added = diesel::select(my_add(1, 2)).get_result::<i32>(connection);
+            assert_eq!(Ok(3), added);
+        }
 
-    // sql_function!(fn my_add(x: Integer, y: Integer) -> Integer);
+        sql_function!(fn answer() -> Integer);
 
-    // #[test]
-    // fn register_multiarg_function() {
-    //     let connection = &mut SqliteConnection::establish(":memory:").unwrap();
-    //     my_add::register_impl(connection, |x: i32, y: i32| x + y).unwrap();
+        #[test]
+        fn register_noarg_function() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            answer::register_impl(connection, || 42).unwrap();
 
-    //     let added = diesel::select(my_add(1, 2)).get_result::<i32>(connection);
-    //     assert_eq!(Ok(3), added);
-    // }
+            let answer = diesel::select(answer()).get_result::<i32>(connection);
+            assert_eq!(Ok(42), answer);
+        }
 
-    // sql_function!(fn answer() -> Integer);
+        #[test]
+        fn register_nondeterministic_noarg_function() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            answer::register_nondeterministic_impl(connection, || 42).unwrap();
 
-    // #[test]
-    // fn register_noarg_function() {
-    //     let connection = &mut SqliteConnection::establish(":memory:").unwrap();
-    //     answer::register_impl(connection, || 42).unwrap();
+            let answer = diesel::select(answer()).get_result::<i32>(connection);
+            assert_eq!(Ok(42), answer);
+        }
 
-    //     let answer = diesel::select(answer()).get_result::<i32>(connection);
-    //     assert_eq!(Ok(42), answer);
-    // }
+        sql_function!(fn add_counter(x: Integer) -> Integer);
+
+        #[test]
+        fn register_nondeterministic_function() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let mut y = 0;
+            add_counter::register_nondeterministic_impl(connection, move |x: i32| {
+                y += 1;
+                x + y
+            })
+            .unwrap();
+
+            let added = diesel::select((add_counter(1), add_counter(1), add_counter(1)))
+                .get_result::<(i32, i32, i32)>(connection);
+            assert_eq!(Ok((2, 3, 4)), added);
+        }
 
-    // #[test]
-    // fn register_nondeterministic_noarg_function() {
-    //     let connection = &mut SqliteConnection::establish(":memory:").unwrap();
-    //     answer::register_nondeterministic_impl(connection, || 42).unwrap();
+        use crate::sqlite::SqliteAggregateFunction;
 
-    //     let answer = diesel::select(answer()).get_result::<i32>(connection);
-    //     assert_eq!(Ok(42), answer);
-    // }
+        sql_function! {
+            #[aggregate]
+            fn my_sum(expr: Integer) -> Integer;
+        }
 
-    // sql_function!(fn add_counter(x: Integer) -> Integer);
+        #[derive(Default)]
+        struct MySum {
+            sum: i32,
+        }
 
-    // #[test]
-    // fn register_nondeterministic_function() {
-    //     let connection = &mut SqliteConnection::establish(":memory:").unwrap();
-    //     let mut y = 0;
-    //     add_counter::register_nondeterministic_impl(connection, move |x: i32| {
-    //         y += 1;
-    //         x + y
-    //     })
-    //     .unwrap();
+        impl SqliteAggregateFunction<i32> for MySum {
+            type Output = i32;
 
-    //     let added = diesel::select((add_counter(1), add_counter(1), add_counter(1)))
-    //         .get_result::<(i32, i32, i32)>(connection);
-    //     assert_eq!(Ok((2, 3, 4)), added);
-    // }
+            fn step(&mut self, expr: i32) {
+                self.sum += expr;
+            }
 
-    use crate::sqlite::SqliteAggregateFunction;
+            fn finalize(aggregator: Option<Self>) -> Self::Output {
+                aggregator.map(|a| a.sum).unwrap_or_default()
+            }
+        }
 
-    sql_function! {
-        #[aggregate]
-        fn my_sum(expr: Integer) -> Integer;
-    }
+        table! {
+            my_sum_example {
+                id -> Integer,
+                value -> Integer,
+            }
+        }
 
-    #[derive(Default)]
-    struct MySum {
-        sum: i32,
-    }
+        #[test]
+        fn register_aggregate_function() {
+            use self::my_sum_example::dsl::*;
+
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            diesel::sql_query(
+                "CREATE TABLE my_sum_example (id integer primary key autoincrement, value integer)",
+            )
+            .execute(connection)
+            .unwrap();
+            diesel::sql_query("INSERT INTO my_sum_example (value) VALUES (1), (2), (3)")
+                .execute(connection)
+                .unwrap();
+
+            my_sum::register_impl::<MySum, _>(connection).unwrap();
+
+            let result = my_sum_example
+                .select(my_sum(value))
+                .get_result::<i32>(connection);
+            assert_eq!(Ok(6), result);
+        }
 
-    impl SqliteAggregateFunction<i32> for MySum {
-        type Output = i32;
+        #[test]
+        fn register_aggregate_function_returns_finalize_default_on_empty_set() {
+            use self::my_sum_example::dsl::*;
 
-        fn step(&mut self, expr: i32) {
-            self.sum += expr;
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            diesel::sql_query(
+                "CREATE TABLE my_sum_example (id integer primary key autoincrement, value integer)",
+            )
+            .execute(connection)
+            .unwrap();
+
+            my_sum::register_impl::<MySum, _>(connection).unwrap();
+
+            let result = my_sum_example
+                .select(my_sum(value))
+                .get_result::<i32>(connection);
+            assert_eq!(Ok(0), result);
         }
 
-        fn finalize(aggregator: Option<Self>) -> Self::Output {
-            aggregator.map(|a| a.sum).unwrap_or_default()
+        sql_function! {
+            #[aggregate]
+            fn range_max(expr1: Integer, expr2: Integer, expr3: Integer) -> Nullable<Integer>;
         }
-    }
 
-    table! {
-        my_sum_example {
-            id -> Integer,
-            value -> Integer,
-        }
-    }
-
-    // #[test]
-    // fn register_aggregate_function() {
-    //     use self::my_sum_example::dsl::*;
-
-    //     let connection = &mut SqliteConnection::establish(":memory:").unwrap();
-    //     diesel::sql_query(
-    //         "CREATE TABLE my_sum_example (id integer primary key autoincrement, value integer)",
-    //     )
-    //     .execute(connection)
-    //     .unwrap();
-    //     diesel::sql_query("INSERT INTO my_sum_example (value) VALUES (1), (2), (3)")
-    //         .execute(connection)
-    //         .unwrap();
-
-    //     my_sum::register_impl::<MySum, _>(connection).unwrap();
-
-    //     let result = my_sum_example
-    //         .select(my_sum(value))
-    //         .get_result::<i32>(connection);
-    //     assert_eq!(Ok(6), result);
-    // }
-
-    // #[test]
-    // fn register_aggregate_function_returns_finalize_default_on_empty_set() {
-    //     use self::my_sum_example::dsl::*;
-
-    //     let connection = &mut SqliteConnection::establish(":memory:").unwrap();
-    //     diesel::sql_query(
-    //         "CREATE TABLE my_sum_example (id integer primary key autoincrement, value integer)",
-    //     )
-    //     .execute(connection)
-    //     .unwrap();
-
-    //     my_sum::register_impl::<MySum, _>(connection).unwrap();
-
-    //     let result = my_sum_example
-    //         .select(my_sum(value))
-    //         .get_result::<i32>(connection);
-    //     assert_eq!(Ok(0), result);
-    // }
-
-    // sql_function! {
-    //     #[aggregate]
-    //     fn range_max(expr1: Integer, expr2: Integer, expr3: Integer) -> Nullable<Integer>;
-    // }
-
-    #[derive(Default)]
-    struct RangeMax<T> {
-        max_value: Option<T>,
-    }
-
-    impl<T: Default + Ord + Copy + Clone> SqliteAggregateFunction<(T, T, T)> for RangeMax<T> {
-        type Output = Option<T>;
-
-        fn step(&mut self, (x0, x1, x2): (T, T, T)) {
-            let max = if x0 >= x1 && x0 >= x2 {
-                x0
-            } else if x1 >= x0 && x1 >= x2 {
-                x1
-            } else {
-                x2
-            };
+        #[derive(Default)]
+        struct RangeMax<T> {
+            max_value: Option<T>,
+        }
 
-            self.max_value = match self.max_value {
-                Some(current_max_value) if max > current_max_value => Some(max),
-                None => Some(max),
-                _ => self.max_value,
-            };
+        impl<T: Default + Ord + Copy + Clone> SqliteAggregateFunction<(T, T, T)> for RangeMax<T> {
+            type Output = Option<T>;
+
+            fn step(&mut self, (x0, x1, x2): (T, T, T)) {
+                let max = if x0 >= x1 && x0 >= x2 {
+                    x0
+                } else if x1 >= x0 && x1 >= x2 {
+                    x1
+                } else {
+                    x2
+                };
+
+                self.max_value = match self.max_value {
+                    Some(current_max_value) if max > current_max_value => Some(max),
+                    None => Some(max),
+                    _ => self.max_value,
+                };
+            }
+
+            fn finalize(aggregator: Option<Self>) -> Self::Output {
+                aggregator?.max_value
+            }
+        }
+
+        table! {
+            range_max_example {
+                id -> Integer,
+                value1 -> Integer,
+                value2 -> Integer,
+                value3 -> Integer,
+            }
+        }
+
+        #[test]
+        fn register_aggregate_multiarg_function() {
+            use self::range_max_example::dsl::*;
+
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            diesel::sql_query(
+                r#"CREATE TABLE range_max_example (
+                    id integer primary key autoincrement,
+                    value1 integer,
+                    value2 integer,
+                    value3 integer
+                )"#,
+            )
+            .execute(connection)
+            .unwrap();
+            diesel::sql_query(
+                "INSERT INTO range_max_example (value1, value2, value3) VALUES (3, 2, 1), (2, 2, 2)",
+            )
+            .execute(connection)
+            .unwrap();
+
+            range_max::register_impl::<RangeMax<i32>, _, _, _>(connection).unwrap();
+            let result = range_max_example
+                .select(range_max(value1, value2, value3))
+                .get_result::<Option<i32>>(connection)
+                .unwrap();
+            assert_eq!(Some(3), result);
         }
 
-        fn finalize(aggregator: Option<Self>) -> Self::Output {
-            aggregator?.max_value
+        table! {
+            my_collation_example {
+                id -> Integer,
+                value -> Text,
+            }
+        }
+
+        #[test]
+        fn register_collation_function() {
+            use self::my_collation_example::dsl::*;
+
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+
+            connection
+                .register_collation("RUSTNOCASE", |rhs, lhs| {
+                    rhs.to_lowercase().cmp(&lhs.to_lowercase())
+                })
+                .unwrap();
+
+            diesel::sql_query(
+                    "CREATE TABLE my_collation_example (id integer primary key autoincrement, value text collate RUSTNOCASE)",
+                ).execute(connection)
+                .unwrap();
+            diesel::sql_query(
+                "INSERT INTO my_collation_example (value) VALUES ('foo'), ('FOo'), ('f00')",
+            )
+            .execute(connection)
+            .unwrap();
+
+            let result = my_collation_example
+                .filter(value.eq("foo"))
+                .select(value)
+                .load::<String>(connection);
+            assert_eq!(
+                Ok(&["foo".to_owned(), "FOo".to_owned()][..]),
+                result.as_ref().map(|vec| vec.as_ref())
+            );
+
+            let result = my_collation_example
+                .filter(value.eq("FOO"))
+                .select(value)
+                .load::<String>(connection);
+            assert_eq!(
+                Ok(&["foo".to_owned(), "FOo".to_owned()][..]),
+                result.as_ref().map(|vec| vec.as_ref())
+            );
+
+            let result = my_collation_example
+                .filter(value.eq("f00"))
+                .select(value)
+                .load::<String>(connection);
+            assert_eq!(
+                Ok(&["f00".to_owned()][..]),
+                result.as_ref().map(|vec| vec.as_ref())
+            );
+
+            let result = my_collation_example
+                .filter(value.eq("F00"))
+                .select(value)
+                .load::<String>(connection);
+            assert_eq!(
+                Ok(&["f00".to_owned()][..]),
+                result.as_ref().map(|vec| vec.as_ref())
+            );
+
+            let result = my_collation_example
+                .filter(value.eq("oof"))
+                .select(value)
+                .load::<String>(connection);
+            assert_eq!(Ok(&[][..]), result.as_ref().map(|vec| vec.as_ref()));
+        }
+
+        #[test]
+        fn register_collation_can_be_used_in_order_by() {
+            use self::my_collation_example::dsl::*;
+
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+
+            connection
+                .register_collation("RUSTNOCASE", |rhs, lhs| {
+                    rhs.to_lowercase().cmp(&lhs.to_lowercase())
+                })
+                .unwrap();
+
+            diesel::sql_query(
+                "CREATE TABLE my_collation_example (id integer primary key autoincrement, value text)",
+            )
+            .execute(connection)
+            .unwrap();
+            diesel::sql_query(
+                "INSERT INTO my_collation_example (value) VALUES ('b'), ('C'), ('A')",
+            )
+            .execute(connection)
+            .unwrap();
+
+            let result = my_collation_example
+                .select(value)
+                .order(sql::<Text>("value COLLATE RUSTNOCASE"))
+                .load::<String>(connection);
+            assert_eq!(
+                Ok(&["A".to_owned(), "b".to_owned(), "C".to_owned()][..]),
+                result.as_ref().map(|vec| vec.as_ref())
+            );
+        }
+
+        #[test]
+        fn panicking_collation_returns_an_error() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+
+            connection
+                .register_collation("PANICKING", |_, _| panic!("collation failed"))
+                .unwrap();
+
+            let result = diesel::select(sql::<diesel::sql_types::Bool>(
+                "'a' < 'b' COLLATE PANICKING",
+            ))
+            .get_result::<bool>(connection);
+            assert!(result.is_err());
         }
     }
 
     table! {
-        range_max_example {
+        animals {
             id -> Integer,
-            value1 -> Integer,
-            value2 -> Integer,
-            value3 -> Integer,
-        }
-    }
-
-    // #[test]
-    // fn register_aggregate_multiarg_function() {
-    //     use self::range_max_example::dsl::*;
-
-    //     let connection = &mut SqliteConnection::establish(":memory:").unwrap();
-    //     diesel::sql_query(
-    //         r#"CREATE TABLE range_max_example (
-    //             id integer primary key autoincrement,
-    //             value1 integer,
-    //             value2 integer,
-    //             value3 integer
-    //         )"#,
-    //     )
-    //     .execute(connection)
-    //     .unwrap();
-    //     diesel::sql_query(
-    //         "INSERT INTO range_max_example (value1, value2, value3) VALUES (3, 2, 1), (2, 2, 2)",
-    //     )
-    //     .execute(connection)
-    //     .unwrap();
-
-    //     range_max::register_impl::<RangeMax<i32>, _, _, _>(connection).unwrap();
-    //     let result = range_max_example
-    //         .select(range_max(value1, value2, value3))
-    //         .get_result::<Option<i32>>(connection)
-    //         .unwrap();
-    //     assert_eq!(Some(3), result);
-    // }
-
-    // table! {
-    //     my_collation_example {
-    //         id -> Integer,
-    //         value -> Text,
-    //     }
-    // }
-
-    // #[test]
-    // fn register_collation_function() {
-    //     use self::my_collation_example::dsl::*;
-
-    //     let connection = &mut SqliteConnection::establish(":memory:").unwrap();
-
-    //     // connection
-    //     //     .register_collation("RUSTNOCASE", |rhs, lhs| {
-    //     //         rhs.to_lowercase().cmp(&lhs.to_lowercase())
-    //     //     })
-    //     //     .unwrap();
-
-    //     diesel::sql_query(
-    //             "CREATE TABLE my_collation_example (id integer primary key autoincrement, value text collate RUSTNOCASE)",
-    //         ).execute(connection)
-    //         .unwrap();
-    //     diesel::sql_query(
-    //         "INSERT INTO my_collation_example (value) VALUES ('foo'), ('FOo'), ('f00')",
-    //     )
-    //     .execute(connection)
-    //     .unwrap();
-
-    //     let result = my_collation_example
-    //         .filter(value.eq("foo"))
-    //         .select(value)
-    //         .load::<String>(connection);
-    //     assert_eq!(
-    //         Ok(&["foo".to_owned(), "FOo".to_owned()][..]),
-    //         result.as_ref().map(|vec| vec.as_ref())
-    //     );
-
-    //     let result = my_collation_example
-    //         .filter(value.eq("FOO"))
-    //         .select(value)
-    //         .load::<String>(connection);
-    //     assert_eq!(
-    //         Ok(&["foo".to_owned(), "FOo".to_owned()][..]),
-    //         result.as_ref().map(|vec| vec.as_ref())
-    //     );
-
-    //     let result = my_collation_example
-    //         .filter(value.eq("f00"))
-    //         .select(value)
-    //         .load::<String>(connection);
-    //     assert_eq!(
-    //         Ok(&["f00".to_owned()][..]),
-    //         result.as_ref().map(|vec| vec.as_ref())
-    //     );
-
-    //     let result = my_collation_example
-    //         .filter(value.eq("F00"))
-    //         .select(value)
-    //         .load::<String>(connection);
-    //     assert_eq!(
-    //         Ok(&["f00".to_owned()][..]),
-    //         result.as_ref().map(|vec| vec.as_ref())
-    //     );
-
-    //     let result = my_collation_example
-    //         .filter(value.eq("oof"))
-    //         .select(value)
-    //         .load::<String>(connection);
-    //     assert_eq!(Ok(&[][..]), result.as_ref().map(|vec| vec.as_ref()));
-    // }
+            name -> Text,
+            species -> Nullable<Text>,
+        }
+    }
+
+    fn setup_animals(connection: &mut SqliteConnection) {
+        diesel::sql_query(
+            "CREATE TABLE animals (id integer primary key autoincrement, name text not null, species text)",
+        )
+        .execute(connection)
+        .unwrap();
+        diesel::sql_query(
+            "INSERT INTO animals (name, species) VALUES ('Jack', 'dog'), ('Tom', NULL)",
+        )
+        .execute(connection)
+        .unwrap();
+    }
+
+    #[test]
+    fn is_compares_null_safe() {
+        use self::animals::dsl::*;
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_animals(connection);
+
+        let dogs = animals
+            .select(name)
+            .filter(species.is("dog"))
+            .load::<String>(connection);
+        assert_eq!(Ok(vec!["Jack".to_owned()]), dogs);
+
+        let unknown = animals
+            .select(name)
+            .filter(species.is(None::<String>))
+            .load::<String>(connection);
+        assert_eq!(Ok(vec!["Tom".to_owned()]), unknown);
+    }
+
+    #[test]
+    fn is_not_compares_null_safe() {
+        use self::animals::dsl::*;
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_animals(connection);
+
+        let not_dogs = animals
+            .select(name)
+            .filter(species.is_not("dog"))
+            .load::<String>(connection);
+        assert_eq!(Ok(vec!["Tom".to_owned()]), not_dogs);
+
+        let known = animals
+            .select(name)
+            .filter(species.is_not(None::<String>))
+            .load::<String>(connection);
+        assert_eq!(Ok(vec!["Jack".to_owned()]), known);
+    }
+
+    #[test]
+    fn is_can_be_combined_with_other_expressions() {
+        use self::animals::dsl::*;
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_animals(connection);
+
+        let query = animals
+            .select(name)
+            .filter(species.is(None::<String>).eq(false).and(id.is(1)));
+        assert_eq!(
+            "SELECT `animals`.`name` FROM `animals` WHERE (((`animals`.`species` IS ?) = ?) AND (`animals`.`id` IS ?)) -- binds: [None, false, 1]",
+            diesel::debug_query::<Sqlite, _>(&query).to_string()
+        );
+        assert_eq!(
+            Ok(vec!["Jack".to_owned()]),
+            query.load::<String>(connection)
+        );
+
+        let helper: crate::dsl::IsNot<species, Option<String>> = species.is_not(None);
+        let known = animals
+            .select(name)
+            .filter(helper)
+            .load::<String>(connection);
+        assert_eq!(Ok(vec!["Jack".to_owned()]), known);
+    }
+
+    #[test]
+    fn opening_a_database_in_a_missing_directory_fails() {
+        let result = SqliteConnection::establish("/does/not/exist/test.db");
+        assert!(matches!(result, Err(ConnectionError::BadConnection(_))));
+    }
+
+    // stock lunatic traps the process instead of reporting these errors
+    #[cfg(feature = "host-statement-errors")]
+    #[test]
+    fn syntax_errors_are_reported_when_preparing() {
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        let result = diesel::sql_query("SELEC 1").execute(connection);
+        match result {
+            Err(Error::DatabaseError(DatabaseErrorKind::Unknown, info)) => {
+                assert!(info.message().contains("syntax error"));
+            }
+            e => panic!("unexpected result {e:?}"),
+        }
+        assert_eq!(0, connection.statement_cache.len());
+    }
+
+    // stock lunatic traps the process instead of reporting these errors
+    #[cfg(feature = "host-statement-errors")]
+    #[test]
+    fn binding_too_many_values_fails() {
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        let result = diesel::sql_query("SELECT 1")
+            .bind::<Integer, _>(1)
+            .execute(connection);
+        match result {
+            Err(Error::DatabaseError(DatabaseErrorKind::Unknown, info)) => {
+                assert!(info.message().contains("column index out of range"));
+            }
+            e => panic!("unexpected result {e:?}"),
+        }
+
+        // the failed bind must not leave the connection in a broken state
+        assert_eq!(
+            Ok(2),
+            diesel::select(2.into_sql::<Integer>()).get_result(connection)
+        );
+    }
+
+    #[test]
+    fn bind_values_are_encoded_like_a_bind_list() {
+        use crate::sqlite::bind_collector::{InternalSqliteBindValue, WireBindList};
+        use crate::sqlite::SqliteType;
+        use lunatic_sqlite_api::wire_format::{BindKey, BindList, BindPair, BindValue};
+
+        let blob = [1u8, 2, 3];
+        let binds = [
+            (
+                InternalSqliteBindValue::BorrowedString("Sean"),
+                SqliteType::Text,
+            ),
+            (
+                InternalSqliteBindValue::String("Tess".into()),
+                SqliteType::Text,
+            ),
+            (
+                InternalSqliteBindValue::BorrowedBinary(&blob),
+                SqliteType::Binary,
+            ),
+            (
+                InternalSqliteBindValue::Binary(Box::new([4, 5])),
+                SqliteType::Binary,
+            ),
+            (InternalSqliteBindValue::I32(-1), SqliteType::Integer),
+            (InternalSqliteBindValue::I64(1 << 40), SqliteType::Long),
+            (InternalSqliteBindValue::F64(0.5), SqliteType::Double),
+            (InternalSqliteBindValue::Null, SqliteType::Text),
+        ];
+        let expected = BindList(
+            vec![
+                BindValue::Text("Sean".into()),
+                BindValue::Text("Tess".into()),
+                BindValue::Blob(vec![1, 2, 3]),
+                BindValue::Blob(vec![4, 5]),
+                BindValue::Int(-1),
+                BindValue::Int64(1 << 40),
+                BindValue::Double(0.5),
+                BindValue::Null,
+            ]
+            .into_iter()
+            .zip(1..)
+            .map(|(value, idx)| BindPair(BindKey::Numeric(idx), value))
+            .collect(),
+        );
+
+        assert_eq!(
+            bincode::serialize(&expected).unwrap(),
+            bincode::serialize(&WireBindList(&binds)).unwrap()
+        );
+    }
+
+    #[test]
+    fn statements_with_many_binds_can_be_reused() {
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        let query = |a: i32, b: &'static str, c: Option<Vec<u8>>| {
+            diesel::select((
+                a.into_sql::<Integer>(),
+                b.into_sql::<diesel::sql_types::Text>(),
+                c.into_sql::<Nullable<diesel::sql_types::Binary>>(),
+            ))
+        };
+
+        assert_eq!(
+            Ok((1, "one".to_owned(), Some(vec![1]))),
+            query(1, "one", Some(vec![1])).get_result(connection)
+        );
+        assert_eq!(
+            Ok((2, "two".to_owned(), None::<Vec<u8>>)),
+            query(2, "two", None).get_result(connection)
+        );
+        assert_eq!(1, connection.statement_cache.len());
+    }
+
+    #[test]
+    fn rows_are_loaded_by_column_name() {
+        #[derive(Debug, PartialEq, QueryableByName)]
+        struct Entry {
+            #[diesel(sql_type = Integer)]
+            id: i32,
+            #[diesel(sql_type = diesel::sql_types::Text)]
+            name: String,
+        }
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        let entries = diesel::sql_query(
+            "SELECT 'Sean' AS name, 1 AS id UNION ALL SELECT 'Tess' AS name, 2 AS id",
+        )
+        .load::<Entry>(connection);
+        assert_eq!(
+            Ok(vec![
+                Entry {
+                    id: 1,
+                    name: "Sean".into()
+                },
+                Entry {
+                    id: 2,
+                    name: "Tess".into()
+                },
+            ]),
+            entries
+        );
+    }
+
+    #[test]
+    fn duplicated_column_names_resolve_to_the_first_column() {
+        let column_names = ColumnNames::new(vec!["id".into(), "name".into(), "id".into()]);
+        assert_eq!(Some(0), column_names.index_of("id"));
+        assert_eq!(Some(1), column_names.index_of("name"));
+        assert_eq!(None, column_names.index_of("species"));
+        assert_eq!(3, column_names.len());
+    }
+
+    table! {
+        pets {
+            id -> Integer,
+            name -> Text,
+            species -> Text,
+        }
+    }
+
+    #[derive(Insertable)]
+    #[diesel(table_name = pets)]
+    struct NewPet<'a> {
+        name: &'a str,
+        species: Option<&'a str>,
+    }
+
+    fn setup_pets(connection: &mut SqliteConnection) {
+        diesel::sql_query(
+            "CREATE TABLE pets (id integer primary key autoincrement, name text not null, \
+             species text not null default 'unknown')",
+        )
+        .execute(connection)
+        .unwrap();
+    }
+
+    #[test]
+    fn default_values_are_left_out_of_inserts() {
+        use self::pets::dsl::*;
+
+        let query = diesel::insert_into(pets).values(NewPet {
+            name: "Jack",
+            species: None,
+        });
+        assert_eq!(
+            "INSERT INTO `pets` (`name`) VALUES (?) -- binds: [\"Jack\"]",
+            diesel::debug_query::<Sqlite, _>(&query).to_string()
+        );
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_pets(connection);
+        assert_eq!(Ok(1), query.execute(connection));
+        assert_eq!(
+            Ok(vec![("Jack".to_owned(), "unknown".to_owned())]),
+            pets.select((name, species)).load(connection)
+        );
+    }
+
+    #[test]
+    fn batch_inserts_insert_all_rows() {
+        use self::pets::dsl::*;
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_pets(connection);
+        let new_pets = vec![
+            NewPet {
+                name: "Jack",
+                species: Some("dog"),
+            },
+            NewPet {
+                name: "Tom",
+                species: Some("cat"),
+            },
+        ];
+
+        assert_eq!(
+            Ok(2),
+            diesel::insert_into(pets)
+                .values(&new_pets)
+                .execute(connection)
+        );
+        // `changes()` counts the rows of the last statement only
+        assert_eq!(Ok(2), last_statement_changes(connection));
+        assert_eq!(
+            Ok(vec![
+                ("Jack".to_owned(), "dog".to_owned()),
+                ("Tom".to_owned(), "cat".to_owned()),
+            ]),
+            pets.select((name, species)).order(id).load(connection)
+        );
+    }
+
+    fn last_statement_changes(connection: &mut SqliteConnection) -> QueryResult<i32> {
+        diesel::select(sql::<Integer>("changes()")).get_result(connection)
+    }
+
+    #[test]
+    fn batch_inserts_with_default_values_insert_each_row() {
+        use self::pets::dsl::*;
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_pets(connection);
+        let new_pets = vec![
+            NewPet {
+                name: "Jack",
+                species: Some("dog"),
+            },
+            NewPet {
+                name: "Tom",
+                species: None,
+            },
+        ];
+
+        assert_eq!(
+            Ok(2),
+            diesel::insert_into(pets)
+                .values(&new_pets)
+                .execute(connection)
+        );
+        assert_eq!(Ok(1), last_statement_changes(connection));
+        assert_eq!(
+            Ok(vec![
+                ("Jack".to_owned(), "dog".to_owned()),
+                ("Tom".to_owned(), "unknown".to_owned()),
+            ]),
+            pets.select((name, species)).order(id).load(connection)
+        );
+    }
+
+    #[test]
+    fn batch_inserts_return_all_rows() {
+        use self::pets::dsl::*;
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_pets(connection);
+        let new_pets = vec![
+            NewPet {
+                name: "Jack",
+                species: Some("dog"),
+            },
+            NewPet {
+                name: "Tom",
+                species: Some("cat"),
+            },
+        ];
+
+        let query = diesel::insert_into(pets)
+            .values(&new_pets)
+            .returning((id, name));
+        assert_eq!(
+            "INSERT INTO `pets` (`name`, `species`) VALUES (?, ?), (?, ?) \
+             RETURNING `pets`.`id`, `pets`.`name` -- binds: [\"Jack\", \"dog\", \"Tom\", \"cat\"]",
+            diesel::debug_query::<Sqlite, _>(&query).to_string()
+        );
+        assert_eq!(
+            Ok(vec![(1, "Jack".to_owned()), (2, "Tom".to_owned())]),
+            query.get_results(connection)
+        );
+        assert_eq!(
+            Ok(vec![(3, "Jack".to_owned(), "dog".to_owned())]),
+            diesel::insert_into(pets)
+                .values(&new_pets[..1])
+                .get_results(connection)
+        );
+    }
+
+    #[test]
+    fn batch_inserts_with_different_columns_cannot_return_rows() {
+        use self::pets::dsl::*;
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_pets(connection);
+        let new_pets = vec![
+            NewPet {
+                name: "Jack",
+                species: Some("dog"),
+            },
+            NewPet {
+                name: "Tom",
+                species: None,
+            },
+        ];
+
+        let result = diesel::insert_into(pets)
+            .values(&new_pets)
+            .returning(id)
+            .get_results::<i32>(connection);
+        assert!(matches!(result, Err(Error::QueryBuilderError(_))));
+        assert_eq!(Ok(0), pets.count().get_result::<i64>(connection));
+    }
+
+    // resetting the statement after the failed step traps with stock lunatic
+    #[cfg(feature = "host-statement-errors")]
+    #[test]
+    fn failing_rows_roll_back_the_whole_batch() {
+        use self::pets::dsl::*;
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_pets(connection);
+        diesel::sql_query("CREATE UNIQUE INDEX pet_names ON pets (name)")
+            .execute(connection)
+            .unwrap();
+        let new_pets = vec![
+            NewPet {
+                name: "Jack",
+                species: None,
+            },
+            NewPet {
+                name: "Jack",
+                species: Some("dog"),
+            },
+        ];
+
+        assert!(diesel::insert_into(pets)
+            .values(&new_pets)
+            .execute(connection)
+            .is_err());
+        assert_eq!(Ok(0), pets.count().get_result::<i64>(connection));
+    }
+
+    table! {
+        nullable_types {
+            id -> Integer,
+            small -> Nullable<SmallInt>,
+            int -> Nullable<Integer>,
+            big -> Nullable<BigInt>,
+            boolean -> Nullable<Bool>,
+            float -> Nullable<Float>,
+            double -> Nullable<Double>,
+            numeric -> Nullable<Numeric>,
+            text -> Nullable<Text>,
+            binary -> Nullable<Binary>,
+            date -> Nullable<Date>,
+            time -> Nullable<Time>,
+            timestamp -> Nullable<Timestamp>,
+        }
+    }
+
+    table! {
+        owners {
+            id -> Integer,
+        }
+    }
+
+    allow_tables_to_appear_in_same_query!(nullable_types, owners);
+
+    #[derive(Debug, Clone, PartialEq, Queryable)]
+    struct NullableTypes {
+        id: i32,
+        small: Option<i16>,
+        int: Option<i32>,
+        big: Option<i64>,
+        boolean: Option<bool>,
+        float: Option<f32>,
+        double: Option<f64>,
+        numeric: Option<bigdecimal::BigDecimal>,
+        text: Option<String>,
+        binary: Option<Vec<u8>>,
+        date: Option<String>,
+        time: Option<String>,
+        timestamp: Option<String>,
+    }
+
+    // strings cannot be bound as date and time types yet, those columns are
+    // filled in by `set_sql_only_columns`
+    #[derive(Insertable)]
+    #[diesel(table_name = nullable_types, treat_none_as_null = true)]
+    struct NewNullableTypes<'a> {
+        id: i32,
+        small: Option<i16>,
+        int: Option<i32>,
+        big: Option<i64>,
+        boolean: Option<bool>,
+        float: Option<f32>,
+        double: Option<f64>,
+        numeric: Option<bigdecimal::BigDecimal>,
+        text: Option<&'a str>,
+        binary: Option<&'a [u8]>,
+    }
+
+    impl NewNullableTypes<'static> {
+        fn nulls(id: i32) -> Self {
+            NewNullableTypes {
+                id,
+                small: None,
+                int: None,
+                big: None,
+                boolean: None,
+                float: None,
+                double: None,
+                numeric: None,
+                text: None,
+                binary: None,
+            }
+        }
+
+        fn values(id: i32) -> Self {
+            NewNullableTypes {
+                id,
+                small: Some(-2),
+                int: Some(42),
+                big: Some(i64::MAX),
+                boolean: Some(true),
+                float: Some(1.5),
+                double: Some(-2.25),
+                numeric: Some(bigdecimal::BigDecimal::new(15.into(), 1)),
+                text: Some("text"),
+                binary: Some(&[0, 1, 2]),
+            }
+        }
+    }
+
+    impl NullableTypes {
+        fn nulls(id: i32) -> Self {
+            NullableTypes {
+                id,
+                small: None,
+                int: None,
+                big: None,
+                boolean: None,
+                float: None,
+                double: None,
+                numeric: None,
+                text: None,
+                binary: None,
+                date: None,
+                time: None,
+                timestamp: None,
+            }
+        }
+
+        /// The values of `NewNullableTypes::values`
+        fn inserted(id: i32) -> Self {
+            NullableTypes {
+                id,
+                small: Some(-2),
+                int: Some(42),
+                big: Some(i64::MAX),
+                boolean: Some(true),
+                float: Some(1.5),
+                double: Some(-2.25),
+                numeric: Some(bigdecimal::BigDecimal::new(15.into(), 1)),
+                text: Some("text".into()),
+                binary: Some(vec![0, 1, 2]),
+                date: None,
+                time: None,
+                timestamp: None,
+            }
+        }
+
+        /// The values of `NewNullableTypes::values` and `set_sql_only_columns`
+        fn values(id: i32) -> Self {
+            NullableTypes {
+                date: Some("2023-01-31".into()),
+                time: Some("12:34:56".into()),
+                timestamp: Some("2023-01-31 12:34:56".into()),
+                ..Self::inserted(id)
+            }
+        }
+    }
+
+    fn setup_nullable_types(connection: &mut SqliteConnection) {
+        connection
+            .batch_execute(
+                "CREATE TABLE nullable_types (id INTEGER PRIMARY KEY, small SMALLINT, \
+                 int INTEGER, big BIGINT, boolean BOOLEAN, float FLOAT, double DOUBLE, \
+                 numeric NUMERIC, text TEXT, binary BLOB, date DATE, time TIME, \
+                 timestamp TIMESTAMP); \
+                 CREATE TABLE owners (id INTEGER PRIMARY KEY);",
+            )
+            .unwrap();
+    }
+
+    fn insert_nullable_types(connection: &mut SqliteConnection) {
+        diesel::insert_into(nullable_types::table)
+            .values(&vec![
+                NewNullableTypes::nulls(1),
+                NewNullableTypes::values(2),
+            ])
+            .execute(connection)
+            .unwrap();
+        set_sql_only_columns(connection, 2);
+    }
+
+    fn set_sql_only_columns(connection: &mut SqliteConnection, id: i32) {
+        use diesel::sql_types::{Date, Time, Timestamp};
+
+        diesel::update(nullable_types::table.find(id))
+            .set((
+                nullable_types::date.eq(sql::<Nullable<Date>>("'2023-01-31'")),
+                nullable_types::time.eq(sql::<Nullable<Time>>("'12:34:56'")),
+                nullable_types::timestamp.eq(sql::<Nullable<Timestamp>>("'2023-01-31 12:34:56'")),
+            ))
+            .execute(connection)
+            .unwrap();
+    }
+
+    #[test]
+    fn null_values_are_loaded_as_none() {
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_nullable_types(connection);
+        insert_nullable_types(connection);
+
+        assert_eq!(
+            Ok(vec![NullableTypes::nulls(1), NullableTypes::values(2)]),
+            nullable_types::table
+                .order(nullable_types::id)
+                .load::<NullableTypes>(connection)
+        );
+    }
+
+    #[test]
+    fn nullable_fields_report_null_values() {
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        let mut rows = connection
+            .load(diesel::select((
+                sql::<Nullable<Integer>>("NULL"),
+                sql::<Nullable<Integer>>("1"),
+            )))
+            .unwrap();
+        let row = rows.next().unwrap().unwrap();
+
+        let null = row.get(0).unwrap();
+        assert!(null.is_null());
+        assert!(null.value().is_none());
+        let one = row.get(1).unwrap();
+        assert!(!one.is_null());
+        assert!(one.value().is_some());
+    }
+
+    #[test]
+    fn left_joins_load_missing_rows_as_none() {
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_nullable_types(connection);
+        diesel::insert_into(owners::table)
+            .values(&vec![owners::id.eq(1), owners::id.eq(2), owners::id.eq(3)])
+            .execute(connection)
+            .unwrap();
+        insert_nullable_types(connection);
+
+        let joined = owners::table
+            .left_join(nullable_types::table.on(nullable_types::id.eq(owners::id)))
+            .select((owners::id, nullable_types::all_columns.nullable()))
+            .order(owners::id)
+            .load::<(i32, Option<NullableTypes>)>(connection);
+        assert_eq!(
+            Ok(vec![
+                (1, Some(NullableTypes::nulls(1))),
+                (2, Some(NullableTypes::values(2))),
+                (3, None),
+            ]),
+            joined
+        );
+    }
+
+    #[test]
+    fn returning_clauses_load_null_values_as_none() {
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        setup_nullable_types(connection);
+
+        assert_eq!(
+            Ok(NullableTypes::nulls(1)),
+            diesel::insert_into(nullable_types::table)
+                .values(&NewNullableTypes::nulls(1))
+                .get_result(connection)
+        );
+        assert_eq!(
+            Ok(NullableTypes::inserted(2)),
+            diesel::insert_into(nullable_types::table)
+                .values(&NewNullableTypes::values(2))
+                .get_result(connection)
+        );
+        assert_eq!(
+            Ok((None, Some("text".to_owned()))),
+            diesel::update(nullable_types::table.find(2))
+                .set(nullable_types::int.eq(None::<i32>))
+                .returning((nullable_types::int, nullable_types::text))
+                .get_result::<(Option<i32>, Option<String>)>(connection)
+        );
+    }
+
+    fn deserialization_error<T: std::fmt::Debug>(
+        result: QueryResult<T>,
+    ) -> crate::sqlite::SqliteDeserializationError {
+        match result {
+            Err(Error::DeserializationError(error)) => error
+                .downcast_ref::<crate::sqlite::SqliteDeserializationError>()
+                .cloned()
+                .unwrap_or_else(|| panic!("unexpected error {error}")),
+            result => panic!("expected a deserialization error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn out_of_range_values_are_truncated_by_default() {
+        use diesel::sql_types::{Float, SmallInt};
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+
+        assert_eq!(
+            Ok((4464, 0, f32::INFINITY)),
+            diesel::select((
+                sql::<SmallInt>("70000"),
+                sql::<Integer>("1 << 40"),
+                sql::<Float>("1e300"),
+            ))
+            .get_result::<(i16, i32, f32)>(connection)
+        );
+    }
+
+    #[test]
+    fn out_of_range_values_are_errors_naming_their_column_in_strict_mode() {
+        use diesel::sql_types::{Float, SmallInt};
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        connection.set_deserialization_mode(DeserializationMode::Strict);
+
+        let error = deserialization_error(
+            diesel::select(sql::<SmallInt>("70000 AS small")).get_result::<i16>(connection),
+        );
+        assert_eq!(Some("small"), error.column_name());
+        assert_eq!(
+            "column `small`: 70000 is out of range for i16",
+            error.to_string()
+        );
+
+        let error = deserialization_error(
+            diesel::select(sql::<Integer>("1 << 40 AS int")).get_result::<i32>(connection),
+        );
+        assert_eq!(Some("int"), error.column_name());
+
+        let error = deserialization_error(
+            diesel::select(sql::<Float>("1e300 AS float")).get_result::<f32>(connection),
+        );
+        assert_eq!(Some("float"), error.column_name());
+    }
+
+    #[test]
+    fn values_are_converted_following_sqlite_affinity_by_default() {
+        use diesel::sql_types::{Bool, Double, Text};
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        assert_eq!(
+            DeserializationMode::Tolerant,
+            connection.deserialization_mode()
+        );
+
+        assert_eq!(
+            Ok((42, 42, 1.0, "12".to_owned(), true)),
+            diesel::select((
+                sql::<Integer>("'42'"),
+                sql::<Integer>("42.0"),
+                sql::<Double>("1"),
+                sql::<Text>("12"),
+                sql::<Bool>("' 1 '"),
+            ))
+            .get_result::<(i32, i32, f64, String, bool)>(connection)
+        );
+        // conversions losing information are still errors
+        deserialization_error(diesel::select(sql::<Integer>("42.5")).get_result::<i32>(connection));
+        deserialization_error(
+            diesel::select(sql::<Integer>("'forty two'")).get_result::<i32>(connection),
+        );
+    }
+
+    #[test]
+    fn strict_mode_requires_matching_storage_classes() {
+        use diesel::sql_types::{Binary, Double, Text};
+
+        let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+        connection.set_deserialization_mode(DeserializationMode::Strict);
+
+        let error = deserialization_error(
+            diesel::select(sql::<Integer>("'42' AS answer")).get_result::<i32>(connection),
+        );
+        assert_eq!(
+            "column `answer`: expected an INTEGER, found TEXT value Text(\"42\")",
+            error.to_string()
+        );
+        deserialization_error(diesel::select(sql::<Integer>("42.0")).get_result::<i32>(connection));
+        deserialization_error(diesel::select(sql::<Double>("1")).get_result::<f64>(connection));
+        deserialization_error(diesel::select(sql::<Text>("12")).get_result::<String>(connection));
+        deserialization_error(
+            diesel::select(sql::<Binary>("'text'")).get_result::<Vec<u8>>(connection),
+        );
+
+        assert_eq!(
+            Ok((42, 1.5, "text".to_owned(), vec![0])),
+            diesel::select((
+                sql::<Integer>("42"),
+                sql::<Double>("1.5"),
+                sql::<Text>("'text'"),
+                sql::<Binary>("X'00'"),
+            ))
+            .get_result::<(i32, f64, String, Vec<u8>)>(connection)
+        );
+    }
+
+    mod borrowed {
+        use std::borrow::Cow;
+
+        use diesel::row::NamedRow;
+        use diesel::sql_types::{Binary, Text};
+        use lunatic::test;
+
+        use super::*;
+        use crate::sqlite::QueryableByNameRef;
+
+        #[derive(Debug, PartialEq)]
+        struct PostRef<'a> {
+            id: i32,
+            title: &'a str,
+            body: Option<&'a [u8]>,
+        }
+
+        impl<'a> QueryableByNameRef<'a> for PostRef<'a> {
+            fn build(row: &'a SqliteRow) -> deserialize::Result<Self> {
+                Ok(PostRef {
+                    id: NamedRow::get::<Integer, _>(row, "id")?,
+                    title: row.get_ref::<Text, _, _>("title")?,
+                    body: row.get_ref::<Nullable<Binary>, _, _>("body")?,
+                })
+            }
+        }
+
+        fn setup_posts(connection: &mut SqliteConnection) {
+            connection
+                .batch_execute(
+                    "CREATE TABLE posts (id INTEGER PRIMARY KEY, title TEXT NOT NULL, body BLOB); \
+                     INSERT INTO posts VALUES (1, 'first', X'0102'), (2, 'second', NULL);",
+                )
+                .unwrap();
+        }
+
+        #[test]
+        fn text_and_blobs_are_borrowed_from_the_row() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            setup_posts(connection);
+
+            let mut rows = connection
+                .load(diesel::sql_query(
+                    "SELECT title, body FROM posts WHERE id = 1",
+                ))
+                .unwrap();
+            let row = rows.next().unwrap().unwrap();
+
+            let title = row.get_ref::<Text, &str, _>(0).unwrap();
+            assert_eq!("first", title);
+            match row.inner_row.0[0] {
+                SqliteValue::Text(ref text) => assert_eq!(text.as_ptr(), title.as_ptr()),
+                ref value => panic!("unexpected value {value:?}"),
+            }
+            assert_eq!(
+                &[1u8, 2][..],
+                row.get_ref::<Binary, &[u8], _>("body").unwrap()
+            );
+            assert!(matches!(
+                row.get_ref::<Text, Cow<'_, str>, _>("title"),
+                Ok(Cow::Borrowed("first"))
+            ));
+            assert!(matches!(
+                row.get_ref::<Binary, Cow<'_, [u8]>, _>("body"),
+                Ok(Cow::Borrowed([1, 2]))
+            ));
+            assert!(row.get_ref::<Text, &str, _>("missing").is_err());
+        }
+
+        #[test]
+        fn numbers_are_only_borrowed_as_cow() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let mut rows = connection
+                .load(diesel::sql_query("SELECT 42 AS answer"))
+                .unwrap();
+            let row = rows.next().unwrap().unwrap();
+
+            assert!(row.get_ref::<Text, &str, _>("answer").is_err());
+            assert_eq!(
+                Ok(Cow::Owned::<str>("42".to_owned())),
+                row.get_ref::<Text, Cow<'_, str>, _>("answer")
+                    .map_err(|e| e.to_string())
+            );
+        }
+
+        #[test]
+        fn structs_with_borrowed_fields_are_built_from_rows() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            setup_posts(connection);
+
+            let mut rows = Vec::new();
+            let posts = connection
+                .load_by_name_ref::<_, PostRef<'_>>(
+                    diesel::sql_query("SELECT id, title, body FROM posts ORDER BY id"),
+                    &mut rows,
+                )
+                .unwrap();
+
+            assert_eq!(
+                vec![
+                    PostRef {
+                        id: 1,
+                        title: "first",
+                        body: Some(&[1, 2]),
+                    },
+                    PostRef {
+                        id: 2,
+                        title: "second",
+                        body: None,
+                    },
+                ],
+                posts
+            );
+            // `rows` stays borrowed as long as the posts are used
+            let title = posts[0].title.as_ptr();
+            let body = posts[0].body.unwrap().as_ptr();
+            match rows[0].inner_row.0[1..] {
+                [SqliteValue::Text(ref text), SqliteValue::Blob(ref blob)] => {
+                    assert_eq!(text.as_ptr(), title);
+                    assert_eq!(blob.as_ptr(), body);
+                }
+                ref values => panic!("unexpected values {values:?}"),
+            }
+            assert!(rows[1].get_ref::<Binary, &[u8], _>("body").is_err());
+        }
+
+        #[test]
+        fn rows_are_replaced_when_loading_by_name_ref() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            setup_posts(connection);
+
+            let mut rows = Vec::new();
+            for id in [1, 2] {
+                let posts = connection
+                    .load_by_name_ref::<_, PostRef<'_>>(
+                        diesel::sql_query("SELECT id, title, body FROM posts WHERE id = ?")
+                            .bind::<Integer, _>(id),
+                        &mut rows,
+                    )
+                    .unwrap();
+                assert_eq!(
+                    vec![id],
+                    posts.iter().map(|post| post.id).collect::<Vec<_>>()
+                );
+            }
+            assert_eq!(1, rows.len());
+        }
+    }
+
+    mod raw_values {
+        use diesel::backend::RawValue;
+        use diesel::deserialize::FromSql;
+        use diesel::sql_types::Text;
+        use lunatic::test;
+
+        use super::*;
+        use crate::sqlite::SqliteType;
+
+        /// A domain type with a `FromSql` impl written against diesel's own
+        /// SQLite backend
+        #[derive(Debug, PartialEq, FromSqlRow)]
+        enum Weight {
+            Grams(i64),
+            Kilograms(f64),
+            Label(String),
+        }
+
+        impl FromSql<Text, Sqlite> for Weight {
+            fn from_sql(value: RawValue<'_, Sqlite>) -> deserialize::Result<Self> {
+                match value.value_type() {
+                    Some(SqliteType::Long) => Ok(Weight::Grams(value.read_long())),
+                    Some(SqliteType::Double) => Ok(Weight::Kilograms(value.read_double())),
+                    _ => Ok(Weight::Label(value.read_text().to_owned())),
+                }
+            }
+        }
+
+        /// Everything the `read_*` methods make of a value
+        #[derive(Debug, PartialEq, FromSqlRow)]
+        struct Reads {
+            value_type: Option<SqliteType>,
+            integer: i32,
+            long: i64,
+            double: f64,
+            text: String,
+            blob: Vec<u8>,
+        }
+
+        impl FromSql<Text, Sqlite> for Reads {
+            fn from_sql(value: RawValue<'_, Sqlite>) -> deserialize::Result<Self> {
+                Ok(Reads {
+                    value_type: value.value_type(),
+                    integer: value.read_integer(),
+                    long: value.read_long(),
+                    double: value.read_double(),
+                    text: value.read_text().to_owned(),
+                    blob: value.read_blob().to_vec(),
+                })
+            }
+        }
+
+        fn reads(connection: &mut SqliteConnection, value: &str) -> Reads {
+            diesel::select(sql::<Text>(value))
+                .get_result(connection)
+                .unwrap()
+        }
+
+        #[test]
+        fn custom_types_inspect_the_storage_class() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+
+            let weights = diesel::select((
+                sql::<Text>("1500"),
+                sql::<Text>("1.5"),
+                sql::<Text>("'heavy'"),
+            ))
+            .get_result::<(Weight, Weight, Weight)>(connection);
+
+            assert_eq!(
+                Ok((
+                    Weight::Grams(1500),
+                    Weight::Kilograms(1.5),
+                    Weight::Label("heavy".to_owned()),
+                )),
+                weights
+            );
+        }
+
+        #[test]
+        fn values_are_read_like_sqlite_converts_them() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+
+            assert_eq!(
+                Reads {
+                    value_type: Some(SqliteType::Long),
+                    integer: 42,
+                    long: 42,
+                    double: 42.0,
+                    text: "42".to_owned(),
+                    blob: b"42".to_vec(),
+                },
+                reads(connection, "42")
+            );
+            assert_eq!(
+                Reads {
+                    value_type: Some(SqliteType::Double),
+                    integer: -2,
+                    long: -2,
+                    double: -2.75,
+                    text: "-2.75".to_owned(),
+                    blob: b"-2.75".to_vec(),
+                },
+                reads(connection, "-2.75")
+            );
+            assert_eq!(
+                Reads {
+                    value_type: Some(SqliteType::Text),
+                    integer: 12,
+                    long: 12,
+                    double: 1250.0,
+                    text: " 12.5e2kg".to_owned(),
+                    blob: b" 12.5e2kg".to_vec(),
+                },
+                reads(connection, "' 12.5e2kg'")
+            );
+            assert_eq!(
+                Reads {
+                    value_type: Some(SqliteType::Binary),
+                    integer: 7,
+                    long: 7,
+                    double: 7.0,
+                    text: "7\u{fffd}".to_owned(),
+                    blob: vec![b'7', 0xff],
+                },
+                reads(connection, "X'37FF'")
+            );
+        }
+
+        #[test]
+        fn out_of_range_numbers_saturate_or_truncate() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+
+            let big = reads(connection, "'99999999999999999999'");
+            assert_eq!((i64::MAX, -1), (big.long, big.integer));
+            assert_eq!(i64::MIN, reads(connection, "-1e300").long);
+            assert_eq!(1 << 32, reads(connection, "4294967296").long);
+            assert_eq!(0, reads(connection, "4294967296").integer);
+            assert_eq!(0.0, reads(connection, "'kg'").double);
+        }
+    }
+
+    /// The items of diesel's `sqlite` module, as named by code written for
+    /// diesel with this crate renamed to `diesel`
+    mod diesel_paths {
+        use diesel::query_builder::QueryBuilder;
+        use diesel::sql_types::HasSqlType;
+        use diesel::{Connection, ConnectionResult};
+        use lunatic::test;
+
+        use crate::sqlite::query_builder::SqliteQueryBuilder;
+        use crate::sqlite::{
+            sql_types, Sqlite, SqliteAggregateFunction, SqliteBindValue, SqliteConnection,
+            SqliteType, SqliteValue,
+        };
+
+        #[derive(Default)]
+        struct Count(i64);
+
+        impl SqliteAggregateFunction<i32> for Count {
+            type Output = i64;
+
+            fn step(&mut self, _: i32) {
+                self.0 += 1;
+            }
+
+            fn finalize(aggregator: Option<Self>) -> Self::Output {
+                aggregator.map_or(0, |count| count.0)
+            }
+        }
+
+        fn from_sql_signature(_: SqliteValue<'_, '_, '_>) {}
+
+        #[test]
+        fn sqlite_items_resolve_to_this_crate() {
+            let _: fn(SqliteValue<'_, '_, '_>) = from_sql_signature;
+            let _: SqliteBindValue<'_> = 42.into();
+            let _: crate::sqlite::SqliteQueryBuilder = SqliteQueryBuilder::new();
+            assert_eq!(
+                SqliteType::Text,
+                <Sqlite as HasSqlType<sql_types::Timestamptz>>::metadata(&mut ())
+            );
+            assert_eq!(
+                SqliteType::Text,
+                <Sqlite as HasSqlType<crate::sql_types::TimestamptzSqlite>>::metadata(&mut ())
+            );
+
+            let mut query_builder = SqliteQueryBuilder::new();
+            query_builder.push_identifier("posts").unwrap();
+            assert_eq!("`posts`", query_builder.finish());
+
+            let mut count = Count::default();
+            count.step(1);
+            assert_eq!(1, Count::finalize(Some(count)));
+            assert_eq!(0, Count::finalize(None));
+            let _: fn(&str) -> ConnectionResult<SqliteConnection> =
+                <SqliteConnection as Connection>::establish;
+        }
+    }
+
+    mod numeric {
+        use std::cell::RefCell;
+        use std::str::FromStr;
+
+        use bigdecimal::num_bigint::BigInt;
+        use bigdecimal::BigDecimal;
+        use diesel::sql_types::{BigInt as SqlBigInt, Double, Numeric, Text};
+        use lunatic::test;
+        use proptest::prelude::*;
+
+        use super::*;
+
+        table! {
+            decimals {
+                id -> Integer,
+                value -> Numeric,
+            }
+        }
+
+        /// Decimals with up to 77 digits and scales far beyond the ones of
+        /// monetary values in both directions
+        fn decimals() -> impl Strategy<Value = BigDecimal> {
+            (prop::collection::vec(any::<u8>(), 0..32), -100i64..100).prop_map(|(bytes, scale)| {
+                BigDecimal::new(BigInt::from_signed_bytes_be(&bytes), scale)
+            })
+        }
+
+        #[test]
+        fn decimals_are_bound_as_text() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let decimal = BigDecimal::from_str("1234.50").unwrap();
+
+            assert_eq!(
+                Ok(("text".to_owned(), "1234.50".to_owned())),
+                diesel::select((
+                    sql::<Text>("typeof(")
+                        .bind::<Numeric, _>(decimal.clone())
+                        .sql(")"),
+                    sql::<Text>("").bind::<Numeric, _>(decimal),
+                ))
+                .get_result::<(String, String)>(connection)
+            );
+        }
+
+        #[test]
+        fn decimals_round_trip_through_text_columns() {
+            let connection = RefCell::new(SqliteConnection::establish(":memory:").unwrap());
+            connection
+                .borrow_mut()
+                .batch_execute("CREATE TABLE decimals (id INTEGER PRIMARY KEY, value TEXT)")
+                .unwrap();
+
+            proptest!(|(decimal in decimals())| {
+                let loaded = diesel::insert_into(decimals::table)
+                    .values(decimals::value.eq(&decimal))
+                    .returning(decimals::value)
+                    .get_result::<BigDecimal>(&mut *connection.borrow_mut())
+                    .unwrap();
+                prop_assert_eq!(&decimal, &loaded);
+                // the scale is kept as well, negative ones are written as
+                // trailing zeros which are read back with a scale of 0
+                if decimal.as_bigint_and_exponent().1 >= 0 {
+                    prop_assert_eq!(decimal.to_string(), loaded.to_string());
+                }
+            });
+        }
+
+        #[test]
+        fn integers_are_read_as_decimals() {
+            let connection = RefCell::new(SqliteConnection::establish(":memory:").unwrap());
+
+            proptest!(|(int in any::<i64>())| {
+                let loaded = diesel::select(sql::<Numeric>("").bind::<SqlBigInt, _>(int))
+                    .get_result::<BigDecimal>(&mut *connection.borrow_mut())
+                    .unwrap();
+                prop_assert_eq!(BigDecimal::from(int), loaded);
+            });
+        }
+
+        #[test]
+        fn reals_are_read_as_their_shortest_decimal() {
+            let connection = RefCell::new(SqliteConnection::establish(":memory:").unwrap());
+            let read_real = |double: f64| {
+                diesel::select(sql::<Numeric>("").bind::<Double, _>(double))
+                    .get_result::<BigDecimal>(&mut *connection.borrow_mut())
+                    .unwrap()
+            };
+
+            assert_eq!(BigDecimal::from_str("0.1").unwrap(), read_real(0.1));
+            proptest!(|(double in prop::num::f64::NORMAL | prop::num::f64::SUBNORMAL | prop::num::f64::ZERO)| {
+                let loaded = read_real(double);
+                prop_assert_eq!(Ok(double), loaded.to_string().parse::<f64>());
+            });
+        }
+
+        #[test]
+        fn decimals_in_numeric_columns_are_read_from_any_storage_class() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            connection
+                .batch_execute("CREATE TABLE decimals (id INTEGER PRIMARY KEY, value NUMERIC)")
+                .unwrap();
+            let values = ["12", "12.00", "-0.25"];
+            diesel::insert_into(decimals::table)
+                .values(
+                    values
+                        .iter()
+                        .map(|value| decimals::value.eq(BigDecimal::from_str(value).unwrap()))
+                        .collect::<Vec<_>>(),
+                )
+                .execute(connection)
+                .unwrap();
+
+            assert_eq!(
+                Ok(vec![
+                    ("integer".to_owned(), BigDecimal::from(12)),
+                    ("integer".to_owned(), BigDecimal::from(12)),
+                    ("real".to_owned(), BigDecimal::from_str("-0.25").unwrap()),
+                ]),
+                decimals::table
+                    .select((sql::<Text>("typeof(value)"), decimals::value))
+                    .order(decimals::id)
+                    .load::<(String, BigDecimal)>(connection)
+            );
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    mod chrono {
+        use ::chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+        use diesel::sql_types::{Date, Time, Timestamp};
+        use lunatic::test;
+
+        use super::*;
+        use crate::sqlite::TimestamptzSqlite;
+
+        table! {
+            events {
+                id -> Integer,
+                day -> Date,
+                starts_at -> Time,
+                happened_at -> Timestamp,
+            }
+        }
+
+        #[derive(Debug, PartialEq, Queryable)]
+        struct Event {
+            id: i32,
+            day: NaiveDate,
+            starts_at: NaiveTime,
+            happened_at: NaiveDateTime,
+        }
+
+        #[derive(Debug, PartialEq, QueryableByName)]
+        struct Stamped {
+            #[diesel(sql_type = TimestamptzSqlite)]
+            stamp: DateTime<FixedOffset>,
+        }
+
+        #[derive(Debug, PartialEq, QueryableByName)]
+        struct Written {
+            #[diesel(sql_type = diesel::sql_types::Text)]
+            text: String,
+        }
+
+        fn epoch() -> NaiveDateTime {
+            NaiveDate::from_ymd_opt(1970, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        }
+
+        fn load_timestamp(connection: &mut SqliteConnection, value: &str) -> NaiveDateTime {
+            diesel::select(sql::<Timestamp>(value))
+                .get_result(connection)
+                .unwrap_or_else(|e| panic!("failed to load {value}: {e}"))
+        }
+
+        #[test]
+        fn chrono_values_round_trip() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            connection
+                .batch_execute(
+                    "CREATE TABLE events (id INTEGER PRIMARY KEY, day DATE NOT NULL, \
+                     starts_at TIME NOT NULL, happened_at TIMESTAMP NOT NULL);",
+                )
+                .unwrap();
+            let day = NaiveDate::from_ymd_opt(2023, 1, 31).unwrap();
+            let event = Event {
+                id: 1,
+                day,
+                starts_at: NaiveTime::from_hms_milli_opt(12, 34, 56, 789).unwrap(),
+                happened_at: day.and_hms_micro_opt(23, 59, 59, 123_456).unwrap(),
+            };
+
+            // values of `time` can't be used in the query builder
+            diesel::sql_query("INSERT INTO events VALUES (?, ?, ?, ?)")
+                .bind::<Integer, _>(event.id)
+                .bind::<Date, _>(event.day)
+                .bind::<Time, _>(event.starts_at)
+                .bind::<Timestamp, _>(event.happened_at)
+                .execute(connection)
+                .unwrap();
+
+            assert_eq!(Ok(event), events::table.first(connection));
+            assert_eq!(
+                Ok(("2023-01-31".to_owned(), "12:34:56.789".to_owned())),
+                events::table
+                    .select((
+                        sql::<diesel::sql_types::Text>("day"),
+                        sql::<diesel::sql_types::Text>("starts_at"),
+                    ))
+                    .first::<(String, String)>(connection)
+            );
+        }
+
+        #[test]
+        fn timestamps_are_read_from_all_storage_forms() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let valid_epoch_forms = [
+                "'1970-01-01 00:00'",
+                "'1970-01-01 00:00:00.000'",
+                "'1970-01-01T00:00:00Z'",
+                "'1970-01-01T00:00:00.000000+01:00'",
+                "'2440587.5'",
+                "2440587.5",
+                "0",
+            ];
+            for value in valid_epoch_forms {
+                assert_eq!(epoch(), load_timestamp(connection, value), "{value}");
+            }
+
+            assert_eq!(
+                epoch() + ::chrono::Duration::milliseconds(1_500),
+                load_timestamp(connection, "1.5 / 86400 + 2440587.5")
+            );
+            assert_eq!(
+                NaiveDate::from_ymd_opt(2001, 9, 9)
+                    .unwrap()
+                    .and_hms_opt(1, 46, 40)
+                    .unwrap(),
+                load_timestamp(connection, "1000000000")
+            );
+            assert!(diesel::select(sql::<Timestamp>("'yesterday'"))
+                .get_result::<NaiveDateTime>(connection)
+                .is_err());
+        }
+
+        #[test]
+        fn dates_and_times_are_read_from_all_storage_forms() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let day = NaiveDate::from_ymd_opt(2001, 9, 9).unwrap();
+            for value in ["'2001-09-09'", "'2001-09-09 01:46:40'", "1000000000"] {
+                let loaded = diesel::select(sql::<Date>(value)).get_result(connection);
+                assert_eq!(Ok(day), loaded, "{value}");
+            }
+
+            let time = NaiveTime::from_hms_opt(1, 46, 40).unwrap();
+            for value in [
+                "'01:46:40'",
+                "'01:46:40Z'",
+                "'2001-09-09 01:46:40'",
+                "1000000000",
+            ] {
+                let loaded = diesel::select(sql::<Time>(value)).get_result(connection);
+                assert_eq!(Ok(time), loaded, "{value}");
+            }
+        }
+
+        #[test]
+        fn timestamps_with_timezones_keep_their_offset() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let stored = "'1970-01-01 01:00:00+01:00'";
+
+            let utc = diesel::select(sql::<TimestamptzSqlite>(stored)).get_result(connection);
+            assert_eq!(Ok(epoch().and_utc()), utc);
+            let naive = diesel::select(sql::<TimestamptzSqlite>(stored)).get_result(connection);
+            assert_eq!(Ok(epoch()), naive);
+            let with_offset = diesel::select(sql::<TimestamptzSqlite>(stored))
+                .get_result::<DateTime<FixedOffset>>(connection)
+                .unwrap();
+            assert_eq!(3600, with_offset.offset().local_minus_utc());
+            assert_eq!(epoch(), with_offset.naive_utc());
+
+            let without_offset = diesel::select(sql::<TimestamptzSqlite>("'1970-01-01 00:00:00'"))
+                .get_result::<DateTime<Utc>>(connection);
+            assert_eq!(Ok(epoch().and_utc()), without_offset);
+        }
+
+        #[test]
+        fn timestamps_with_timezones_are_written_in_utc() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let offset = FixedOffset::east_opt(3600).unwrap();
+            let stamp = epoch().and_utc().with_timezone(&offset);
+
+            let stored = diesel::sql_query("SELECT ? AS stamp")
+                .bind::<TimestamptzSqlite, _>(stamp)
+                .get_result::<Stamped>(connection)
+                .unwrap();
+            assert_eq!(0, stored.stamp.offset().local_minus_utc());
+            assert_eq!(stamp, stored.stamp);
+
+            let written = diesel::sql_query("SELECT ? AS text")
+                .bind::<TimestamptzSqlite, _>(stamp)
+                .get_result::<Written>(connection);
+            assert_eq!(
+                Ok(Written {
+                    text: "1970-01-01 00:00:00+00:00".to_owned()
+                }),
+                written
+            );
+        }
+    }
+
+    #[cfg(feature = "time")]
+    mod time {
+        use ::time::macros::{date, datetime, offset, time};
+        use ::time::{Date as NaiveDate, OffsetDateTime, PrimitiveDateTime, Time as NaiveTime};
+        use diesel::sql_types::{Date, Text, Time, Timestamp};
+        use lunatic::test;
+
+        use super::*;
+        use crate::sqlite::TimestamptzSqlite;
+
+        table! {
+            events {
+                id -> Integer,
+                day -> Date,
+                starts_at -> Time,
+                happened_at -> Timestamp,
+            }
+        }
+
+        #[derive(Debug, PartialEq, Queryable)]
+        struct Event {
+            id: i32,
+            day: NaiveDate,
+            starts_at: NaiveTime,
+            happened_at: PrimitiveDateTime,
+        }
+
+        #[derive(Debug, PartialEq, QueryableByName)]
+        struct Stamped {
+            #[diesel(sql_type = TimestamptzSqlite)]
+            stamp: OffsetDateTime,
+        }
+
+        #[derive(Debug, PartialEq, QueryableByName)]
+        struct Written {
+            #[diesel(sql_type = Text)]
+            text: String,
+        }
+
+        fn load_timestamp(connection: &mut SqliteConnection, value: &str) -> PrimitiveDateTime {
+            diesel::select(sql::<Timestamp>(value))
+                .get_result(connection)
+                .unwrap_or_else(|e| panic!("failed to load {value}: {e}"))
+        }
+
+        #[test]
+        fn time_values_round_trip() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            connection
+                .batch_execute(
+                    "CREATE TABLE events (id INTEGER PRIMARY KEY, day DATE NOT NULL, \
+                     starts_at TIME NOT NULL, happened_at TIMESTAMP NOT NULL);",
+                )
+                .unwrap();
+            let event = Event {
+                id: 1,
+                day: date!(2023 - 01 - 31),
+                starts_at: time!(12:34:56.789),
+                happened_at: datetime!(2023-01-31 23:59:59.123_456),
+            };
+
+            // values of `time` can't be used in the query builder
+            diesel::sql_query("INSERT INTO events VALUES (?, ?, ?, ?)")
+                .bind::<Integer, _>(event.id)
+                .bind::<Date, _>(event.day)
+                .bind::<Time, _>(event.starts_at)
+                .bind::<Timestamp, _>(event.happened_at)
+                .execute(connection)
+                .unwrap();
+
+            assert_eq!(Ok(event), events::table.first(connection));
+            assert_eq!(
+                Ok((
+                    "2023-01-31".to_owned(),
+                    "12:34:56.789".to_owned(),
+                    "2023-01-31 23:59:59.123456".to_owned()
+                )),
+                events::table
+                    .select((
+                        sql::<Text>("day"),
+                        sql::<Text>("starts_at"),
+                        sql::<Text>("happened_at"),
+                    ))
+                    .first::<(String, String, String)>(connection)
+            );
+        }
+
+        #[test]
+        fn timestamps_are_read_from_all_storage_forms() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let epoch = datetime!(1970-01-01 0:00);
+            let valid_epoch_forms = [
+                "'1970-01-01 00:00'",
+                "'1970-01-01 00:00:00.000'",
+                "'1970-01-01T00:00:00Z'",
+                "'1970-01-01T00:00:00.000000+01:00'",
+                "'2440587.5'",
+                "2440587.5",
+                "0",
+            ];
+            for value in valid_epoch_forms {
+                assert_eq!(epoch, load_timestamp(connection, value), "{value}");
+            }
+
+            assert_eq!(
+                datetime!(1970-01-01 0:00:01.5),
+                load_timestamp(connection, "1.5 / 86400 + 2440587.5")
+            );
+            assert_eq!(
+                datetime!(2001-09-09 1:46:40),
+                load_timestamp(connection, "1000000000")
+            );
+            assert!(diesel::select(sql::<Timestamp>("'yesterday'"))
+                .get_result::<PrimitiveDateTime>(connection)
+                .is_err());
+        }
+
+        #[test]
+        fn dates_and_times_are_read_from_all_storage_forms() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            for value in ["'2001-09-09'", "'2001-09-09 01:46:40'", "1000000000"] {
+                let loaded = diesel::select(sql::<Date>(value)).get_result(connection);
+                assert_eq!(Ok(date!(2001 - 09 - 09)), loaded, "{value}");
+            }
+
+            for value in [
+                "'01:46:40'",
+                "'01:46:40Z'",
+                "'2001-09-09 01:46:40'",
+                "1000000000",
+            ] {
+                let loaded = diesel::select(sql::<Time>(value)).get_result(connection);
+                assert_eq!(Ok(time!(1:46:40)), loaded, "{value}");
+            }
+        }
+
+        #[test]
+        fn timestamps_with_timezones_keep_their_offset() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let stored = "'1970-01-01 01:00:00+01:00'";
+
+            let with_offset = diesel::select(sql::<TimestamptzSqlite>(stored))
+                .get_result::<OffsetDateTime>(connection);
+            assert_eq!(Ok(datetime!(1970-01-01 1:00 +1)), with_offset);
+            let naive = diesel::select(sql::<TimestamptzSqlite>(stored)).get_result(connection);
+            assert_eq!(Ok(datetime!(1970-01-01 0:00)), naive);
+
+            let without_offset = diesel::select(sql::<TimestamptzSqlite>("'1970-01-01 00:00:00'"))
+                .get_result::<OffsetDateTime>(connection);
+            assert_eq!(Ok(OffsetDateTime::UNIX_EPOCH), without_offset);
+        }
+
+        #[test]
+        fn timestamps_with_timezones_are_written_in_utc() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let stamp = OffsetDateTime::UNIX_EPOCH.to_offset(offset!(+1));
+
+            let stored = diesel::sql_query("SELECT ? AS stamp")
+                .bind::<TimestamptzSqlite, _>(stamp)
+                .get_result::<Stamped>(connection)
+                .unwrap();
+            assert!(stored.stamp.offset().is_utc());
+            assert_eq!(stamp, stored.stamp);
+
+            let written = diesel::sql_query("SELECT ? AS text")
+                .bind::<TimestamptzSqlite, _>(stamp)
+                .get_result::<Written>(connection);
+            assert_eq!(
+                Ok(Written {
+                    text: "1970-01-01 00:00:00+00:00".to_owned()
+                }),
+                written
+            );
+        }
+
+        #[cfg(feature = "chrono")]
+        #[test]
+        fn values_are_written_like_chrono_does() {
+            #[derive(QueryableByName)]
+            struct ChronoStamp {
+                #[diesel(sql_type = Timestamp)]
+                stamp: ::chrono::NaiveDateTime,
+            }
+
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let chrono_day = ::chrono::NaiveDate::from_ymd_opt(2023, 1, 31).unwrap();
+            let subseconds = [0, 789_000, 123_456, 1];
+            for micros in subseconds {
+                let chrono_value = chrono_day.and_hms_micro_opt(12, 34, 56, micros).unwrap();
+                let time_value =
+                    datetime!(2023-01-31 12:34:56) + ::time::Duration::microseconds(micros.into());
+
+                let written_by_chrono = diesel::select(chrono_value.into_sql::<Timestamp>())
+                    .get_result::<String>(connection);
+                let written_by_time = diesel::sql_query("SELECT ? AS text")
+                    .bind::<Timestamp, _>(time_value)
+                    .get_result::<Written>(connection)
+                    .map(|written| written.text);
+                assert_eq!(written_by_chrono, written_by_time);
+
+                let read_by_time = diesel::select(chrono_value.into_sql::<Timestamp>())
+                    .get_result::<PrimitiveDateTime>(connection);
+                assert_eq!(Ok(time_value), read_by_time);
+                let read_by_chrono = diesel::sql_query("SELECT ? AS stamp")
+                    .bind::<Timestamp, _>(time_value)
+                    .get_result::<ChronoStamp>(connection)
+                    .map(|read| read.stamp);
+                assert_eq!(Ok(chrono_value), read_by_chrono);
+            }
+        }
+    }
+
+    #[cfg(feature = "serde_json")]
+    mod json {
+        use diesel::sql_types::Text;
+        use lunatic::test;
+        use serde_json::json;
+
+        use crate::dsl::json;
+
+        use super::*;
+
+        table! {
+            documents {
+                id -> Integer,
+                data -> Json,
+            }
+        }
+
+        fn setup_documents(connection: &mut SqliteConnection) {
+            connection
+                .batch_execute(
+                    "CREATE TABLE documents (id INTEGER PRIMARY KEY, data JSON NOT NULL);",
+                )
+                .unwrap();
+            diesel::insert_into(documents::table)
+                .values(&vec![
+                    (
+                        documents::id.eq(1),
+                        documents::data.eq(json(
+                            json!({"name": "Jack", "age": 42, "tags": ["a", "b"]}).to_string(),
+                        )),
+                    ),
+                    (
+                        documents::id.eq(2),
+                        documents::data.eq(json(json!(7).to_string())),
+                    ),
+                ])
+                .execute(connection)
+                .unwrap();
+        }
+
+        #[test]
+        fn json_values_round_trip() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            setup_documents(connection);
+
+            assert_eq!(
+                Ok(vec![
+                    json!({"name": "Jack", "age": 42, "tags": ["a", "b"]}),
+                    json!(7),
+                ]),
+                documents::table
+                    .select(documents::data)
+                    .order(documents::id)
+                    .load::<serde_json::Value>(connection)
+            );
+        }
+
+        #[test]
+        fn json_values_are_extracted_as_sql_values() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            setup_documents(connection);
+            let document = documents::table.find(1);
+
+            let query = document.select((
+                documents::data.json_extract::<Text, _>("$.name"),
+                documents::data.retrieve_as_value::<Integer, _>("$.age"),
+                documents::data.retrieve_as_value::<Text, _>("$.missing"),
+            ));
+            assert_eq!(
+                "SELECT json_extract(`documents`.`data`, ?), (`documents`.`data` ->> ?), \
+                 (`documents`.`data` ->> ?) FROM `documents` WHERE (`documents`.`id` = ?) \
+                 -- binds: [\"$.name\", \"$.age\", \"$.missing\", 1]",
+                diesel::debug_query::<Sqlite, _>(&query).to_string()
+            );
+            assert_eq!(
+                Ok((Some("Jack".to_owned()), Some(42), None)),
+                query.first::<(Option<String>, Option<i32>, Option<String>)>(connection)
+            );
+        }
+
+        #[test]
+        fn json_values_are_retrieved_as_json() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            setup_documents(connection);
+
+            let retrieved = documents::table
+                .find(1)
+                .select((
+                    documents::data.retrieve_as_object("$.name"),
+                    documents::data.retrieve_as_object("$.tags"),
+                    documents::data.retrieve_as_object("$.missing"),
+                ))
+                .first(connection);
+            assert_eq!(
+                Ok((
+                    Some(json!("Jack")),
+                    Some(json!(["a", "b"])),
+                    None::<serde_json::Value>
+                )),
+                retrieved
+            );
+        }
+
+        #[test]
+        fn json_set_inserts_values_as_json() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            setup_documents(connection);
+
+            diesel::update(documents::table.find(1))
+                .set(documents::data.eq(documents::data.json_set("$.owner", json(json!({"id": 2}).to_string()))))
+                .execute(connection)
+                .unwrap();
+            assert_eq!(
+                Ok(Some(2)),
+                documents::table
+                    .find(1)
+                    .select(documents::data.json_extract::<Integer, _>("$.owner.id"))
+                    .first(connection)
+            );
+        }
+
+        #[test]
+        fn json_each_checks_the_elements_of_arrays() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            setup_documents(connection);
+
+            let query = documents::table.select(documents::id).filter(
+                "b".into_sql::<Text>()
+                    .eq_any(documents::data.retrieve_as_object("$.tags").json_each()),
+            );
+            assert_eq!(
+                "SELECT `documents`.`id` FROM `documents` WHERE (? IN \
+                 (SELECT value FROM json_each((`documents`.`data` -> ?)))) \
+                 -- binds: [\"b\", \"$.tags\"]",
+                diesel::debug_query::<Sqlite, _>(&query).to_string()
+            );
+            assert_eq!(Ok(vec![1]), query.load::<i32>(connection));
+        }
+    }
+
+    #[cfg(feature = "uuid")]
+    mod uuid {
+        use ::uuid::Uuid;
+        use diesel::sql_types::{Binary, Text};
+        use lunatic::test;
+
+        use super::*;
+        use crate::sqlite::UuidSqlite;
+
+        table! {
+            use diesel::sql_types::*;
+            use crate::sqlite::UuidSqlite;
+
+            accounts {
+                id -> UuidSqlite,
+                name -> Text,
+                invited_by -> Nullable<UuidSqlite>,
+            }
+        }
+
+        #[derive(Debug, PartialEq, Queryable)]
+        struct Account {
+            id: Uuid,
+            name: String,
+            invited_by: Option<Uuid>,
+        }
+
+        #[derive(Debug, PartialEq, QueryableByName)]
+        struct Name {
+            #[diesel(sql_type = Text)]
+            name: String,
+        }
+
+        #[derive(Debug, PartialEq, QueryableByName)]
+        struct Written {
+            #[diesel(sql_type = Text)]
+            text: String,
+        }
+
+        const HYPHENATED: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+        fn uuid() -> Uuid {
+            Uuid::parse_str(HYPHENATED).unwrap()
+        }
+
+        #[test]
+        fn uuids_round_trip_as_blobs() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            connection
+                .batch_execute(
+                    "CREATE TABLE accounts (
+                        id BLOB PRIMARY KEY,
+                        name TEXT NOT NULL,
+                        invited_by BLOB
+                    )",
+                )
+                .unwrap();
+            let bob = Uuid::from_u128(1);
+            let account = Account {
+                id: uuid(),
+                name: "alice".into(),
+                invited_by: Some(bob),
+            };
+
+            diesel::sql_query("INSERT INTO accounts VALUES (?, ?, ?)")
+                .bind::<UuidSqlite, _>(account.id)
+                .bind::<Text, _>(&account.name)
+                .bind::<Nullable<UuidSqlite>, _>(account.invited_by)
+                .execute(connection)
+                .unwrap();
+
+            assert_eq!(Ok(account), accounts::table.first(connection));
+            assert_eq!(
+                Ok(vec![Name {
+                    name: "alice".to_owned()
+                }]),
+                diesel::sql_query("SELECT name FROM accounts WHERE invited_by = ? AND id != ?")
+                    .bind::<UuidSqlite, _>(bob)
+                    .bind::<UuidSqlite, _>(bob)
+                    .load(connection)
+            );
+            assert_eq!(
+                Ok((uuid(), "blob".to_owned(), 16)),
+                accounts::table
+                    .select((
+                        accounts::id,
+                        sql::<Text>("typeof(id)"),
+                        sql::<Integer>("length(id)"),
+                    ))
+                    .first::<(Uuid, String, i32)>(connection)
+            );
+        }
+
+        #[test]
+        fn uuids_are_written_as_hyphenated_text() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+
+            assert_eq!(
+                Ok(Written {
+                    text: HYPHENATED.to_owned()
+                }),
+                diesel::sql_query("SELECT ? AS text")
+                    .bind::<Text, _>(uuid())
+                    .get_result(connection)
+            );
+        }
+
+        #[test]
+        fn uuids_are_read_from_either_storage_form() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            let blob = "X'67e5504410b1426f9247bb680e5fe0c8'";
+            let text = "'67e55044-10b1-426f-9247-bb680e5fe0c8'";
+
+            for value in [blob, text] {
+                assert_eq!(
+                    Ok(uuid()),
+                    diesel::select(sql::<Binary>(value)).get_result(connection),
+                    "{value}"
+                );
+                assert_eq!(
+                    Ok(uuid()),
+                    diesel::select(sql::<Text>(value)).get_result(connection),
+                    "{value}"
+                );
+            }
+            assert!(diesel::select(sql::<Binary>("X'67e55044'"))
+                .get_result::<Uuid>(connection)
+                .is_err());
+            assert!(diesel::select(sql::<Text>("'not a uuid'"))
+                .get_result::<Uuid>(connection)
+                .is_err());
+        }
+    }
+
+    mod migrations {
+        use diesel::migration::MigrationVersion;
+        use diesel::sql_types::BigInt;
+        use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+        use lunatic::test;
+
+        use super::*;
+
+        const MIGRATIONS: EmbeddedMigrations = embed_migrations!("test_migrations/sqlite");
+
+        table! {
+            users {
+                id -> Integer,
+                name -> Text,
+            }
+        }
+
+        table! {
+            posts {
+                id -> Integer,
+                user_id -> Integer,
+                title -> Text,
+                updated_at -> Text,
+            }
+        }
+
+        fn versions(versions: Vec<MigrationVersion>) -> Vec<String> {
+            versions.iter().map(ToString::to_string).collect()
+        }
+
+        fn tables(connection: &mut SqliteConnection) -> QueryResult<Vec<String>> {
+            diesel::select(sql::<Text>(
+                "name FROM sqlite_master \
+                 WHERE type = 'table' AND name <> '__diesel_schema_migrations' ORDER BY name",
+            ))
+            .load(connection)
+        }
+
+        #[test]
+        fn pending_migrations_are_run() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            assert!(connection.has_pending_migration(MIGRATIONS).unwrap());
+
+            let run = connection.run_pending_migrations(MIGRATIONS).unwrap();
+            assert_eq!(vec!["20230101000000", "20230102000000"], versions(run));
+            assert_eq!(
+                Ok(vec!["Sean".to_string()]),
+                users::table.select(users::name).load(connection)
+            );
+            assert_eq!(Ok(0), posts::table.count().get_result(connection));
+            assert!(!connection.has_pending_migration(MIGRATIONS).unwrap());
+            assert!(connection
+                .run_pending_migrations(MIGRATIONS)
+                .unwrap()
+                .is_empty());
+        }
+
+        #[test]
+        fn applied_migrations_are_read_from_the_migrations_table() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            assert!(connection.applied_migrations().unwrap().is_empty());
+
+            connection.run_pending_migrations(MIGRATIONS).unwrap();
+            assert_eq!(
+                vec!["20230102000000", "20230101000000"],
+                versions(connection.applied_migrations().unwrap())
+            );
+            assert_eq!(
+                Ok(2),
+                diesel::select(sql::<BigInt>(
+                    "COUNT(*) FROM __diesel_schema_migrations WHERE run_on IS NOT NULL"
+                ))
+                .get_result::<i64>(connection)
+            );
+        }
+
+        #[test]
+        fn migrations_can_manage_updated_at() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            connection.run_pending_migrations(MIGRATIONS).unwrap();
+            diesel::insert_into(posts::table)
+                .values((
+                    posts::user_id.eq(1),
+                    posts::title.eq("first"),
+                    posts::updated_at.eq("2000-01-01 00:00:00"),
+                ))
+                .execute(connection)
+                .unwrap();
+
+            diesel::update(posts::table)
+                .set(posts::title.eq("edited"))
+                .execute(connection)
+                .unwrap();
+            let updated_at = posts::table
+                .select(posts::updated_at)
+                .get_result::<String>(connection)
+                .unwrap();
+            assert_ne!("2000-01-01 00:00:00", updated_at);
+        }
+
+        #[test]
+        fn last_migration_is_reverted() {
+            let connection = &mut SqliteConnection::establish(":memory:").unwrap();
+            connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+            let reverted = connection.revert_last_migration(MIGRATIONS).unwrap();
+            assert_eq!("20230102000000", reverted.to_string());
+            assert_eq!(
+                vec!["20230101000000"],
+                versions(connection.applied_migrations().unwrap())
+            );
+            assert_eq!(Ok(vec!["users".to_string()]), tables(connection));
+            assert_eq!(Ok(1), users::table.count().get_result(connection));
+
+            connection.revert_last_migration(MIGRATIONS).unwrap();
+            assert!(connection.applied_migrations().unwrap().is_empty());
+            assert_eq!(Ok(Vec::new()), tables(connection));
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    mod pool {
+        use std::time::Duration;
+
+        use diesel::sql_types::{BigInt, Bool, Integer};
+        use lunatic::{test, Mailbox, Process};
+
+        use super::*;
+        use crate::sqlite::pool::{Pool, PoolConfig, PoolError, PoolState};
+
+        fn count_notes(connection: &mut SqliteConnection, (): ()) -> QueryResult<i64> {
+            sql::<BigInt>("SELECT COUNT(*) FROM notes").get_result(connection)
+        }
+
+        #[test]
+        fn leased_connections_run_functions_in_their_process() {
+            let pool = PoolConfig::new(":memory:").max_size(2).build().unwrap();
+            assert_eq!(
+                PoolState {
+                    connections: 2,
+                    idle_connections: 2
+                },
+                pool.state()
+            );
+
+            let connection = pool.get().unwrap();
+            let sum = connection.run(41, |connection, n: i32| {
+                diesel::select(n.into_sql::<Integer>() + 1).get_result::<i32>(connection)
+            });
+            assert_eq!(Ok(42), sum);
+            assert_eq!(Ok(()), connection.ping());
+            assert!(!connection.is_broken());
+            assert_eq!(1, pool.state().idle_connections);
+
+            drop(connection);
+            assert_eq!(2, pool.state().idle_connections);
+        }
+
+        #[test]
+        fn errors_of_functions_are_returned() {
+            let pool = PoolConfig::new(":memory:").max_size(1).build().unwrap();
+            let connection = pool.get().unwrap();
+
+            match connection.run((), count_notes) {
+                Err(Error::DatabaseError(DatabaseErrorKind::Unknown, info)) => {
+                    assert!(info.message().contains("no such table"))
+                }
+                result => panic!("unexpected result {result:?}"),
+            }
+            let missing = connection.run((), |connection, ()| {
+                diesel::select(1.into_sql::<Integer>())
+                    .filter(false.into_sql::<Bool>())
+                    .get_result::<i32>(connection)
+            });
+            assert_eq!(Err(Error::NotFound), missing);
+        }
+
+        #[test]
+        fn connections_are_reused() {
+            let pool = PoolConfig::new(":memory:").max_size(1).build().unwrap();
+            pool.get()
+                .unwrap()
+                .run((), |connection, ()| {
+                    connection.batch_execute("CREATE TABLE notes (text TEXT)")
+                })
+                .unwrap();
+            // every connection has a database of its own
+            assert_eq!(Ok(0), pool.get().unwrap().run((), count_notes));
+        }
+
+        #[test]
+        fn expired_connections_are_replaced() {
+            let pool = PoolConfig::new(":memory:")
+                .max_size(1)
+                .max_lifetime(Some(Duration::ZERO))
+                .build()
+                .unwrap();
+            pool.get()
+                .unwrap()
+                .run((), |connection, ()| {
+                    connection.batch_execute("CREATE TABLE notes (text TEXT)")
+                })
+                .unwrap();
+            assert!(pool.get().unwrap().run((), count_notes).is_err());
+            assert_eq!(1, pool.state().connections);
+        }
+
+        #[test]
+        fn expired_idle_connections_are_replaced() {
+            let pool = PoolConfig::new(":memory:")
+                .max_size(2)
+                .max_lifetime(Some(Duration::ZERO))
+                .build()
+                .unwrap();
+            // both idle connections expired, the caller gets a new one
+            let _connection = pool.get().unwrap();
+            assert_eq!(2, pool.state().connections);
+        }
+
+        #[test]
+        fn checkouts_time_out_while_all_connections_are_leased() {
+            let pool = PoolConfig::new(":memory:")
+                .max_size(1)
+                .checkout_timeout(Duration::from_millis(10))
+                .build()
+                .unwrap();
+            let connection = pool.get().unwrap();
+            assert_eq!(Some(PoolError::Timeout), pool.get().err());
+
+            drop(connection);
+            assert!(pool.get().is_ok());
+        }
+
+        #[test]
+        fn returned_connections_are_pinged_for_waiting_callers(mailbox: Mailbox<i32>) {
+            let pool = PoolConfig::new(":memory:").max_size(1).build().unwrap();
+            let connection = pool.get().unwrap();
+            Process::spawn_link(
+                (pool.clone(), mailbox.this()),
+                |(pool, parent): (Pool, Process<i32>), _: Mailbox<()>| {
+                    let answer = pool.get().unwrap().run((), |connection, ()| {
+                        diesel::select(42.into_sql::<Integer>()).get_result::<i32>(connection)
+                    });
+                    parent.send(answer.unwrap());
+                },
+            );
+            // the other process waits for the only connection
+            assert_eq!(
+                PoolState {
+                    connections: 1,
+                    idle_connections: 0
+                },
+                pool.state()
+            );
+            drop(connection);
+            assert_eq!(42, mailbox.receive());
+        }
+
+        #[test]
+        fn connections_are_handed_out_untested() {
+            let pool = PoolConfig::new(":memory:")
+                .max_size(1)
+                .test_on_check_out(false)
+                .build()
+                .unwrap();
+            for _ in 0..2 {
+                assert_eq!(Ok(()), pool.get().unwrap().ping());
+            }
+        }
+
+        #[test]
+        fn connections_dying_while_leased_are_replaced(mailbox: Mailbox<()>) {
+            let pool = PoolConfig::new(":memory:").max_size(1).build().unwrap();
+            Process::spawn(
+                (pool.clone(), mailbox.this()),
+                |(pool, parent): (Pool, Process<()>), _: Mailbox<()>| {
+                    let connection = pool.get().unwrap();
+                    parent.send(());
+                    let _ = connection.run((), |_, ()| -> QueryResult<()> {
+                        panic!("the process of the connection dies")
+                    });
+                },
+            );
+            mailbox.receive();
+            // waits until the connection is replaced
+            let answer = pool.get().unwrap().run((), |connection, ()| {
+                diesel::select(42.into_sql::<Integer>()).get_result::<i32>(connection)
+            });
+            assert_eq!(Ok(42), answer);
+            assert_eq!(1, pool.state().connections);
+        }
+
+        #[test]
+        fn pools_fail_to_start_without_a_database() {
+            let pool = PoolConfig::new("/does/not/exist/app.db")
+                .max_size(1)
+                .build();
+            assert!(matches!(pool, Err(PoolError::ConnectionError(_))));
+        }
+
+        #[test]
+        fn pools_are_shared_with_other_processes(mailbox: Mailbox<i32>) {
+            let pool = PoolConfig::new(":memory:").max_size(1).build().unwrap();
+            Process::spawn_link(
+                (pool, mailbox.this()),
+                |(pool, parent): (Pool, Process<i32>), _: Mailbox<()>| {
+                    let connection = pool.get().unwrap();
+                    let answer = connection.run((), |connection, ()| {
+                        diesel::select(42.into_sql::<Integer>()).get_result::<i32>(connection)
+                    });
+                    parent.send(answer.unwrap());
+                },
+            );
+            assert_eq!(42, mailbox.receive());
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    mod server {
+        use diesel::sql_types::Integer;
+        use lunatic::{test, Mailbox, Process};
+
+        use super::*;
+        use crate::sqlite::server::{DbServer, DbServerConfig};
+
+        table! {
+            posts {
+                id -> Integer,
+                title -> Text,
+                published -> Bool,
+            }
+        }
+
+        #[derive(Debug, PartialEq, Queryable, QueryableByName)]
+        #[diesel(table_name = posts)]
+        struct Post {
+            id: i32,
+            title: String,
+            published: bool,
+        }
+
+        #[derive(Insertable)]
+        #[diesel(table_name = posts)]
+        struct NewPost<'a> {
+            id: i32,
+            title: &'a str,
+            published: Option<bool>,
+        }
+
+        fn publish(connection: &mut SqliteConnection, id: i32) -> QueryResult<usize> {
+            diesel::update(posts::table.find(id))
+                .set(posts::published.eq(true))
+                .execute(connection)
+        }
+
+        fn start_server() -> DbServer {
+            let server = DbServerConfig::new(":memory:")
+                .register("publish", publish)
+                .register("count", |connection, ()| {
+                    posts::table.count().get_result::<i64>(connection)
+                })
+                .build()
+                .unwrap();
+            server
+                .execute(diesel::sql_query(
+                    "CREATE TABLE posts (
+                        id INTEGER PRIMARY KEY,
+                        title TEXT NOT NULL,
+                        published BOOLEAN NOT NULL DEFAULT 0
+                    )",
+                ))
+                .unwrap();
+            server
+        }
+
+        #[test]
+        fn queries_run_in_the_server_process() {
+            let server = start_server();
+            for (id, title) in [(1, "first"), (2, "second")] {
+                let inserted = server.execute(
+                    diesel::insert_into(posts::table)
+                        .values((posts::id.eq(id), posts::title.eq(title))),
+                );
+                assert_eq!(Ok(1), inserted);
+            }
+
+            assert_eq!(
+                Ok(vec!["first".to_string()]),
+                server.load::<String, _>(posts::table.select(posts::title).filter(posts::id.eq(1)))
+            );
+            assert_eq!(
+                Ok(Post {
+                    id: 2,
+                    title: "second".to_string(),
+                    published: false
+                }),
+                server.get_result(posts::table.find(2))
+            );
+            let by_name = server.load::<Post, _>(
+                diesel::sql_query("SELECT * FROM posts WHERE title = ?").bind::<Text, _>("first"),
+            );
+            assert_eq!(
+                Ok(vec![1]),
+                by_name.map(|posts| posts.iter().map(|post| post.id).collect())
+            );
+            assert_eq!(
+                Err(Error::NotFound),
+                server.get_result::<Post, _>(posts::table.find(3))
+            );
+        }
+
+        #[test]
+        fn errors_of_queries_are_returned() {
+            let server = start_server();
+            server
+                .execute(
+                    diesel::insert_into(posts::table)
+                        .values((posts::id.eq(1), posts::title.eq("first"))),
+                )
+                .unwrap();
+            match server.execute(
+                diesel::insert_into(posts::table)
+                    .values((posts::id.eq(1), posts::title.eq("again"))),
+            ) {
+                Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {}
+                result => panic!("unexpected result {result:?}"),
+            }
+            // the rows are deserialized by the calling process
+            assert!(matches!(
+                server.load::<Post, _>(diesel::sql_query("SELECT id, title FROM posts")),
+                Err(Error::DeserializationError(_))
+            ));
+        }
+
+        #[test]
+        fn errors_keep_their_kind_across_processes() {
+            let server = start_server();
+
+            match server.run((), |_, ()| {
+                Err::<(), _>(Error::InvalidCString(
+                    std::ffi::CString::new("a\0b").unwrap_err(),
+                ))
+            }) {
+                Err(Error::InvalidCString(e)) => assert_eq!(1, e.nul_position()),
+                result => panic!("unexpected result {result:?}"),
+            }
+            match server.run((), |_, ()| {
+                Err::<(), _>(Error::RollbackErrorOnCommit {
+                    rollback_error: Box::new(Error::BrokenTransactionManager),
+                    commit_error: Box::new(Error::NotFound),
+                })
+            }) {
+                Err(Error::RollbackErrorOnCommit {
+                    rollback_error,
+                    commit_error,
+                }) => {
+                    assert_eq!(Error::BrokenTransactionManager, *rollback_error);
+                    assert_eq!(Error::NotFound, *commit_error);
+                }
+                result => panic!("unexpected result {result:?}"),
+            }
+        }
+
+        #[test]
+        fn batch_inserts_run_in_the_server_process() {
+            let server = start_server();
+            let new_post = |id, published| NewPost {
+                id,
+                title: "post",
+                published,
+            };
+
+            let batch = vec![new_post(1, None), new_post(2, None)];
+            assert_eq!(
+                Ok(2),
+                server.insert_batch(diesel::insert_into(posts::table).values(&batch))
+            );
+            // rows leaving out different columns are inserted one by one
+            let batch = vec![new_post(3, Some(true)), new_post(4, None)];
+            assert_eq!(
+                Ok(2),
+                server.insert_batch(diesel::insert_into(posts::table).values(&batch))
+            );
+            assert_eq!(
+                Ok(vec![false, false, true, false]),
+                server.load::<bool, _>(posts::table.select(posts::published).order(posts::id))
+            );
+
+            // and rolled back together
+            let batch = vec![new_post(5, Some(true)), new_post(1, None)];
+            match server.insert_batch(diesel::insert_into(posts::table).values(&batch)) {
+                Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {}
+                result => panic!("unexpected result {result:?}"),
+            }
+            assert_eq!(Ok(4), server.get_result::<i64, _>(posts::table.count()));
+        }
+
+        #[test]
+        fn registered_functions_are_called_by_name() {
+            let server = start_server();
+            server
+                .execute(
+                    diesel::insert_into(posts::table)
+                        .values((posts::id.eq(1), posts::title.eq("first"))),
+                )
+                .unwrap();
+
+            assert_eq!(Ok(1), server.call::<_, usize>("publish", 1));
+            assert_eq!(
+                Ok(true),
+                server.get_result(posts::table.find(1).select(posts::published))
+            );
+            assert_eq!(Ok(1), server.call::<_, i64>("count", ()));
+            assert_eq!(
+                Ok(2),
+                server.run(1, |connection, n: i32| {
+                    diesel::select(n.into_sql::<Integer>() + 1).get_result::<i32>(connection)
+                })
+            );
+
+            assert!(matches!(
+                server.call::<_, usize>("unpublish", 1),
+                Err(Error::QueryBuilderError(_))
+            ));
+            // the argument of `publish` is an `i32`
+            assert!(matches!(
+                server.call::<_, usize>("publish", "1"),
+                Err(Error::DeserializationError(_))
+            ));
+            // a `u32` would decode from the `usize` it returns
+            assert!(matches!(
+                server.call::<_, u32>("publish", 1),
+                Err(Error::DeserializationError(_))
+            ));
+        }
+
+        #[test]
+        fn queries_unsafe_to_cache_are_not_cached_by_the_server() {
+            let server = start_server();
+            let cached_statements = || {
+                server
+                    .run((), |connection, ()| Ok(connection.statement_cache.len()))
+                    .unwrap()
+            };
+            assert_eq!(0, cached_statements());
+
+            server
+                .load::<Post, _>(posts::table.filter(posts::id.eq(1)))
+                .unwrap();
+            assert_eq!(1, cached_statements());
+
+            for ids in [vec![1], vec![1, 2], vec![1, 2, 3]] {
+                server
+                    .load::<Post, _>(posts::table.filter(posts::id.eq_any(ids)))
+                    .unwrap();
+            }
+            server
+                .load::<Post, _>(diesel::sql_query("SELECT * FROM posts"))
+                .unwrap();
+            assert_eq!(1, cached_statements());
+        }
+
+        #[test]
+        fn servers_fail_to_start_without_a_database() {
+            let server = DbServerConfig::new("/does/not/exist/app.db").build();
+            assert!(matches!(server, Err(ConnectionError::BadConnection(_))));
+        }
+
+        #[test]
+        fn servers_are_shared_with_other_processes(mailbox: Mailbox<usize>) {
+            let server = start_server();
+            for (id, title) in [(1, "first"), (2, "second")] {
+                Process::spawn_link(
+                    (server.clone(), mailbox.this(), id, title.to_string()),
+                    |(server, parent, id, title): (DbServer, Process<usize>, i32, String),
+                     _: Mailbox<()>| {
+                        let inserted = server
+                            .execute(
+                                diesel::insert_into(posts::table)
+                                    .values((posts::id.eq(id), posts::title.eq(title))),
+                            )
+                            .unwrap();
+                        parent.send(inserted);
+                    },
+                );
+            }
+            assert_eq!(2, mailbox.receive() + mailbox.receive());
+            assert_eq!(Ok(2), server.call::<_, i64>("count", ()));
+        }
+    }
 }
@@ -1,85 +1,222 @@
-// //! Sqlite specific expression methods.
+//! Sqlite specific expression methods.
 
-// use diesel::dsl;
-// use diesel::expression::AsExpression;
-// use diesel::operators::*;
-// use diesel::sql_types::SqlType;
-// use diesel::Expression;
+use diesel::expression::AsExpression;
+use diesel::sql_types::SqlType;
+#[cfg(feature = "serde_json")]
+use diesel::sql_types::{Json, SingleValue, Text};
+use diesel::Expression;
 
-// use super::helper_types::Is;
+#[cfg(feature = "serde_json")]
+use self::private::JsonOrNullableJson;
+use super::grouped::Grouped;
+#[cfg(feature = "serde_json")]
+use super::json::{ExtractValue, JsonEach, JsonSet};
+use super::operators::{Is, IsNot};
+use crate::dsl;
 
-// /// Sqlite specific methods which are present on all expressions.
-// pub trait SqliteExpressionMethods: Expression + Sized {
-//     /// Creates a Sqlite `IS` expression.
-//     ///
-//     /// The `IS` operator work like = except when one or both of the operands are NULL.
-//     /// In this case, if both operands are NULL, then the `IS` operator evaluates to true.
-//     /// If one operand is NULL and the other is not, then the `IS` operator evaluates to false.
-//     /// It is not possible for an `IS` expression to evaluate to NULL.
-//     ///
-//     /// # Example
-//     ///
-//     /// ```rust
-//     /// # include!("../../doctest_setup.rs");
-//     /// #
-//     /// # fn main() {
-//     /// #     run_test().unwrap();
-//     /// # }
-//     /// #
-//     /// # fn run_test() -> QueryResult<()> {
-//     /// #     use schema::animals::dsl::*;
-//     /// #     let connection = &mut establish_connection();
-//     /// let jack_is_a_dog = animals
-//     ///     .select(name)
-//     ///     .filter(species.is("dog"))
-//     ///     .get_results::<Option<String>>(connection)?;
-//     /// assert_eq!(vec![Some("Jack".to_string())], jack_is_a_dog);
-//     /// #     Ok(())
-//     /// # }
-//     /// ```
-//     fn is<T>(self, other: T) -> dsl::Is<Self, T>
-//     where
-//         Self::SqlType: SqlType,
-//         T: AsExpression<Self::SqlType>,
-//     {
-//         Grouped(Is::new(self, other.as_expression()))
-//     }
+/// Sqlite specific methods which are present on all expressions.
+pub trait SqliteExpressionMethods: Expression + Sized {
+    /// Creates a Sqlite `IS` expression.
+    ///
+    /// The `IS` operator work like = except when one or both of the operands are NULL.
+    /// In this case, if both operands are NULL, then the `IS` operator evaluates to true.
+    /// If one operand is NULL and the other is not, then the `IS` operator evaluates to false.
+    /// It is not possible for an `IS` expression to evaluate to NULL.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # include!("../../../doctest_setup.rs");
+    /// #
+    /// # fn main() {
+    /// #     run_test().unwrap();
+    /// # }
+    /// #
+    /// # fn run_test() -> QueryResult<()> {
+    /// #     use schema::animals::dsl::*;
+    /// #     let connection = &mut establish_connection();
+    /// let jack_is_a_dog = animals
+    ///     .select(name)
+    ///     .filter(species.is("dog"))
+    ///     .get_results::<Option<String>>(connection)?;
+    /// assert_eq!(vec![Some("Jack".to_string())], jack_is_a_dog);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    fn is<T>(self, other: T) -> dsl::Is<Self, T>
+    where
+        Self::SqlType: SqlType,
+        T: AsExpression<Self::SqlType>,
+    {
+        Grouped(Is::new(self, other.as_expression()))
+    }
 
-//     /// Creates a Sqlite `IS NOT` expression.
-//     ///
-//     /// The `IS NOT` operator work like != except when one or both of the operands are NULL.
-//     /// In this case, if both operands are NULL, then the `IS NOT` operator evaluates to false.
-//     /// If one operand is NULL and the other is not, then the `IS NOT` operator is true.
-//     /// It is not possible for an `IS NOT` expression to evaluate to NULL.
-//     ///
-//     /// # Example
-//     ///
-//     /// ```rust
-//     /// # include!("../../doctest_setup.rs");
-//     /// #
-//     /// # fn main() {
-//     /// #     run_test().unwrap();
-//     /// # }
-//     /// #
-//     /// # fn run_test() -> QueryResult<()> {
-//     /// #     use schema::animals::dsl::*;
-//     /// #     let connection = &mut establish_connection();
-//     /// let jack_is_not_a_spider = animals
-//     ///     .select(name)
-//     ///     .filter(species.is_not("spider"))
-//     ///     .get_results::<Option<String>>(connection)?;
-//     /// assert_eq!(vec![Some("Jack".to_string())], jack_is_not_a_spider);
-//     /// #     Ok(())
-//     /// # }
-//     /// ```
-//     #[allow(clippy::wrong_self_convention)] // This is named after the sql operator
-//     fn is_not<T>(self, other: T) -> dsl::IsNot<Self, T>
-//     where
-//         Self::SqlType: SqlType,
-//         T: AsExpression<Self::SqlType>,
-//     {
-//         Grouped(IsNot::new(self, other.as_expression()))
-//     }
-// }
+    /// Creates a Sqlite `IS NOT` expression.
+    ///
+    /// The `IS NOT` operator work like != except when one or both of the operands are NULL.
+    /// In this case, if both operands are NULL, then the `IS NOT` operator evaluates to false.
+    /// If one operand is NULL and the other is not, then the `IS NOT` operator is true.
+    /// It is not possible for an `IS NOT` expression to evaluate to NULL.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # include!("../../../doctest_setup.rs");
+    /// #
+    /// # fn main() {
+    /// #     run_test().unwrap();
+    /// # }
+    /// #
+    /// # fn run_test() -> QueryResult<()> {
+    /// #     use schema::animals::dsl::*;
+    /// #     let connection = &mut establish_connection();
+    /// let jack_is_not_a_spider = animals
+    ///     .select(name)
+    ///     .filter(species.is_not("spider"))
+    ///     .get_results::<Option<String>>(connection)?;
+    /// assert_eq!(vec![Some("Jack".to_string())], jack_is_not_a_spider);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[allow(clippy::wrong_self_convention)] // This is named after the sql operator
+    fn is_not<T>(self, other: T) -> dsl::IsNot<Self, T>
+    where
+        Self::SqlType: SqlType,
+        T: AsExpression<Self::SqlType>,
+    {
+        Grouped(IsNot::new(self, other.as_expression()))
+    }
+}
 
-// impl<T: Expression> SqliteExpressionMethods for T {}
+impl<T: Expression> SqliteExpressionMethods for T {}
+
+/// Sqlite specific methods present on JSON expressions.
+///
+/// These are rendered using the functions and operators of SQLite's builtin
+/// JSON support, paths are given in SQLite's syntax (`$.a.b[1]`).
+#[cfg(feature = "serde_json")]
+pub trait SqliteJsonExpressionMethods: Expression + Sized {
+    /// Creates a SQLite `json_extract(json, path)` expression.
+    ///
+    /// SQLite returns the value at `path` as a SQL value: JSON strings as
+    /// text, numbers as integer or real, booleans as integer and objects or
+    /// arrays as JSON text. The expected SQL type therefore has to be given,
+    /// missing values are returned as `NULL`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let names = documents::table
+    ///     .select(documents::data.json_extract::<Text, _>("$.name"))
+    ///     .load::<Option<String>>(connection)?;
+    /// ```
+    fn json_extract<ST, P>(self, path: P) -> dsl::JsonExtract<Self, P, ST>
+    where
+        ST: SingleValue,
+        P: AsExpression<Text>,
+    {
+        ExtractValue::new(self, path.as_expression())
+    }
+
+    /// Creates a SQLite `->` expression.
+    ///
+    /// This returns the value at `path` as JSON, regardless of whether it is a
+    /// scalar, an object or an array.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let tags = documents::table
+    ///     .select(documents::data.retrieve_as_object("$.tags"))
+    ///     .load::<Option<serde_json::Value>>(connection)?;
+    /// ```
+    fn retrieve_as_object<P>(self, path: P) -> dsl::RetrieveAsObject<Self, P>
+    where
+        P: AsExpression<Text>,
+    {
+        Grouped(super::operators::RetrieveAsObject::new(
+            self,
+            path.as_expression(),
+        ))
+    }
+
+    /// Creates a SQLite `->>` expression.
+    ///
+    /// Like [`json_extract`](Self::json_extract) this returns the value at
+    /// `path` as a SQL value of the given type.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let ages = documents::table
+    ///     .select(documents::data.retrieve_as_value::<Integer, _>("$.age"))
+    ///     .load::<Option<i32>>(connection)?;
+    /// ```
+    fn retrieve_as_value<ST, P>(self, path: P) -> dsl::RetrieveAsValue<Self, P, ST>
+    where
+        ST: SingleValue,
+        P: AsExpression<Text>,
+    {
+        ExtractValue::new(self, path.as_expression())
+    }
+
+    /// Creates a SQLite `json_set(json, path, value)` expression.
+    ///
+    /// The value at `path` is replaced or inserted, the value is passed as
+    /// JSON so that for example objects are not inserted as a string.
+    /// `serde_json` values are passed with [`json`](crate::dsl::json).
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// diesel::update(documents::table.find(1))
+    ///     .set(documents::data.eq(documents::data.json_set("$.age", json(json!(43).to_string()))))
+    ///     .execute(connection)?;
+    /// ```
+    fn json_set<P, V>(self, path: P, value: V) -> dsl::JsonSet<Self, P, V>
+    where
+        P: AsExpression<Text>,
+        V: AsExpression<Json>,
+    {
+        JsonSet::new(self, path.as_expression(), value.as_expression())
+    }
+
+    /// Creates a SQLite `json_each(json)` table-valued function.
+    ///
+    /// It can be used on the right hand side of `eq_any` and `ne_all`, to
+    /// check whether a value is one of the elements of a JSON array or one of
+    /// the values of a JSON object.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let tagged = documents::table
+    ///     .filter("rust".into_sql::<Text>().eq_any(documents::data.retrieve_as_object("$.tags").json_each()))
+    ///     .select(documents::id)
+    ///     .load::<i32>(connection)?;
+    /// ```
+    fn json_each(self) -> dsl::JsonEach<Self> {
+        JsonEach::new(self)
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl<T> SqliteJsonExpressionMethods for T
+where
+    T: Expression,
+    T::SqlType: JsonOrNullableJson,
+{
+}
+
+#[cfg(feature = "serde_json")]
+mod private {
+    use diesel::sql_types::{Json, Nullable};
+
+    /// Marker trait used to implement `SqliteJsonExpressionMethods` on the
+    /// appropriate types. Once coherence takes associated types into account,
+    /// we can remove this trait.
+    pub trait JsonOrNullableJson {}
+
+    impl JsonOrNullableJson for Json {}
+    impl JsonOrNullableJson for Nullable<Json> {}
+}
@@ -0,0 +1,22 @@
+//! SQLite specific functions
+
+use diesel::sql_types::*;
+
+// diesel's macro, as none of these need to be implemented by the guest
+diesel::expression::functions::sql_function! {
+    /// Parses JSON text into a JSON document, rendered as `json(text)`.
+    ///
+    /// This is how `serde_json` values are written through the query builder,
+    /// as diesel only implements `AsExpression<Json>` for them with one of its
+    /// own backends enabled.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// diesel::update(documents::table.find(1))
+    ///     .set(documents::data.eq(json(json!({"age": 43}).to_string())))
+    ///     .execute(connection)?;
+    /// ```
+    #[cfg(feature = "serde_json")]
+    fn json(text: Text) -> Json;
+}
@@ -0,0 +1,28 @@
+use diesel::expression::{Expression, ValidGrouping};
+use diesel::query_builder::{AstPass, QueryFragment, QueryId};
+use diesel::result::QueryResult;
+
+use crate::sqlite::Sqlite;
+
+// diesel keeps its own `Grouped` expression crate private, so the SQLite
+// operators need a local copy to put parentheses around their operands
+#[derive(Debug, Copy, Clone, QueryId, Default, ValidGrouping)]
+pub struct Grouped<T>(pub T);
+
+impl<T: Expression> Expression for Grouped<T> {
+    type SqlType = T::SqlType;
+}
+
+impl<T> QueryFragment<Sqlite> for Grouped<T>
+where
+    T: QueryFragment<Sqlite>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Sqlite>) -> QueryResult<()> {
+        out.push_sql("(");
+        self.0.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+impl_selectable_expression!(Grouped<T>);
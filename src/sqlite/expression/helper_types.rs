@@ -1,8 +1,36 @@
-// use crate::dsl::AsExpr;
-// // use diesel::expression::grouped::Grouped;
+use crate::dsl::AsExpr;
+#[cfg(feature = "serde_json")]
+use crate::dsl::AsExprOf;
+#[cfg(feature = "serde_json")]
+use diesel::sql_types::{Json, Text};
 
-// /// The return type of `lhs.is(rhs)`.
-// pub type Is<Lhs, Rhs> = Grouped<super::operators::Is<Lhs, AsExpr<Rhs, Lhs>>>;
+use super::grouped::Grouped;
 
-// /// The return type of `lhs.is_not(rhs)`.
-// pub type IsNot<Lhs, Rhs> = Grouped<super::operators::IsNot<Lhs, AsExpr<Rhs, Lhs>>>;
+/// The return type of `lhs.is(rhs)`.
+pub type Is<Lhs, Rhs> = Grouped<super::operators::Is<Lhs, AsExpr<Rhs, Lhs>>>;
+
+/// The return type of `lhs.is_not(rhs)`.
+pub type IsNot<Lhs, Rhs> = Grouped<super::operators::IsNot<Lhs, AsExpr<Rhs, Lhs>>>;
+
+/// The return type of `json.json_extract::<ST, _>(path)`.
+#[cfg(feature = "serde_json")]
+pub type JsonExtract<Lhs, Rhs, ST> = super::json::ExtractValue<Lhs, AsExprOf<Rhs, Text>, ST, false>;
+
+/// The return type of `json.retrieve_as_object(path)`.
+#[cfg(feature = "serde_json")]
+pub type RetrieveAsObject<Lhs, Rhs> =
+    Grouped<super::operators::RetrieveAsObject<Lhs, AsExprOf<Rhs, Text>>>;
+
+/// The return type of `json.retrieve_as_value::<ST, _>(path)`.
+#[cfg(feature = "serde_json")]
+pub type RetrieveAsValue<Lhs, Rhs, ST> =
+    super::json::ExtractValue<Lhs, AsExprOf<Rhs, Text>, ST, true>;
+
+/// The return type of `json.json_set(path, value)`.
+#[cfg(feature = "serde_json")]
+pub type JsonSet<Lhs, Path, Value> =
+    super::json::JsonSet<Lhs, AsExprOf<Path, Text>, AsExprOf<Value, Json>>;
+
+/// The return type of `json.json_each()`.
+#[cfg(feature = "serde_json")]
+pub type JsonEach<Expr> = super::json::JsonEach<Expr>;
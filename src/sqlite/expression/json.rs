@@ -0,0 +1,241 @@
+//! Query dsl nodes for the functions of SQLite's JSON support
+//!
+//! `json_extract` and `->>` return the SQL value found at a path, which is
+//! text, an integer, a real or `NULL` depending on the JSON value. The nodes
+//! for them therefore carry the SQL type the caller expects.
+
+use std::marker::PhantomData;
+
+use diesel::expression::array_comparison::{AsInExpression, MaybeEmpty};
+use diesel::expression::{
+    is_aggregate, AppearsOnTable, Expression, MixedAggregates, SelectableExpression,
+    TypedExpressionType, ValidGrouping,
+};
+use diesel::sql_types::{IntoNullable, SingleValue, SqlType};
+
+use crate::query_builder::{AstPass, QueryFragment, QueryId};
+use crate::result::QueryResult;
+use crate::sqlite::Sqlite;
+
+/// The SQL value at a path of a JSON document, rendered as
+/// `json_extract(json, path)` or `json ->> path`
+#[derive(Debug, Clone, Copy)]
+pub struct ExtractValue<J, P, ST, const ARROW_OPERATOR: bool> {
+    json: J,
+    path: P,
+    _sql_type: PhantomData<ST>,
+}
+
+impl<J, P, ST, const ARROW_OPERATOR: bool> ExtractValue<J, P, ST, ARROW_OPERATOR> {
+    pub(crate) fn new(json: J, path: P) -> Self {
+        ExtractValue {
+            json,
+            path,
+            _sql_type: PhantomData,
+        }
+    }
+}
+
+impl<J, P, ST, const ARROW_OPERATOR: bool> Expression for ExtractValue<J, P, ST, ARROW_OPERATOR>
+where
+    J: Expression,
+    P: Expression,
+    ST: SqlType + SingleValue + IntoNullable,
+    ST::Nullable: TypedExpressionType,
+{
+    type SqlType = ST::Nullable;
+}
+
+impl<J, P, ST, const ARROW_OPERATOR: bool> QueryFragment<Sqlite>
+    for ExtractValue<J, P, ST, ARROW_OPERATOR>
+where
+    J: QueryFragment<Sqlite>,
+    P: QueryFragment<Sqlite>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Sqlite>) -> QueryResult<()> {
+        if ARROW_OPERATOR {
+            out.push_sql("(");
+            self.json.walk_ast(out.reborrow())?;
+            out.push_sql(" ->> ");
+            self.path.walk_ast(out.reborrow())?;
+        } else {
+            out.push_sql("json_extract(");
+            self.json.walk_ast(out.reborrow())?;
+            out.push_sql(", ");
+            self.path.walk_ast(out.reborrow())?;
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+// `#[derive(QueryId)]` does not support const generics
+impl<J, P, ST, const ARROW_OPERATOR: bool> QueryId for ExtractValue<J, P, ST, ARROW_OPERATOR>
+where
+    J: QueryId,
+    P: QueryId,
+    ST: 'static,
+{
+    type QueryId = ExtractValue<J::QueryId, P::QueryId, ST, ARROW_OPERATOR>;
+
+    const HAS_STATIC_QUERY_ID: bool = J::HAS_STATIC_QUERY_ID && P::HAS_STATIC_QUERY_ID;
+}
+
+impl<J, P, ST, GB, const ARROW_OPERATOR: bool> ValidGrouping<GB>
+    for ExtractValue<J, P, ST, ARROW_OPERATOR>
+where
+    J: ValidGrouping<GB>,
+    P: ValidGrouping<GB>,
+    J::IsAggregate: MixedAggregates<P::IsAggregate>,
+{
+    type IsAggregate = <J::IsAggregate as MixedAggregates<P::IsAggregate>>::Output;
+}
+
+impl<J, P, ST, QS, const ARROW_OPERATOR: bool> AppearsOnTable<QS>
+    for ExtractValue<J, P, ST, ARROW_OPERATOR>
+where
+    Self: Expression,
+    J: AppearsOnTable<QS>,
+    P: AppearsOnTable<QS>,
+{
+}
+
+impl<J, P, ST, QS, const ARROW_OPERATOR: bool> SelectableExpression<QS>
+    for ExtractValue<J, P, ST, ARROW_OPERATOR>
+where
+    Self: AppearsOnTable<QS>,
+    J: SelectableExpression<QS>,
+    P: SelectableExpression<QS>,
+{
+}
+
+/// A JSON document with the value at a path replaced, rendered as
+/// `json_set(json, path, json(value))`
+///
+/// The value is passed through `json()` so that SQLite inserts it as JSON
+/// instead of as a string.
+#[derive(Debug, Clone, Copy, QueryId, ValidGrouping)]
+pub struct JsonSet<J, P, V> {
+    json: J,
+    path: P,
+    value: V,
+}
+
+impl<J, P, V> JsonSet<J, P, V> {
+    pub(crate) fn new(json: J, path: P, value: V) -> Self {
+        JsonSet { json, path, value }
+    }
+}
+
+impl<J, P, V> Expression for JsonSet<J, P, V>
+where
+    J: Expression,
+    P: Expression,
+    V: Expression,
+{
+    type SqlType = J::SqlType;
+}
+
+impl<J, P, V> QueryFragment<Sqlite> for JsonSet<J, P, V>
+where
+    J: QueryFragment<Sqlite>,
+    P: QueryFragment<Sqlite>,
+    V: QueryFragment<Sqlite>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Sqlite>) -> QueryResult<()> {
+        out.push_sql("json_set(");
+        self.json.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        self.path.walk_ast(out.reborrow())?;
+        out.push_sql(", json(");
+        self.value.walk_ast(out.reborrow())?;
+        out.push_sql("))");
+        Ok(())
+    }
+}
+
+impl_selectable_expression!(JsonSet<J, P, V>);
+
+/// The elements of a JSON array, or the values of a JSON object
+///
+/// `json_each` is a table-valued function, so this is not an expression on its
+/// own. It can be used on the right hand side of `eq_any` and `ne_all`, where
+/// it is rendered as `SELECT value FROM json_each(json)`.
+#[derive(Debug, Clone, Copy)]
+pub struct JsonEach<J> {
+    json: J,
+}
+
+impl<J> JsonEach<J> {
+    pub(crate) fn new(json: J) -> Self {
+        JsonEach { json }
+    }
+}
+
+impl<J, ST> AsInExpression<ST> for JsonEach<J>
+where
+    J: Expression,
+    ST: SqlType + TypedExpressionType,
+{
+    type InExpression = JsonEachValues<J, ST>;
+
+    fn as_in_expression(self) -> Self::InExpression {
+        JsonEachValues {
+            json: self.json,
+            _sql_type: PhantomData,
+        }
+    }
+}
+
+/// The subselect of `JsonEach` when used in an `IN` expression
+#[derive(Debug, Clone, Copy, QueryId)]
+pub struct JsonEachValues<J, ST> {
+    json: J,
+    _sql_type: PhantomData<ST>,
+}
+
+impl<J, ST> Expression for JsonEachValues<J, ST>
+where
+    J: Expression,
+    ST: SqlType + TypedExpressionType,
+{
+    type SqlType = ST;
+}
+
+impl<J, ST> MaybeEmpty for JsonEachValues<J, ST> {
+    fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+impl<J, ST> QueryFragment<Sqlite> for JsonEachValues<J, ST>
+where
+    J: QueryFragment<Sqlite>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Sqlite>) -> QueryResult<()> {
+        out.push_sql("SELECT value FROM json_each(");
+        self.json.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+// like for a subselect, the values never take part in the grouping of the
+// outer query
+impl<J, ST, GB> ValidGrouping<GB> for JsonEachValues<J, ST> {
+    type IsAggregate = is_aggregate::Never;
+}
+
+impl<J, ST, QS> AppearsOnTable<QS> for JsonEachValues<J, ST>
+where
+    Self: Expression,
+    J: AppearsOnTable<QS>,
+{
+}
+
+impl<J, ST, QS> SelectableExpression<QS> for JsonEachValues<J, ST>
+where
+    Self: AppearsOnTable<QS>,
+    J: SelectableExpression<QS>,
+{
+}
@@ -5,5 +5,10 @@
 //! kept separate purely for documentation purposes.
 
 pub(crate) mod expression_methods;
+#[cfg(feature = "serde_json")]
+pub(crate) mod functions;
+mod grouped;
 pub(crate) mod helper_types;
+#[cfg(feature = "serde_json")]
+mod json;
 mod operators;
@@ -1,6 +1,16 @@
 use diesel::sql_types::Bool;
+#[cfg(feature = "serde_json")]
+use diesel::sql_types::{Json, Nullable};
 
 use crate::sqlite::Sqlite;
 
 __diesel_infix_operator!(Is, " IS ", ConstantNullability Bool, backend: Sqlite);
 __diesel_infix_operator!(IsNot, " IS NOT ", ConstantNullability Bool, backend: Sqlite);
+#[cfg(feature = "serde_json")]
+__diesel_infix_operator!(
+    name = RetrieveAsObject,
+    operator = " -> ",
+    return_ty = (Nullable<Json>),
+    backend_ty_params = (),
+    backend_ty = Sqlite,
+);
@@ -3,15 +3,22 @@ use std::path::Path;
 use diesel::{result::Error, QueryResult};
 use lunatic::LunaticError;
 pub use lunatic_sqlite_api::guest_api::*;
-use lunatic_sqlite_api::wire_format::{BindPair, SqliteError};
-pub use lunatic_sqlite_api::*;
+use lunatic_sqlite_api::wire_format::SqliteError;
+
+use super::bind_collector::BindValues;
+use super::constants::SQLITE_OK;
 
 pub fn open(path: &Path) -> Result<u64, LunaticError> {
-    let conn_id = 0u64;
+    // the host writes either the connection id or the id of the error as a
+    // little endian u64, even though the binding declares a `*mut u32`
+    let mut conn_id = 0u64;
     let path_str = path.to_str().unwrap();
     unsafe {
-        let status =
-            sqlite_guest_bindings::open(path_str.as_ptr(), path_str.len(), &mut (conn_id as u32));
+        let status = sqlite_guest_bindings::open(
+            path_str.as_ptr(),
+            path_str.len(),
+            &mut conn_id as *mut u64 as *mut u32,
+        );
         // 1 means that a generic error happened
         if status == 1 {
             return Err(LunaticError::Error(conn_id));
@@ -24,41 +31,264 @@ pub fn open(path: &Path) -> Result<u64, LunaticError> {
     Ok(conn_id)
 }
 
-/// returns a tuple consisting of the length of data written to the buf
-/// as well as the resource id
-pub fn query_prepare(conn_id: u64, query: &str) -> (u32, u64) {
-    let len = 0u32;
-    unsafe {
-        let resource_id =
-            sqlite_guest_bindings::query_prepare(conn_id, query.as_ptr(), query.len() as u32);
-        (len, resource_id)
+/// prepares `query` and returns the id of the new statement, or the SQLite
+/// response code if the query could not be prepared
+///
+/// Without the `host-statement-errors` feature the host traps the process
+/// instead of returning an error.
+#[cfg(feature = "host-statement-errors")]
+pub fn query_prepare(conn_id: u64, query: &str) -> Result<u64, u32> {
+    let mut statement_id = 0u64;
+    let status = unsafe {
+        sqlite_statement_bindings::query_prepare_checked(
+            conn_id,
+            query.as_ptr(),
+            query.len() as u32,
+            &mut statement_id,
+        )
+    };
+    match status {
+        SQLITE_OK => Ok(statement_id),
+        status => Err(status),
     }
 }
 
+#[cfg(not(feature = "host-statement-errors"))]
+pub fn query_prepare(conn_id: u64, query: &str) -> Result<u64, u32> {
+    Ok(
+        unsafe {
+            sqlite_guest_bindings::query_prepare(conn_id, query.as_ptr(), query.len() as u32)
+        },
+    )
+}
+
 pub fn execute(conn_id: u64, exec_str: &str) -> u32 {
     unsafe { sqlite_guest_bindings::execute(conn_id, exec_str.as_ptr(), exec_str.len() as u32) }
 }
 
-pub fn bind_value(statement_id: u64, value: BindPair) {
-    let bind_list = BindList(vec![value]);
-    let encoded = bincode::serialize(&bind_list).unwrap();
+pub fn sqlite3_changes(conn_id: u64) -> u32 {
+    unsafe { sqlite_guest_bindings::sqlite3_changes(conn_id) }
+}
+
+/// Host functions working on prepared statements which report failures to the guest.
+///
+/// The equivalent functions of `lunatic-sqlite-api` trap the whole process if
+/// SQLite rejects a query or a bind value. These variants return the SQLite
+/// response code instead, the error itself can be retrieved through `last_error`
+/// on the connection the statement belongs to.
+///
+/// No released lunatic runtime (up to 0.13) provides them, so they are only
+/// imported with the `host-statement-errors` feature, for runtimes that do.
+#[cfg(feature = "host-statement-errors")]
+pub mod sqlite_statement_bindings {
+    #[link(wasm_import_module = "lunatic::sqlite")]
+    extern "C" {
+        /// prepares `query` and writes the id of the new statement to `statement_id_ptr`
+        ///
+        /// returns the SQLite response code
+        pub fn query_prepare_checked(
+            connection_id: u64,
+            query_ptr: *const u8,
+            query_len: u32,
+            statement_id_ptr: *mut u64,
+        ) -> u32;
+
+        /// binds a bincode encoded `BindList` to the statement
+        ///
+        /// returns the SQLite response code of the first failing bind
+        pub fn bind_value_checked(statement_id: u64, bind_data_ptr: u32, bind_data_len: u32)
+            -> u32;
+
+        /// resets the statement so that it can be bound again
+        ///
+        /// returns the SQLite response code
+        pub fn statement_reset_checked(statement_id: u64) -> u32;
+
+        /// finalizes the statement and releases it in the host
+        ///
+        /// returns the SQLite response code
+        pub fn sqlite3_finalize_checked(statement_id: u64) -> u32;
+    }
+}
+
+/// Host functions that register guest callbacks on a connection.
+///
+/// Function pointers can't be handed to the host, so instead the host is given
+/// the name of an exported guest trampoline together with an opaque `callback_id`.
+/// Whenever SQLite invokes the callback, the host allocates the bincode encoded
+/// arguments in guest memory (via `lunatic_alloc`) and calls the trampoline with
+/// `(callback_id, args_ptr, args_len)`. The trampoline returns a composite `u64`
+/// of the length (upper half) and pointer (lower half) of the encoded result.
+///
+/// No released lunatic runtime (up to 0.13) provides them, so they are only
+/// imported with the `host-callbacks` feature, for runtimes that do.
+#[cfg(feature = "host-callbacks")]
+pub mod sqlite_callback_bindings {
+    #[link(wasm_import_module = "lunatic::sqlite")]
+    extern "C" {
+        /// registers a scalar function `fn_name` taking `num_args` arguments
+        /// which is backed by the guest export `trampoline`
+        ///
+        /// returns the SQLite response code
+        #[allow(clippy::too_many_arguments)]
+        pub fn create_function(
+            connection_id: u64,
+            fn_name_ptr: *const u8,
+            fn_name_len: u32,
+            num_args: u32,
+            deterministic: u32,
+            trampoline_ptr: *const u8,
+            trampoline_len: u32,
+            callback_id: u64,
+        ) -> u32;
+
+        /// registers an aggregate function `fn_name` taking `num_args` arguments
+        /// which is backed by the guest exports `step_trampoline` and `final_trampoline`
+        ///
+        /// Both trampolines additionally receive an id of the aggregate context
+        /// (directly after the `callback_id`) so that the guest can tell apart
+        /// concurrent evaluations of the same aggregate.
+        ///
+        /// returns the SQLite response code
+        #[allow(clippy::too_many_arguments)]
+        pub fn create_aggregate_function(
+            connection_id: u64,
+            fn_name_ptr: *const u8,
+            fn_name_len: u32,
+            num_args: u32,
+            step_trampoline_ptr: *const u8,
+            step_trampoline_len: u32,
+            final_trampoline_ptr: *const u8,
+            final_trampoline_len: u32,
+            callback_id: u64,
+        ) -> u32;
+
+        /// registers a collation `collation_name` which is backed by the guest export `trampoline`
+        ///
+        /// The trampoline returns the ordering of the two compared strings as `-1`, `0` or `1`.
+        ///
+        /// returns the SQLite response code
+        pub fn create_collation(
+            connection_id: u64,
+            collation_name_ptr: *const u8,
+            collation_name_len: u32,
+            trampoline_ptr: *const u8,
+            trampoline_len: u32,
+            callback_id: u64,
+        ) -> u32;
+    }
+}
+
+#[cfg(feature = "host-callbacks")]
+pub fn create_function(
+    conn_id: u64,
+    fn_name: &str,
+    num_args: u32,
+    deterministic: bool,
+    trampoline: &str,
+    callback_id: u64,
+) -> u32 {
     unsafe {
-        sqlite_guest_bindings::bind_value(
+        sqlite_callback_bindings::create_function(
+            conn_id,
+            fn_name.as_ptr(),
+            fn_name.len() as u32,
+            num_args,
+            deterministic as u32,
+            trampoline.as_ptr(),
+            trampoline.len() as u32,
+            callback_id,
+        )
+    }
+}
+
+#[cfg(feature = "host-callbacks")]
+pub fn create_aggregate_function(
+    conn_id: u64,
+    fn_name: &str,
+    num_args: u32,
+    step_trampoline: &str,
+    final_trampoline: &str,
+    callback_id: u64,
+) -> u32 {
+    unsafe {
+        sqlite_callback_bindings::create_aggregate_function(
+            conn_id,
+            fn_name.as_ptr(),
+            fn_name.len() as u32,
+            num_args,
+            step_trampoline.as_ptr(),
+            step_trampoline.len() as u32,
+            final_trampoline.as_ptr(),
+            final_trampoline.len() as u32,
+            callback_id,
+        )
+    }
+}
+
+#[cfg(feature = "host-callbacks")]
+pub fn create_collation(
+    conn_id: u64,
+    collation_name: &str,
+    trampoline: &str,
+    callback_id: u64,
+) -> u32 {
+    unsafe {
+        sqlite_callback_bindings::create_collation(
+            conn_id,
+            collation_name.as_ptr(),
+            collation_name.len() as u32,
+            trampoline.as_ptr(),
+            trampoline.len() as u32,
+            callback_id,
+        )
+    }
+}
+
+/// binds all values in a single host call and returns the SQLite response code
+///
+/// `binds` is a `WireBindList` or a `BindList` received from another process.
+/// Without the `host-statement-errors` feature the host traps the process
+/// instead of returning an error, so this always returns `SQLITE_OK`.
+pub(crate) fn bind_values<B: BindValues>(statement_id: u64, binds: &B) -> u32 {
+    let encoded = bincode::serialize(binds).unwrap();
+    #[cfg(feature = "host-statement-errors")]
+    unsafe {
+        sqlite_statement_bindings::bind_value_checked(
             statement_id,
             encoded.as_ptr() as u32,
             encoded.len() as u32,
         )
     }
+    #[cfg(not(feature = "host-statement-errors"))]
+    unsafe {
+        sqlite_guest_bindings::bind_value(
+            statement_id,
+            encoded.as_ptr() as u32,
+            encoded.len() as u32,
+        );
+        SQLITE_OK
+    }
 }
 
-pub fn sqlite3_reset(statement_id: u64) {
+/// returns the SQLite response code of resetting the statement
+///
+/// Without the `host-statement-errors` feature the host traps the process
+/// instead of returning an error, so this always returns `SQLITE_OK`.
+pub fn sqlite3_reset(statement_id: u64) -> u32 {
+    #[cfg(feature = "host-statement-errors")]
+    unsafe {
+        sqlite_statement_bindings::statement_reset_checked(statement_id)
+    }
+    #[cfg(not(feature = "host-statement-errors"))]
     unsafe {
         sqlite_guest_bindings::statement_reset(statement_id);
+        SQLITE_OK
     }
 }
 
 // helper function to unwrap byte slice that was allocated during host call
-fn unroll_vec(ptr: u32, len: u32) -> Vec<u8> {
+pub(crate) fn unroll_vec(ptr: u32, len: u32) -> Vec<u8> {
     let len = len as usize;
     unsafe { Vec::from_raw_parts(ptr as *mut u8, len, len) }
 }
@@ -73,9 +303,19 @@ pub fn last_error(connection_id: u64) -> QueryResult<SqliteError> {
     }
 }
 
-pub fn sqlite3_finalize(statement_id: u64) {
+/// returns the SQLite response code of finalizing the statement
+///
+/// Without the `host-statement-errors` feature the host ignores the response
+/// code, so this always returns `SQLITE_OK`.
+pub fn sqlite3_finalize(statement_id: u64) -> u32 {
+    #[cfg(feature = "host-statement-errors")]
+    unsafe {
+        sqlite_statement_bindings::sqlite3_finalize_checked(statement_id)
+    }
+    #[cfg(not(feature = "host-statement-errors"))]
     unsafe {
         sqlite_guest_bindings::sqlite3_finalize(statement_id);
+        SQLITE_OK
     }
 }
 
@@ -89,8 +329,7 @@ pub fn read_row(statement_id: u64) -> QueryResult<SqliteRow> {
         let ptr = sqlite_guest_bindings::read_row(statement_id, &mut len_ptr);
         let encoded_row = unroll_vec(ptr, len_ptr);
         bincode::deserialize(encoded_row.as_slice()).map_err(|e| {
-            eprintln!("Failed to deserialize sqlite row {e:?}");
-            Error::DeserializationError("Failed to deserialize sqlite row".into())
+            Error::DeserializationError(format!("Failed to deserialize sqlite row: {e}").into())
         })
     }
 }
@@ -1,16 +1,37 @@
 mod bind_collector;
+#[cfg(feature = "host-callbacks")]
+mod callbacks;
 mod constants;
 mod diesel_backend;
 mod diesel_connection;
-mod expression;
+pub(crate) mod expression;
+#[cfg(feature = "host-callbacks")]
 mod functions;
+#[cfg(target_arch = "wasm32")]
 mod host_bindings;
-mod query_builder;
+#[cfg(not(target_arch = "wasm32"))]
+mod native_host;
+#[cfg(not(target_arch = "wasm32"))]
+use native_host as host_bindings;
+#[cfg(target_arch = "wasm32")]
+pub mod pool;
+pub mod query_builder;
+#[cfg(target_arch = "wasm32")]
+mod remote;
+#[cfg(target_arch = "wasm32")]
+pub mod server;
+mod sqlite_value;
 mod stmt;
 mod types;
 
+pub use bind_collector::SqliteBindValue;
 pub use diesel_backend::Sqlite;
 pub use diesel_backend::SqliteType;
+pub use query_builder::SqliteQueryBuilder;
+pub use sqlite_value::{DeserializationMode, SqliteDeserializationError, SqliteValue};
+#[cfg(feature = "uuid")]
+pub use types::UuidSqlite;
+pub use types::{FromSqlRef, QueryableByNameRef, TimestamptzSqlite};
 
 pub use diesel_connection::*;
 
@@ -39,3 +60,12 @@ pub trait SqliteAggregateFunction<Args>: Default {
     /// caught and cause a return with an error value.
     fn finalize(aggregator: Option<Self>) -> Self::Output;
 }
+
+/// SQLite specific sql types
+pub mod sql_types {
+    #[doc(inline)]
+    pub use super::types::TimestamptzSqlite as Timestamptz;
+    #[cfg(feature = "uuid")]
+    #[doc(inline)]
+    pub use super::types::UuidSqlite as Uuid;
+}
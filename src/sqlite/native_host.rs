@@ -0,0 +1,841 @@
+//! An in-process stand-in for the SQLite host functions of lunatic.
+//!
+//! Built instead of `host_bindings.rs` on targets other than wasm32 when the
+//! `native-test-host` feature is enabled, so that the backend can be tested
+//! and debugged natively. Every function has the signature of its counterpart
+//! in `host_bindings.rs` and is split the same way: the `host` module plays
+//! the lunatic runtime on top of a bundled SQLite, while the functions in
+//! here only see what the guest would see. Bind values, rows, column names,
+//! errors and callback arguments cross between both bincode encoded, and the
+//! host returns the same SQLite response codes. Failing prepares, binds,
+//! resets and finalizes panic like stock lunatic traps, unless the
+//! `host-statement-errors` feature is enabled. Then they are reported like by
+//! a runtime providing the functions of that feature.
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::path::Path;
+
+use diesel::{result::Error, QueryResult};
+use lunatic_sqlite_api::wire_format::{SqliteError, SqliteRow};
+
+use super::bind_collector::BindValues;
+use super::constants::SQLITE_OK;
+
+/// Stand-in for `lunatic::LunaticError`, which reads and drops its message
+/// through host functions of lunatic. The host keeps the messages the same
+/// way, and never denies permissions.
+pub enum LunaticError {
+    Error(u64),
+}
+
+impl Drop for LunaticError {
+    fn drop(&mut self) {
+        let LunaticError::Error(id) = *self;
+        host::drop_error(id);
+    }
+}
+
+impl Debug for LunaticError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Display for LunaticError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let LunaticError::Error(id) = *self;
+        write!(f, "{}", host::error_to_string(id))
+    }
+}
+
+pub fn open(path: &Path) -> Result<u64, LunaticError> {
+    host::open(path).map_err(LunaticError::Error)
+}
+
+/// prepares `query` and returns the id of the new statement, or the SQLite
+/// response code if the query could not be prepared
+///
+/// Without the `host-statement-errors` feature the host traps the process
+/// instead of returning an error.
+pub fn query_prepare(conn_id: u64, query: &str) -> Result<u64, u32> {
+    let result = host::query_prepare_checked(conn_id, query);
+    if let Err(status) = result {
+        trap_unless_checked(conn_id, "query_prepare", status);
+    }
+    result
+}
+
+pub fn execute(conn_id: u64, exec_str: &str) -> u32 {
+    host::execute(conn_id, exec_str)
+}
+
+pub fn sqlite3_changes(conn_id: u64) -> u32 {
+    host::sqlite3_changes(conn_id)
+}
+
+#[cfg(feature = "host-callbacks")]
+pub fn create_function(
+    conn_id: u64,
+    fn_name: &str,
+    num_args: u32,
+    deterministic: bool,
+    trampoline: &str,
+    callback_id: u64,
+) -> u32 {
+    host::create_function(
+        conn_id,
+        fn_name,
+        num_args,
+        deterministic,
+        trampoline,
+        callback_id,
+    )
+}
+
+#[cfg(feature = "host-callbacks")]
+pub fn create_aggregate_function(
+    conn_id: u64,
+    fn_name: &str,
+    num_args: u32,
+    step_trampoline: &str,
+    final_trampoline: &str,
+    callback_id: u64,
+) -> u32 {
+    host::create_aggregate_function(
+        conn_id,
+        fn_name,
+        num_args,
+        step_trampoline,
+        final_trampoline,
+        callback_id,
+    )
+}
+
+#[cfg(feature = "host-callbacks")]
+pub fn create_collation(
+    conn_id: u64,
+    collation_name: &str,
+    trampoline: &str,
+    callback_id: u64,
+) -> u32 {
+    host::create_collation(conn_id, collation_name, trampoline, callback_id)
+}
+
+/// binds all values in a single host call and returns the SQLite response code
+///
+/// `binds` is a `WireBindList` or a `BindList` received from another process.
+/// Without the `host-statement-errors` feature the host traps the process
+/// instead of returning an error.
+pub(crate) fn bind_values<B: BindValues>(statement_id: u64, binds: &B) -> u32 {
+    let encoded = bincode::serialize(binds).unwrap();
+    let status = host::bind_value_checked(statement_id, &encoded);
+    trap_unless_checked(host::connection_id(statement_id), "bind_value", status);
+    status
+}
+
+/// returns the SQLite response code of resetting the statement
+///
+/// Without the `host-statement-errors` feature the host traps the process
+/// instead of returning an error.
+pub fn sqlite3_reset(statement_id: u64) -> u32 {
+    let status = host::statement_reset_checked(statement_id);
+    trap_unless_checked(host::connection_id(statement_id), "statement_reset", status);
+    status
+}
+
+pub fn last_error(connection_id: u64) -> QueryResult<SqliteError> {
+    let encoded_error = host::last_error(connection_id);
+    bincode::deserialize(encoded_error.as_slice())
+        .map_err(|_| Error::DeserializationError("Failed to deserialize sqlite error".into()))
+}
+
+/// returns the SQLite response code of finalizing the statement
+///
+/// Without the `host-statement-errors` feature the host drops the statement
+/// and ignores the response code, like stock lunatic.
+pub fn sqlite3_finalize(statement_id: u64) -> u32 {
+    let status = host::sqlite3_finalize_checked(statement_id);
+    if cfg!(feature = "host-statement-errors") {
+        status
+    } else {
+        SQLITE_OK
+    }
+}
+
+pub fn sqlite3_step(statement_id: u64) -> u32 {
+    host::sqlite3_step(statement_id)
+}
+
+pub fn read_row(statement_id: u64) -> QueryResult<SqliteRow> {
+    let encoded_row = host::read_row(statement_id);
+    bincode::deserialize(encoded_row.as_slice()).map_err(|e| {
+        Error::DeserializationError(format!("Failed to deserialize sqlite row: {e}").into())
+    })
+}
+
+pub fn column_names(statement_id: u64) -> QueryResult<Vec<String>> {
+    let encoded_column_names = host::column_names(statement_id);
+    bincode::deserialize(encoded_column_names.as_slice()).map_err(|_| {
+        Error::DeserializationError("Failed to deserialize list of sqlite column names".into())
+    })
+}
+
+/// Panics with the error of the connection if `status` isn't `SQLITE_OK`,
+/// like stock lunatic traps the process. The functions of the
+/// `host-statement-errors` feature return the status instead.
+fn trap_unless_checked(connection_id: u64, function: &str, status: u32) {
+    if cfg!(not(feature = "host-statement-errors")) && status != SQLITE_OK {
+        let error = last_error(connection_id)
+            .ok()
+            .and_then(|error| error.message);
+        panic!("lunatic::sqlite::{function}: {}", error.unwrap_or_default());
+    }
+}
+
+/// The host side of the SQLite functions
+///
+/// Connections and statements are resources of the calling thread, like they
+/// are resources of the calling process in lunatic. Panics in here mirror the
+/// traps of the runtime, e.g. for ids that don't exist.
+mod host {
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+    use std::ffi::{c_char, c_int, c_void, CStr, CString};
+    use std::path::Path;
+    use std::{ptr, slice};
+
+    use lunatic_sqlite_api::wire_format::{
+        BindKey, BindList, BindValue, SqliteError, SqliteRow, SqliteValue,
+    };
+    use sqlite3_sys as ffi;
+
+    use crate::sqlite::constants::SQLITE_OK;
+
+    #[cfg(feature = "host-callbacks")]
+    pub(super) use self::functions::{
+        create_aggregate_function, create_collation, create_function,
+    };
+
+    const SQLITE_ABORT: u32 = 4;
+    const SQLITE_MISUSE: u32 = 21;
+
+    struct Connection {
+        db: *mut ffi::sqlite3,
+        /// The error of a failed collation in the running step, which SQLite
+        /// can't report itself
+        callback_error: Option<SqliteError>,
+    }
+
+    impl Drop for Connection {
+        fn drop(&mut self) {
+            // `_v2` defers closing until the remaining statements are finalized
+            unsafe { ffi::sqlite3_close_v2(self.db) };
+        }
+    }
+
+    struct Statement {
+        stmt: *mut ffi::sqlite3_stmt,
+        connection_id: u64,
+    }
+
+    impl Drop for Statement {
+        fn drop(&mut self) {
+            unsafe { ffi::sqlite3_finalize(self.stmt) };
+        }
+    }
+
+    thread_local! {
+        static NEXT_RESOURCE_ID: Cell<u64> = const { Cell::new(1) };
+        static CONNECTIONS: RefCell<HashMap<u64, Connection>> = RefCell::new(HashMap::new());
+        static STATEMENTS: RefCell<HashMap<u64, Statement>> = RefCell::new(HashMap::new());
+        static ERRORS: RefCell<HashMap<u64, String>> = RefCell::new(HashMap::new());
+    }
+
+    fn next_resource_id() -> u64 {
+        NEXT_RESOURCE_ID.with(|id| {
+            let resource_id = id.get();
+            id.set(resource_id + 1);
+            resource_id
+        })
+    }
+
+    // Only raw pointers are handed out, so that callbacks running inside of
+    // SQLite are free to use the resources again
+
+    fn db(connection_id: u64) -> *mut ffi::sqlite3 {
+        CONNECTIONS.with(
+            |connections| match connections.borrow().get(&connection_id) {
+                Some(connection) => connection.db,
+                None => panic!("lunatic::sqlite: connection {connection_id} does not exist"),
+            },
+        )
+    }
+
+    pub(super) fn connection_id(statement_id: u64) -> u64 {
+        statement(statement_id).1
+    }
+
+    fn stmt(statement_id: u64) -> *mut ffi::sqlite3_stmt {
+        statement(statement_id).0
+    }
+
+    fn statement(statement_id: u64) -> (*mut ffi::sqlite3_stmt, u64) {
+        STATEMENTS.with(|statements| match statements.borrow().get(&statement_id) {
+            Some(statement) => (statement.stmt, statement.connection_id),
+            None => panic!("lunatic::sqlite: statement {statement_id} does not exist"),
+        })
+    }
+
+    fn set_callback_error(connection_id: u64, error: Option<SqliteError>) {
+        CONNECTIONS.with(|connections| {
+            if let Some(connection) = connections.borrow_mut().get_mut(&connection_id) {
+                connection.callback_error = error;
+            }
+        })
+    }
+
+    fn transient() -> Option<ffi::sqlite3_callback> {
+        // `SQLITE_TRANSIENT` makes SQLite copy the value before the call returns
+        Some(unsafe { std::mem::transmute::<isize, ffi::sqlite3_callback>(-1) })
+    }
+
+    unsafe fn text(ptr: *const u8, len: c_int) -> String {
+        if ptr.is_null() {
+            return String::new();
+        }
+        String::from_utf8_lossy(slice::from_raw_parts(ptr, len as usize)).into_owned()
+    }
+
+    unsafe fn blob(ptr: *const c_void, len: c_int) -> Vec<u8> {
+        if ptr.is_null() {
+            return Vec::new();
+        }
+        slice::from_raw_parts(ptr as *const u8, len as usize).to_vec()
+    }
+
+    /// Stores an error like lunatic does for the errors of its host functions
+    /// and returns its id
+    fn new_error(message: String) -> u64 {
+        let error_id = next_resource_id();
+        ERRORS.with(|errors| errors.borrow_mut().insert(error_id, message));
+        error_id
+    }
+
+    pub(super) fn error_to_string(error_id: u64) -> String {
+        ERRORS.with(|errors| match errors.borrow().get(&error_id) {
+            Some(message) => message.clone(),
+            None => panic!("lunatic::error: error {error_id} does not exist"),
+        })
+    }
+
+    pub(super) fn drop_error(error_id: u64) {
+        ERRORS.with(|errors| errors.borrow_mut().remove(&error_id));
+    }
+
+    /// returns the id of the new connection, or the id of the error if the
+    /// database can't be opened
+    pub(super) fn open(path: &Path) -> Result<u64, u64> {
+        let path = path
+            .to_str()
+            .and_then(|path| CString::new(path).ok())
+            .ok_or_else(|| new_error(format!("Invalid database path {path:?}")))?;
+        let flags = ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE | ffi::SQLITE_OPEN_URI;
+        let mut db = ptr::null_mut();
+        let status = unsafe { ffi::sqlite3_open_v2(path.as_ptr(), &mut db, flags, ptr::null()) };
+        let connection = Connection {
+            db,
+            callback_error: None,
+        };
+        if status as u32 != SQLITE_OK {
+            let message = unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(db)) };
+            return Err(new_error(message.to_string_lossy().into_owned()));
+        }
+
+        let connection_id = next_resource_id();
+        CONNECTIONS.with(|connections| connections.borrow_mut().insert(connection_id, connection));
+        Ok(connection_id)
+    }
+
+    pub(super) fn query_prepare_checked(connection_id: u64, query: &str) -> Result<u64, u32> {
+        let db = db(connection_id);
+        let mut statement = ptr::null_mut();
+        let status = unsafe {
+            ffi::sqlite3_prepare_v2(
+                db,
+                query.as_ptr() as *const c_char,
+                query.len() as c_int,
+                &mut statement,
+                ptr::null_mut(),
+            )
+        } as u32;
+        if status != SQLITE_OK {
+            return Err(status);
+        }
+
+        let statement_id = next_resource_id();
+        STATEMENTS.with(|statements| {
+            statements.borrow_mut().insert(
+                statement_id,
+                Statement {
+                    stmt: statement,
+                    connection_id,
+                },
+            )
+        });
+        Ok(statement_id)
+    }
+
+    pub(super) fn execute(connection_id: u64, exec_str: &str) -> u32 {
+        let db = db(connection_id);
+        let Ok(exec_str) = CString::new(exec_str) else {
+            return SQLITE_MISUSE;
+        };
+        unsafe {
+            ffi::sqlite3_exec(
+                db,
+                exec_str.as_ptr(),
+                None,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            ) as u32
+        }
+    }
+
+    pub(super) fn sqlite3_changes(connection_id: u64) -> u32 {
+        unsafe { ffi::sqlite3_changes(db(connection_id)) as u32 }
+    }
+
+    pub(super) fn bind_value_checked(statement_id: u64, bind_data: &[u8]) -> u32 {
+        let statement = stmt(statement_id);
+        let Ok(values) = bincode::deserialize::<BindList>(bind_data) else {
+            return SQLITE_MISUSE;
+        };
+        for (position, pair) in (1..).zip(values.iter()) {
+            let idx = match pair.0 {
+                BindKey::None => position,
+                BindKey::Numeric(idx) => idx as c_int,
+                BindKey::String(ref name) => match CString::new(name.as_str()) {
+                    Ok(name) => unsafe {
+                        ffi::sqlite3_bind_parameter_index(statement, name.as_ptr())
+                    },
+                    Err(_) => return SQLITE_MISUSE,
+                },
+            };
+            let status = unsafe {
+                match pair.1 {
+                    BindValue::Null => ffi::sqlite3_bind_null(statement, idx),
+                    BindValue::Blob(ref bytes) => ffi::sqlite3_bind_blob(
+                        statement,
+                        idx,
+                        bytes.as_ptr() as *const c_void,
+                        bytes.len() as c_int,
+                        transient(),
+                    ),
+                    BindValue::Text(ref text) => ffi::sqlite3_bind_text(
+                        statement,
+                        idx,
+                        text.as_ptr() as *const c_char,
+                        text.len() as c_int,
+                        transient(),
+                    ),
+                    BindValue::Double(double) => ffi::sqlite3_bind_double(statement, idx, double),
+                    BindValue::Int(int) => ffi::sqlite3_bind_int64(statement, idx, int.into()),
+                    BindValue::Int64(int) => ffi::sqlite3_bind_int64(statement, idx, int),
+                }
+            } as u32;
+            if status != SQLITE_OK {
+                return status;
+            }
+        }
+        SQLITE_OK
+    }
+
+    pub(super) fn statement_reset_checked(statement_id: u64) -> u32 {
+        unsafe { ffi::sqlite3_reset(stmt(statement_id)) as u32 }
+    }
+
+    pub(super) fn sqlite3_finalize_checked(statement_id: u64) -> u32 {
+        let (statement, _) = statement(statement_id);
+        STATEMENTS.with(|statements| {
+            // finalized here to report the status, instead of when dropped
+            let statement = statements.borrow_mut().remove(&statement_id);
+            std::mem::forget(statement);
+        });
+        unsafe { ffi::sqlite3_finalize(statement) as u32 }
+    }
+
+    pub(super) fn sqlite3_step(statement_id: u64) -> u32 {
+        let (statement, connection_id) = statement(statement_id);
+        set_callback_error(connection_id, None);
+        let status = unsafe { ffi::sqlite3_step(statement) } as u32;
+        // comparisons can't fail in SQLite, so the step fails afterwards instead
+        let collation_failed = CONNECTIONS.with(|connections| {
+            connections
+                .borrow()
+                .get(&connection_id)
+                .is_some_and(|connection| connection.callback_error.is_some())
+        });
+        if collation_failed {
+            SQLITE_ABORT
+        } else {
+            status
+        }
+    }
+
+    pub(super) fn read_row(statement_id: u64) -> Vec<u8> {
+        let statement = stmt(statement_id);
+        let column_count = unsafe { ffi::sqlite3_column_count(statement) };
+        let row = SqliteRow(
+            (0..column_count)
+                .map(|idx| unsafe { read_column(statement, idx) })
+                .collect(),
+        );
+        bincode::serialize(&row).unwrap()
+    }
+
+    unsafe fn read_column(statement: *mut ffi::sqlite3_stmt, idx: c_int) -> SqliteValue {
+        match ffi::sqlite3_column_type(statement, idx) {
+            ffi::SQLITE_INTEGER => SqliteValue::Integer(ffi::sqlite3_column_int64(statement, idx)),
+            ffi::SQLITE_FLOAT => SqliteValue::Double(ffi::sqlite3_column_double(statement, idx)),
+            ffi::SQLITE_TEXT => {
+                let ptr = ffi::sqlite3_column_text(statement, idx);
+                SqliteValue::Text(text(ptr, ffi::sqlite3_column_bytes(statement, idx)))
+            }
+            ffi::SQLITE_BLOB => {
+                let ptr = ffi::sqlite3_column_blob(statement, idx);
+                SqliteValue::Blob(blob(ptr, ffi::sqlite3_column_bytes(statement, idx)))
+            }
+            _ => SqliteValue::Null,
+        }
+    }
+
+    pub(super) fn column_names(statement_id: u64) -> Vec<u8> {
+        let statement = stmt(statement_id);
+        let column_count = unsafe { ffi::sqlite3_column_count(statement) };
+        let column_names = (0..column_count)
+            .map(|idx| unsafe {
+                CStr::from_ptr(ffi::sqlite3_column_name(statement, idx))
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>();
+        bincode::serialize(&column_names).unwrap()
+    }
+
+    pub(super) fn last_error(connection_id: u64) -> Vec<u8> {
+        let db = db(connection_id);
+        let callback_error = CONNECTIONS.with(|connections| {
+            connections
+                .borrow_mut()
+                .get_mut(&connection_id)
+                .and_then(|connection| connection.callback_error.take())
+        });
+        let error = callback_error.unwrap_or_else(|| unsafe {
+            SqliteError {
+                code: Some(ffi::sqlite3_extended_errcode(db) as u32),
+                message: Some(
+                    CStr::from_ptr(ffi::sqlite3_errmsg(db))
+                        .to_string_lossy()
+                        .into_owned(),
+                ),
+            }
+        });
+        bincode::serialize(&error).unwrap()
+    }
+
+    /// Registering guest callbacks, only provided with the `host-callbacks` feature
+    #[cfg(feature = "host-callbacks")]
+    mod functions {
+        use super::*;
+        use crate::sqlite::callbacks::{
+            self, AGGREGATE_FINAL_TRAMPOLINE, AGGREGATE_STEP_TRAMPOLINE, COLLATION_TRAMPOLINE,
+            FUNCTION_TRAMPOLINE,
+        };
+
+        const SQLITE_ERROR: u32 = 1;
+
+        /// The data SQLite passes to the callbacks of a function or collation
+        struct Callback {
+            connection_id: u64,
+            callback_id: u64,
+        }
+
+        fn register<F>(
+            connection_id: u64,
+            name: &str,
+            trampolines: &[&str],
+            callback_id: u64,
+            register: F,
+        ) -> u32
+        where
+            F: FnOnce(*mut ffi::sqlite3, &CStr, *mut c_void) -> c_int,
+        {
+            let db = db(connection_id);
+            // the runtime looks the trampolines up among the exports of the guest
+            let exports = [
+                FUNCTION_TRAMPOLINE,
+                AGGREGATE_STEP_TRAMPOLINE,
+                AGGREGATE_FINAL_TRAMPOLINE,
+                COLLATION_TRAMPOLINE,
+            ];
+            if !trampolines.iter().all(|name| exports.contains(name)) {
+                return SQLITE_ERROR;
+            }
+            let Ok(name) = CString::new(name) else {
+                return SQLITE_MISUSE;
+            };
+            let callback = Box::into_raw(Box::new(Callback {
+                connection_id,
+                callback_id,
+            }));
+            // SQLite drops the callback through `drop_callback`, even if
+            // registering it failed
+            register(db, &name, callback as *mut c_void) as u32
+        }
+
+        extern "C" fn drop_callback(callback: *mut c_void) {
+            drop(unsafe { Box::from_raw(callback as *mut Callback) });
+        }
+
+        pub(crate) fn create_function(
+            connection_id: u64,
+            fn_name: &str,
+            num_args: u32,
+            deterministic: bool,
+            trampoline: &str,
+            callback_id: u64,
+        ) -> u32 {
+            let mut flags = ffi::SQLITE_UTF8;
+            if deterministic {
+                flags |= ffi::SQLITE_DETERMINISTIC;
+            }
+            register(
+                connection_id,
+                fn_name,
+                &[trampoline],
+                callback_id,
+                |db, name, callback| unsafe {
+                    ffi::sqlite3_create_function_v2(
+                        db,
+                        name.as_ptr(),
+                        num_args as c_int,
+                        flags,
+                        callback,
+                        Some(call_function),
+                        None,
+                        None,
+                        Some(drop_callback),
+                    )
+                },
+            )
+        }
+
+        pub(crate) fn create_aggregate_function(
+            connection_id: u64,
+            fn_name: &str,
+            num_args: u32,
+            step_trampoline: &str,
+            final_trampoline: &str,
+            callback_id: u64,
+        ) -> u32 {
+            register(
+                connection_id,
+                fn_name,
+                &[step_trampoline, final_trampoline],
+                callback_id,
+                |db, name, callback| unsafe {
+                    ffi::sqlite3_create_function_v2(
+                        db,
+                        name.as_ptr(),
+                        num_args as c_int,
+                        ffi::SQLITE_UTF8,
+                        callback,
+                        None,
+                        Some(aggregate_step),
+                        Some(aggregate_final),
+                        Some(drop_callback),
+                    )
+                },
+            )
+        }
+
+        pub(crate) fn create_collation(
+            connection_id: u64,
+            collation_name: &str,
+            trampoline: &str,
+            callback_id: u64,
+        ) -> u32 {
+            register(
+                connection_id,
+                collation_name,
+                &[trampoline],
+                callback_id,
+                |db, name, callback| unsafe {
+                    ffi::sqlite3_create_collation_v2(
+                        db,
+                        name.as_ptr(),
+                        ffi::SQLITE_UTF8,
+                        callback,
+                        Some(call_collation),
+                        Some(drop_callback),
+                    )
+                },
+            )
+        }
+
+        unsafe fn callback<'a>(ctx: *mut ffi::sqlite3_context) -> &'a Callback {
+            &*(ffi::sqlite3_user_data(ctx) as *const Callback)
+        }
+
+        /// Encodes the arguments of a function call as `SqliteRow`
+        unsafe fn encode_args(argc: c_int, argv: *mut *mut ffi::sqlite3_value) -> Vec<u8> {
+            let args = SqliteRow(
+                (0..argc as usize)
+                    .map(|idx| {
+                        let value = *argv.add(idx);
+                        match ffi::sqlite3_value_type(value) {
+                            ffi::SQLITE_INTEGER => {
+                                SqliteValue::Integer(ffi::sqlite3_value_int64(value))
+                            }
+                            ffi::SQLITE_FLOAT => {
+                                SqliteValue::Double(ffi::sqlite3_value_double(value))
+                            }
+                            ffi::SQLITE_TEXT => SqliteValue::Text(text(
+                                ffi::sqlite3_value_text(value),
+                                ffi::sqlite3_value_bytes(value),
+                            )),
+                            ffi::SQLITE_BLOB => SqliteValue::Blob(blob(
+                                ffi::sqlite3_value_blob(value),
+                                ffi::sqlite3_value_bytes(value),
+                            )),
+                            _ => SqliteValue::Null,
+                        }
+                    })
+                    .collect(),
+            );
+            bincode::serialize(&args).unwrap()
+        }
+
+        /// Sets the result of a function call from the encoded result of its trampoline
+        unsafe fn set_result(ctx: *mut ffi::sqlite3_context, encoded_result: &[u8]) {
+            let result = bincode::deserialize::<Result<BindValue, SqliteError>>(encoded_result)
+                .unwrap_or_else(|_| {
+                    Err(SqliteError {
+                        code: None,
+                        message: Some("Failed to deserialize the result of a callback".to_owned()),
+                    })
+                });
+            match result {
+                Ok(BindValue::Null) => ffi::sqlite3_result_null(ctx),
+                Ok(BindValue::Blob(bytes)) => ffi::sqlite3_result_blob(
+                    ctx,
+                    bytes.as_ptr() as *const c_void,
+                    bytes.len() as c_int,
+                    transient(),
+                ),
+                Ok(BindValue::Text(text)) => ffi::sqlite3_result_text(
+                    ctx,
+                    text.as_ptr() as *const c_char,
+                    text.len() as c_int,
+                    transient(),
+                ),
+                Ok(BindValue::Double(double)) => ffi::sqlite3_result_double(ctx, double),
+                Ok(BindValue::Int(int)) => ffi::sqlite3_result_int64(ctx, int.into()),
+                Ok(BindValue::Int64(int)) => ffi::sqlite3_result_int64(ctx, int),
+                Err(error) => set_error(ctx, error),
+            }
+        }
+
+        unsafe fn set_error(ctx: *mut ffi::sqlite3_context, error: SqliteError) {
+            let message = error
+                .message
+                .unwrap_or_else(|| "callback failed".to_owned());
+            ffi::sqlite3_result_error(
+                ctx,
+                message.as_ptr() as *const c_char,
+                message.len() as c_int,
+            );
+        }
+
+        /// Identifies an evaluation of an aggregate by an id stored in its context
+        unsafe fn aggregate_context_id(ctx: *mut ffi::sqlite3_context) -> Option<u64> {
+            let context_id =
+                ffi::sqlite3_aggregate_context(ctx, std::mem::size_of::<u64>() as c_int)
+                    as *mut u64;
+            if context_id.is_null() {
+                return None;
+            }
+            // SQLite zeroes the context when it is allocated for the first row
+            if *context_id == 0 {
+                *context_id = next_resource_id();
+            }
+            Some(*context_id)
+        }
+
+        extern "C" fn call_function(
+            ctx: *mut ffi::sqlite3_context,
+            argc: c_int,
+            argv: *mut *mut ffi::sqlite3_value,
+        ) {
+            unsafe {
+                let callback = callback(ctx);
+                let result =
+                    callbacks::call_function(callback.callback_id, &encode_args(argc, argv));
+                set_result(ctx, &result);
+            }
+        }
+
+        extern "C" fn aggregate_step(
+            ctx: *mut ffi::sqlite3_context,
+            argc: c_int,
+            argv: *mut *mut ffi::sqlite3_value,
+        ) {
+            unsafe {
+                let callback = callback(ctx);
+                let Some(context_id) = aggregate_context_id(ctx) else {
+                    return ffi::sqlite3_result_error_nomem(ctx);
+                };
+                let result = callbacks::aggregate_step(
+                    callback.callback_id,
+                    context_id,
+                    &encode_args(argc, argv),
+                );
+                if let Ok(Err(error)) = bincode::deserialize::<Result<(), SqliteError>>(&result) {
+                    set_error(ctx, error);
+                }
+            }
+        }
+
+        extern "C" fn aggregate_final(ctx: *mut ffi::sqlite3_context) {
+            unsafe {
+                let callback = callback(ctx);
+                let Some(context_id) = aggregate_context_id(ctx) else {
+                    return ffi::sqlite3_result_error_nomem(ctx);
+                };
+                let result = callbacks::aggregate_final(callback.callback_id, context_id);
+                set_result(ctx, &result);
+            }
+        }
+
+        extern "C" fn call_collation(
+            callback: *mut c_void,
+            lhs_len: c_int,
+            lhs_ptr: *const c_void,
+            rhs_len: c_int,
+            rhs_ptr: *const c_void,
+        ) -> c_int {
+            let callback = unsafe { &*(callback as *const Callback) };
+            let (lhs, rhs) = unsafe { (blob(lhs_ptr, lhs_len), blob(rhs_ptr, rhs_len)) };
+            let result = callbacks::call_collation(callback.callback_id, lhs, rhs);
+            match bincode::deserialize::<Result<i32, SqliteError>>(&result) {
+                Ok(Ok(ordering)) => ordering,
+                result => {
+                    // the running step fails with this error once SQLite returns
+                    let error = result.unwrap_or_else(|_| Err(SqliteError::default()));
+                    set_callback_error(callback.connection_id, error.err());
+                    0
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,792 @@
+//! A pool of SQLite connections, running as a lunatic process
+//!
+//! Every connection of the pool is owned by a process of its own, which runs
+//! the queries of the process it is leased to. The pool process hands out
+//! these connections, replaces them when they break or exceed their maximum
+//! lifetime and lets callers wait for a free connection up to a timeout.
+//!
+//! ```rust,ignore
+//! use diesel::sqlite::pool::{Pool, PoolConfig};
+//!
+//! let pool = PoolConfig::new("app.db").max_size(4).build()?;
+//! let connection = pool.get()?;
+//! let titles = connection.run((), |connection, ()| {
+//!     posts::table.select(posts::title).load::<String>(connection)
+//! })?;
+//! ```
+//!
+//! The pool is an `AbstractProcess` ([`ConnectionPool`]) taking a
+//! [`PoolConfig`], so it can be started as the child of a supervisor and
+//! looked up by name with [`Pool::lookup`]. It never waits on the processes of
+//! its connections: they establish the connection once they are started and
+//! ping themselves before they are handed out, reporting back with a message,
+//! and callers tell whether a connection is broken when they return it.
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use diesel::{Connection, QueryResult};
+use lunatic::process::{
+    AbstractProcess, Message, MessageHandler, ProcessRef, Request, RequestHandler, StartProcess,
+};
+use lunatic::{host, Mailbox, MailboxResult, Process, Tag};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::remote::{self, RemoteCall};
+use super::SqliteConnection;
+
+/// The settings of a [`Pool`], with the defaults of diesel's r2d2 pool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolConfig {
+    database_url: String,
+    max_size: u32,
+    min_idle: Option<u32>,
+    checkout_timeout: Duration,
+    max_lifetime: Option<Duration>,
+    test_on_check_out: bool,
+}
+
+impl PoolConfig {
+    /// A pool of connections to `database_url`
+    pub fn new(database_url: impl Into<String>) -> Self {
+        PoolConfig {
+            database_url: database_url.into(),
+            max_size: 10,
+            min_idle: None,
+            checkout_timeout: Duration::from_secs(30),
+            max_lifetime: Some(Duration::from_secs(30 * 60)),
+            test_on_check_out: true,
+        }
+    }
+
+    /// The maximum number of connections, 10 by default
+    pub fn max_size(mut self, max_size: u32) -> Self {
+        assert!(max_size > 0, "max_size must be positive");
+        self.max_size = max_size;
+        self
+    }
+
+    /// The number of connections kept open while they are not used, all of
+    /// them by default
+    pub fn min_idle(mut self, min_idle: Option<u32>) -> Self {
+        self.min_idle = min_idle;
+        self
+    }
+
+    /// How long [`Pool::get`] waits for a free connection, 30 seconds by
+    /// default
+    pub fn checkout_timeout(mut self, checkout_timeout: Duration) -> Self {
+        self.checkout_timeout = checkout_timeout;
+        self
+    }
+
+    /// The age after which connections are closed once they are returned, 30
+    /// minutes by default
+    pub fn max_lifetime(mut self, max_lifetime: Option<Duration>) -> Self {
+        self.max_lifetime = max_lifetime;
+        self
+    }
+
+    /// Whether connections are pinged before they are handed out, which is
+    /// the default
+    pub fn test_on_check_out(mut self, test_on_check_out: bool) -> Self {
+        self.test_on_check_out = test_on_check_out;
+        self
+    }
+
+    /// Starts the pool process, linked to the calling process
+    ///
+    /// Waits until the initial connections are established and fails if one
+    /// of them cannot be.
+    pub fn build(self) -> Result<Pool, PoolError> {
+        let checkout_timeout = self.checkout_timeout;
+        let process = ConnectionPool::start_link(self, None);
+        let tag = Tag::new();
+        process.send(AwaitStart {
+            reply: Process::this(),
+            tag,
+        });
+        let mailbox: Mailbox<Option<PoolError>> = unsafe { Mailbox::new() };
+        match mailbox.tag_receive(&[tag]) {
+            None => Ok(Pool {
+                process,
+                checkout_timeout,
+            }),
+            Some(error) => {
+                process.shutdown();
+                Err(error)
+            }
+        }
+    }
+
+    fn min_idle_connections(&self) -> usize {
+        self.min_idle.unwrap_or(self.max_size).min(self.max_size) as usize
+    }
+}
+
+/// An error of checking out a connection from a [`Pool`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PoolError {
+    /// No connection became free within the checkout timeout
+    Timeout,
+    /// A new connection could not be established
+    ConnectionError(String),
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::Timeout => f.write_str("timed out waiting for a connection"),
+            PoolError::ConnectionError(message) => {
+                write!(f, "failed to establish a connection: {message}")
+            }
+        }
+    }
+}
+
+impl Error for PoolError {}
+
+/// The number of connections of a [`Pool`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolState {
+    /// All open connections
+    pub connections: u32,
+    /// The connections that are not leased
+    pub idle_connections: u32,
+}
+
+/// How much longer than the checkout timeout [`Pool::get`] waits for an
+/// answer of the pool before it gives up on its own
+const CHECKOUT_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+/// A handle of a running [`ConnectionPool`]
+///
+/// Handles can be cloned and sent to other processes, all of them check out
+/// connections of the same pool.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Pool {
+    process: ProcessRef<ConnectionPool>,
+    checkout_timeout: Duration,
+}
+
+impl Pool {
+    /// The pool started with `name`, e.g. by a supervisor
+    pub fn lookup(name: &str) -> Option<Self> {
+        ProcessRef::lookup(name).map(Pool::from)
+    }
+
+    /// The process of the pool
+    pub fn process(&self) -> &ProcessRef<ConnectionPool> {
+        &self.process
+    }
+
+    /// Leases a connection to the calling process
+    ///
+    /// Waits up to the checkout timeout of the pool when all connections are
+    /// leased. The connection is returned once the [`PooledConnection`] is
+    /// dropped.
+    pub fn get(&self) -> Result<PooledConnection, PoolError> {
+        let tag = Tag::new();
+        self.process.send(CheckOut {
+            reply: Process::this(),
+            tag,
+        });
+        // the pool answers late when the caller has to wait for a connection,
+        // and with a timeout of its own once the checkout timeout passed. The
+        // caller only gives up by itself if the pool doesn't answer at all.
+        let mailbox: Mailbox<Result<Lease, PoolError>> = unsafe { Mailbox::new() };
+        let timeout = self.checkout_timeout + CHECKOUT_GRACE_PERIOD;
+        let lease = match mailbox.tag_receive_timeout(&[tag], timeout) {
+            MailboxResult::TimedOut => {
+                self.process.send(CancelCheckOut(tag));
+                return Err(PoolError::Timeout);
+            }
+            result => result.unwrap()?,
+        };
+        // the connection dies with the caller and the other way round, the
+        // pool replaces it in both cases
+        lease.connection.link();
+        Ok(PooledConnection {
+            pool: self.process.clone(),
+            lease,
+            broken: Cell::new(false),
+        })
+    }
+
+    /// The number of open and idle connections
+    pub fn state(&self) -> PoolState {
+        self.process.request(GetState)
+    }
+}
+
+impl From<ProcessRef<ConnectionPool>> for Pool {
+    /// The handle of a running pool, which is asked for its checkout timeout
+    fn from(process: ProcessRef<ConnectionPool>) -> Self {
+        let checkout_timeout = process.request(GetCheckoutTimeout);
+        Pool {
+            process,
+            checkout_timeout,
+        }
+    }
+}
+
+/// A connection leased from a [`Pool`]
+///
+/// Queries run in the process owning the connection, see
+/// [`PooledConnection::run`].
+pub struct PooledConnection {
+    pool: ProcessRef<ConnectionPool>,
+    lease: Lease,
+    // as of the last function run with the connection
+    broken: Cell<bool>,
+}
+
+impl PooledConnection {
+    /// Runs `function` with the connection
+    ///
+    /// The function runs in the process owning the connection, so it can't
+    /// capture anything; its input is passed as `argument`. Both the argument
+    /// and the result are serialized to cross the processes. A panic of the
+    /// function kills the calling process as well.
+    pub fn run<A, R>(
+        &self,
+        argument: A,
+        function: fn(&mut SqliteConnection, A) -> QueryResult<R>,
+    ) -> QueryResult<R>
+    where
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned,
+    {
+        let (result, broken) = self
+            .lease
+            .connection
+            .request(Run(RemoteCall::new(function, &argument)));
+        self.broken.set(broken);
+        remote::decode_result(&result)
+    }
+
+    /// Checks that the connection still answers queries
+    pub fn ping(&self) -> QueryResult<()> {
+        self.run((), |connection, ()| connection.ping())
+    }
+
+    /// Whether a failed transaction left the connection unusable, in which
+    /// case the pool closes it once it is returned
+    ///
+    /// The process of the connection reports this after every function run
+    /// with [`PooledConnection::run`], so returning the connection doesn't
+    /// have to ask a process that might have died already.
+    pub fn is_broken(&self) -> bool {
+        self.broken.get()
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        self.lease.connection.unlink();
+        self.pool.send(CheckIn {
+            id: self.lease.id,
+            broken: self.broken.get(),
+        });
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Lease {
+    id: i64,
+    connection: ProcessRef<PooledConnectionProcess>,
+}
+
+/// The process of the pool, usually used through [`Pool`]
+pub struct ConnectionPool {
+    this: ProcessRef<ConnectionPool>,
+    config: PoolConfig,
+    // by the tag of the link to the connection's process
+    connections: HashMap<i64, Slot>,
+    idle: VecDeque<i64>,
+    // the connections that are not established yet
+    opening: HashSet<i64>,
+    // the callers of connections that are being established or pinging
+    // themselves, the other connections become idle once they reported back
+    pending: HashMap<i64, Waiting>,
+    waiting: VecDeque<Waiting>,
+    // until the initial connections are established
+    starting: Option<Starting>,
+}
+
+struct Slot {
+    process: ProcessRef<PooledConnectionProcess>,
+    created_at: Instant,
+}
+
+struct Waiting<T = Result<Lease, PoolError>> {
+    reply: Process<T>,
+    tag: Tag,
+}
+
+struct Starting {
+    connections: HashSet<i64>,
+    error: Option<PoolError>,
+    // the caller of `PoolConfig::build`
+    caller: Option<Waiting<Option<PoolError>>>,
+}
+
+impl AbstractProcess for ConnectionPool {
+    type Arg = PoolConfig;
+    type State = Self;
+
+    fn init(this: ProcessRef<Self>, config: PoolConfig) -> Self {
+        // connections are replaced when their process dies
+        unsafe { host::api::process::die_when_link_dies(0) };
+        let mut pool = ConnectionPool {
+            this,
+            config,
+            connections: HashMap::new(),
+            idle: VecDeque::new(),
+            opening: HashSet::new(),
+            pending: HashMap::new(),
+            waiting: VecDeque::new(),
+            starting: None,
+        };
+        pool.fill_idle();
+        pool.starting = Some(Starting {
+            connections: pool.opening.clone(),
+            error: None,
+            caller: None,
+        });
+        pool
+    }
+
+    fn terminate(state: Self) {
+        for slot in state.connections.into_values() {
+            slot.process.shutdown();
+        }
+    }
+
+    fn handle_link_trapped(state: &mut Self, tag: Tag) {
+        if state.connections.remove(&tag.id()).is_some() {
+            state.idle.retain(|&id| id != tag.id());
+            state.opening.remove(&tag.id());
+            if let Some(waiting) = state.pending.remove(&tag.id()) {
+                state.waiting.push_front(waiting);
+            }
+            state.started(
+                tag.id(),
+                Err("the process of the connection died".to_owned()),
+            );
+            state.replace_closed();
+        }
+    }
+}
+
+impl ConnectionPool {
+    /// Starts the process of a new connection, which reports back with
+    /// [`Opened`] once the connection is established
+    fn open(&mut self) -> i64 {
+        let tag = Tag::new();
+        let open = (
+            self.config.database_url.clone(),
+            self.this.clone(),
+            tag.id(),
+        );
+        let process = PooledConnectionProcess::start(open, None);
+        unsafe { host::api::process::link(tag.id(), process.id()) };
+        self.connections.insert(
+            tag.id(),
+            Slot {
+                process,
+                created_at: Instant::now(),
+            },
+        );
+        self.opening.insert(tag.id());
+        tag.id()
+    }
+
+    fn close(&mut self, id: i64) {
+        if let Some(slot) = self.connections.remove(&id) {
+            slot.process.unlink();
+            slot.process.shutdown();
+        }
+    }
+
+    fn fill_idle(&mut self) {
+        let becoming_idle = |pool: &Self| {
+            let opening = pool.opening.iter();
+            opening.filter(|id| !pool.pending.contains_key(id)).count()
+        };
+        while self.idle.len() + becoming_idle(self) < self.config.min_idle_connections()
+            && self.connections.len() < self.config.max_size as usize
+        {
+            self.open();
+        }
+    }
+
+    /// Notes that an initial connection was established or failed to be
+    fn started(&mut self, id: i64, result: Result<(), String>) {
+        let Some(starting) = &mut self.starting else {
+            return;
+        };
+        if starting.connections.remove(&id) {
+            if let Err(message) = result {
+                starting
+                    .error
+                    .get_or_insert(PoolError::ConnectionError(message));
+            }
+            self.finish_starting();
+        }
+    }
+
+    /// Answers the caller of [`PoolConfig::build`] once all initial
+    /// connections reported back
+    fn finish_starting(&mut self) {
+        if let Some(Starting {
+            connections,
+            caller: Some(_),
+            ..
+        }) = &self.starting
+        {
+            if connections.is_empty() {
+                let starting = self.starting.take().unwrap();
+                let caller = starting.caller.unwrap();
+                caller.reply.tag_send(caller.tag, starting.error);
+            }
+        }
+    }
+
+    fn is_expired(&self, id: i64) -> bool {
+        match (self.config.max_lifetime, self.connections.get(&id)) {
+            (Some(max_lifetime), Some(slot)) => slot.created_at.elapsed() >= max_lifetime,
+            _ => false,
+        }
+    }
+
+    /// Hands an idle connection or a new one to the caller, or returns the
+    /// caller if all connections are leased
+    ///
+    /// Idle connections are pinged first if the pool tests them on check out,
+    /// the caller gets them once they answered with [`Pinged`], new ones once
+    /// they are established.
+    fn hand_out(&mut self, waiting: Waiting) -> Result<(), Waiting> {
+        let closed_expired = self.close_expired_idle();
+        let handed_out = if let Some(id) = self.idle.pop_front() {
+            if self.config.test_on_check_out {
+                self.connections[&id].process.send(Ping {
+                    pool: self.this.clone(),
+                    id,
+                });
+                self.pending.insert(id, waiting);
+            } else {
+                self.lease(id, waiting);
+            }
+            Ok(())
+        } else if self.connections.len() < self.config.max_size as usize {
+            let id = self.open();
+            self.pending.insert(id, waiting);
+            Ok(())
+        } else {
+            Err(waiting)
+        };
+        if closed_expired {
+            self.fill_idle();
+        }
+        handed_out
+    }
+
+    /// Closes the idle connections that exceeded their maximum lifetime,
+    /// returning whether there were any
+    fn close_expired_idle(&mut self) -> bool {
+        let expired: Vec<i64> = self
+            .idle
+            .iter()
+            .copied()
+            .filter(|&id| self.is_expired(id))
+            .collect();
+        self.idle.retain(|id| !expired.contains(id));
+        for &id in &expired {
+            self.close(id);
+        }
+        !expired.is_empty()
+    }
+
+    fn lease(&self, id: i64, waiting: Waiting) {
+        let lease = Lease {
+            id,
+            connection: self.connections[&id].process.clone(),
+        };
+        waiting.reply.tag_send(waiting.tag, Ok(lease));
+    }
+
+    /// Hands out connections to the callers waiting for one
+    fn serve_waiting(&mut self) {
+        while let Some(waiting) = self.waiting.pop_front() {
+            if let Err(waiting) = self.hand_out(waiting) {
+                self.waiting.push_front(waiting);
+                break;
+            }
+        }
+    }
+
+    fn replace_closed(&mut self) {
+        self.serve_waiting();
+        self.fill_idle();
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct AwaitStart {
+    reply: Process<Option<PoolError>>,
+    tag: Tag,
+}
+
+impl MessageHandler<AwaitStart> for ConnectionPool {
+    fn handle(state: &mut Self, AwaitStart { reply, tag }: AwaitStart) {
+        match &mut state.starting {
+            Some(starting) => {
+                starting.caller = Some(Waiting { reply, tag });
+                state.finish_starting();
+            }
+            None => reply.tag_send(tag, None),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct GetState;
+
+impl RequestHandler<GetState> for ConnectionPool {
+    type Response = PoolState;
+
+    fn handle(state: &mut Self, _: GetState) -> PoolState {
+        PoolState {
+            connections: state.connections.len() as u32,
+            idle_connections: state.idle.len() as u32,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct GetCheckoutTimeout;
+
+impl RequestHandler<GetCheckoutTimeout> for ConnectionPool {
+    type Response = Duration;
+
+    fn handle(state: &mut Self, _: GetCheckoutTimeout) -> Duration {
+        state.config.checkout_timeout
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CheckOut {
+    reply: Process<Result<Lease, PoolError>>,
+    tag: Tag,
+}
+
+impl MessageHandler<CheckOut> for ConnectionPool {
+    fn handle(state: &mut Self, CheckOut { reply, tag }: CheckOut) {
+        let mut waiting = Waiting { reply, tag };
+        // earlier callers are served first
+        if state.waiting.is_empty() {
+            match state.hand_out(waiting) {
+                Ok(()) => return,
+                Err(not_served) => waiting = not_served,
+            }
+        }
+        state.waiting.push_back(waiting);
+        state
+            .this
+            .send_after(CheckOutTimeout(tag), state.config.checkout_timeout);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CheckOutTimeout(Tag);
+
+impl MessageHandler<CheckOutTimeout> for ConnectionPool {
+    fn handle(state: &mut Self, CheckOutTimeout(tag): CheckOutTimeout) {
+        if let Some(position) = state.waiting.iter().position(|waiting| waiting.tag == tag) {
+            let waiting = state.waiting.remove(position).unwrap();
+            waiting.reply.tag_send(tag, Err(PoolError::Timeout));
+        }
+    }
+}
+
+/// Sent by a caller that stopped waiting for a connection
+#[derive(Serialize, Deserialize)]
+struct CancelCheckOut(Tag);
+
+impl MessageHandler<CancelCheckOut> for ConnectionPool {
+    fn handle(state: &mut Self, CancelCheckOut(tag): CancelCheckOut) {
+        state.waiting.retain(|waiting| waiting.tag != tag);
+        // the connection becomes idle once it reported back
+        state.pending.retain(|_, waiting| waiting.tag != tag);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Opened {
+    id: i64,
+    result: Result<(), String>,
+}
+
+impl MessageHandler<Opened> for ConnectionPool {
+    fn handle(state: &mut Self, Opened { id, result }: Opened) {
+        if !state.opening.remove(&id) {
+            // the connection died while it was being established
+            return;
+        }
+        state.started(id, result.clone());
+        let waiting = state.pending.remove(&id);
+        match (result, waiting) {
+            (Ok(()), Some(waiting)) => state.lease(id, waiting),
+            (Ok(()), None) => {
+                state.idle.push_back(id);
+                state.serve_waiting();
+            }
+            (Err(message), waiting) => {
+                state.close(id);
+                if let Some(waiting) = waiting {
+                    let error = PoolError::ConnectionError(message);
+                    waiting.reply.tag_send(waiting.tag, Err(error));
+                }
+                // opening another connection would most likely fail the same
+                // way, the failure shows up again at the next checkout
+                state.serve_waiting();
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Pinged {
+    id: i64,
+    healthy: bool,
+}
+
+impl MessageHandler<Pinged> for ConnectionPool {
+    fn handle(state: &mut Self, Pinged { id, healthy }: Pinged) {
+        if !state.connections.contains_key(&id) {
+            // the connection died while it was pinging itself
+            return;
+        }
+        let waiting = state.pending.remove(&id);
+        if !healthy {
+            state.close(id);
+            if let Some(waiting) = waiting {
+                state.waiting.push_front(waiting);
+            }
+            state.replace_closed();
+        } else if let Some(waiting) = waiting {
+            state.lease(id, waiting);
+        } else {
+            // the caller stopped waiting for it
+            state.idle.push_back(id);
+            state.serve_waiting();
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CheckIn {
+    id: i64,
+    broken: bool,
+}
+
+impl MessageHandler<CheckIn> for ConnectionPool {
+    fn handle(state: &mut Self, CheckIn { id, broken }: CheckIn) {
+        if !state.connections.contains_key(&id) {
+            // the connection died while it was leased and is replaced already
+            return;
+        }
+        if state.is_expired(id) || broken {
+            state.close(id);
+            state.replace_closed();
+        } else {
+            state.idle.push_back(id);
+            state.serve_waiting();
+        }
+    }
+}
+
+/// The process owning a connection of a [`ConnectionPool`]
+pub struct PooledConnectionProcess;
+
+impl AbstractProcess for PooledConnectionProcess {
+    /// The database URL, the pool and the id of the connection in the pool
+    type Arg = (String, ProcessRef<ConnectionPool>, i64);
+    type State = Option<SqliteConnection>;
+
+    fn init(this: ProcessRef<Self>, (database_url, pool, id): Self::Arg) -> Self::State {
+        // `start` waits for `init`, the pool doesn't wait for the connection
+        this.send(Open {
+            database_url,
+            pool,
+            id,
+        });
+        None
+    }
+}
+
+fn connection(state: &mut Option<SqliteConnection>) -> &mut SqliteConnection {
+    state
+        .as_mut()
+        .expect("the pool only leases established connections")
+}
+
+#[derive(Serialize, Deserialize)]
+struct Open {
+    database_url: String,
+    pool: ProcessRef<ConnectionPool>,
+    id: i64,
+}
+
+impl MessageHandler<Open> for PooledConnectionProcess {
+    fn handle(
+        state: &mut Self::State,
+        Open {
+            database_url,
+            pool,
+            id,
+        }: Open,
+    ) {
+        let result = match SqliteConnection::establish(&database_url) {
+            Ok(connection) => {
+                *state = Some(connection);
+                Ok(())
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        pool.send(Opened { id, result });
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Ping {
+    pool: ProcessRef<ConnectionPool>,
+    id: i64,
+}
+
+impl MessageHandler<Ping> for PooledConnectionProcess {
+    fn handle(state: &mut Self::State, Ping { pool, id }: Ping) {
+        let connection = connection(state);
+        let healthy = !connection.is_broken() && connection.ping().is_ok();
+        pool.send(Pinged { id, healthy });
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Run(RemoteCall);
+
+impl RequestHandler<Run> for PooledConnectionProcess {
+    /// The encoded result and whether the connection is broken afterwards
+    type Response = (Vec<u8>, bool);
+
+    fn handle(state: &mut Self::State, Run(call): Run) -> (Vec<u8>, bool) {
+        let connection = connection(state);
+        let result = call.call(connection);
+        (result, connection.is_broken())
+    }
+}
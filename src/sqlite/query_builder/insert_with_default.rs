@@ -0,0 +1,295 @@
+//! Inserts of records with `DEFAULT` values and batch inserts
+//!
+//! SQLite does not support the `DEFAULT` keyword, so columns without a value
+//! are left out of the column list instead. A batch is sent as one multi-row
+//! `INSERT` statement as long as every row inserts the same columns, otherwise
+//! each row is inserted on its own inside of a transaction.
+//!
+//! Batches with a `RETURNING` clause are loaded through diesel's `LoadQuery`,
+//! which only runs a single statement. They are sent as one multi-row
+//! `INSERT` as well, and fail with `Error::QueryBuilderError` if their rows
+//! leave out different columns.
+
+use diesel::backend::sql_dialect::default_keyword_for_insert::DoesNotSupportDefaultKeyword;
+use diesel::insertable::{
+    CanInsertInSingleQuery, ColumnInsertValue, DefaultableColumnInsertValue, InsertValues,
+};
+use diesel::query_builder::{
+    BatchInsert, InsertStatement, NoFromClause, ReturningClause, ValuesClause,
+};
+use diesel::query_dsl::methods::ExecuteDsl;
+
+use crate::connection::Connection;
+use crate::expression::{AppearsOnTable, Expression};
+use crate::query_builder::{AstPass, QueryFragment, QueryId};
+use crate::result::{Error, QueryResult};
+use crate::sqlite::{Sqlite, SqliteConnection};
+use crate::{Column, Table};
+
+// diesel implements this for backends supporting the `DEFAULT` keyword only,
+// and the table of an insert is only known through the column here
+#[allow(unknown_lints, uncovered_param_in_projection)]
+impl<Col, Expr> InsertValues<Col::Table, Sqlite>
+    for DefaultableColumnInsertValue<ColumnInsertValue<Col, Expr>>
+where
+    Col: Column,
+    Expr: Expression<SqlType = Col::SqlType> + AppearsOnTable<NoFromClause>,
+    Self: QueryFragment<Sqlite>,
+{
+    fn column_names(&self, mut out: AstPass<'_, '_, Sqlite>) -> QueryResult<()> {
+        if let Self::Expression(..) = *self {
+            out.push_identifier(Col::NAME)?;
+        }
+        Ok(())
+    }
+}
+
+impl<Col, Expr> QueryFragment<Sqlite, DoesNotSupportDefaultKeyword>
+    for DefaultableColumnInsertValue<ColumnInsertValue<Col, Expr>>
+where
+    Expr: QueryFragment<Sqlite>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Sqlite>) -> QueryResult<()> {
+        if let Self::Expression(ref inner) = *self {
+            inner.walk_ast(out.reborrow())?;
+        }
+        Ok(())
+    }
+}
+
+/// A batch insert whose rows all insert into the same columns
+///
+/// This is used instead of implementing `QueryFragment` for `BatchInsert`, so
+/// that batches stay free to pick between a single statement and one statement
+/// per row in their `ExecuteDsl` impl.
+#[allow(missing_debug_implementations, missing_copy_implementations)]
+pub struct SqliteBatchInsertWrapper<V, T, QId, const STATIC_QUERY_ID: bool>(
+    BatchInsert<V, T, QId, STATIC_QUERY_ID>,
+);
+
+impl<V, T, QId, const STATIC_QUERY_ID: bool> SqliteBatchInsertWrapper<V, T, QId, STATIC_QUERY_ID> {
+    /// Wraps a batch whose rows all insert the same columns
+    pub(crate) fn new(batch: BatchInsert<V, T, QId, STATIC_QUERY_ID>) -> Self {
+        SqliteBatchInsertWrapper(batch)
+    }
+}
+
+impl<V, Tab, QId, const STATIC_QUERY_ID: bool> QueryFragment<Sqlite>
+    for SqliteBatchInsertWrapper<Vec<ValuesClause<V, Tab>>, Tab, QId, STATIC_QUERY_ID>
+where
+    ValuesClause<V, Tab>: QueryFragment<Sqlite>,
+    V: QueryFragment<Sqlite>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Sqlite>) -> QueryResult<()> {
+        if !STATIC_QUERY_ID {
+            out.unsafe_to_cache_prepared();
+        }
+
+        walk_rows(&self.0.values, out)
+    }
+}
+
+/// Writes the column list of the first row followed by the values of all rows
+fn walk_rows<'b, V, Tab>(
+    rows: &'b [ValuesClause<V, Tab>],
+    mut out: AstPass<'_, 'b, Sqlite>,
+) -> QueryResult<()>
+where
+    ValuesClause<V, Tab>: QueryFragment<Sqlite>,
+    V: QueryFragment<Sqlite>,
+{
+    let mut rows = rows.iter();
+    if let Some(row) = rows.next() {
+        row.walk_ast(out.reborrow())?;
+    }
+    for row in rows {
+        out.push_sql(", (");
+        row.values.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+    }
+    Ok(())
+}
+
+impl<V, Tab, QId, const STATIC_QUERY_ID: bool> CanInsertInSingleQuery<Sqlite>
+    for SqliteBatchInsertWrapper<Vec<V>, Tab, QId, STATIC_QUERY_ID>
+{
+    fn rows_to_insert(&self) -> Option<usize> {
+        Some(self.0.values.len())
+    }
+}
+
+impl<V, Tab, QId, const STATIC_QUERY_ID: bool> QueryId
+    for SqliteBatchInsertWrapper<V, Tab, QId, STATIC_QUERY_ID>
+where
+    BatchInsert<V, Tab, QId, STATIC_QUERY_ID>: QueryId,
+{
+    type QueryId = <BatchInsert<V, Tab, QId, STATIC_QUERY_ID> as QueryId>::QueryId;
+
+    const HAS_STATIC_QUERY_ID: bool =
+        <BatchInsert<V, Tab, QId, STATIC_QUERY_ID> as QueryId>::HAS_STATIC_QUERY_ID;
+}
+
+/// Compares which columns two rows leave out of an insert, without rendering them
+///
+/// Columns are left out if their value is `DefaultableColumnInsertValue::Default`.
+/// This is implemented for the values of records and tuples of them with up to
+/// 32 columns, the number diesel supports by default.
+pub trait LeftOutColumns {
+    /// Whether `self` leaves out the same columns as `other`
+    fn leaves_out_same_columns(&self, other: &Self) -> bool;
+}
+
+impl<T> LeftOutColumns for DefaultableColumnInsertValue<T> {
+    fn leaves_out_same_columns(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::Default, Self::Default) | (Self::Expression(_), Self::Expression(_))
+        )
+    }
+}
+
+impl<Col, Expr> LeftOutColumns for ColumnInsertValue<Col, Expr> {
+    fn leaves_out_same_columns(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+macro_rules! tuple_impls {
+    ($(($($idx:tt $T:ident)+))+) => {
+        $(
+            impl<$($T: LeftOutColumns),+> LeftOutColumns for ($($T,)+) {
+                fn leaves_out_same_columns(&self, other: &Self) -> bool {
+                    $(self.$idx.leaves_out_same_columns(&other.$idx))&&+
+                }
+            }
+        )+
+    };
+}
+
+tuple_impls! {
+    (0 T0)
+    (0 T0 1 T1)
+    (0 T0 1 T1 2 T2)
+    (0 T0 1 T1 2 T2 3 T3)
+    (0 T0 1 T1 2 T2 3 T3 4 T4)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20 21 T21)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20 21 T21 22 T22)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20 21 T21 22 T22 23 T23)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20 21 T21 22 T22 23 T23 24 T24)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20 21 T21 22 T22 23 T23 24 T24 25 T25)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20 21 T21 22 T22 23 T23 24 T24 25 T25 26 T26)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20 21 T21 22 T22 23 T23 24 T24 25 T25 26 T26 27 T27)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20 21 T21 22 T22 23 T23 24 T24 25 T25 26 T26 27 T27 28 T28)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20 21 T21 22 T22 23 T23 24 T24 25 T25 26 T26 27 T27 28 T28 29 T29)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20 21 T21 22 T22 23 T23 24 T24 25 T25 26 T26 27 T27 28 T28 29 T29 30 T30)
+    (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11 12 T12 13 T13 14 T14 15 T15 16 T16 17 T17 18 T18 19 T19 20 T20 21 T21 22 T22 23 T23 24 T24 25 T25 26 T26 27 T27 28 T28 29 T29 30 T30 31 T31)
+}
+
+/// Whether all rows can be inserted by a single statement, which is the case
+/// if none of them leaves out a different set of columns than the others
+pub(crate) fn rows_share_columns<V, Tab>(rows: &[ValuesClause<V, Tab>]) -> bool
+where
+    V: LeftOutColumns,
+{
+    match rows.split_first() {
+        Some((first, rows)) => rows
+            .iter()
+            .all(|row| first.values.leaves_out_same_columns(&row.values)),
+        None => true,
+    }
+}
+
+type BatchInsertStatement<T, V, QId, Op, Ret, const STATIC_QUERY_ID: bool> =
+    InsertStatement<T, BatchInsert<Vec<ValuesClause<V, T>>, T, QId, STATIC_QUERY_ID>, Op, Ret>;
+
+// without a `Ret` parameter, which defaults to diesel's private `NoReturningClause`
+impl<V, T, QId, Op, const STATIC_QUERY_ID: bool> ExecuteDsl<SqliteConnection, Sqlite>
+    for InsertStatement<T, BatchInsert<Vec<ValuesClause<V, T>>, T, QId, STATIC_QUERY_ID>, Op>
+where
+    T: Table + Copy,
+    Op: Copy,
+    V: InsertValues<T, Sqlite> + LeftOutColumns,
+    InsertStatement<
+        T,
+        SqliteBatchInsertWrapper<Vec<ValuesClause<V, T>>, T, QId, STATIC_QUERY_ID>,
+        Op,
+    >: ExecuteDsl<SqliteConnection, Sqlite>,
+    for<'a> InsertStatement<T, &'a ValuesClause<V, T>, Op>: ExecuteDsl<SqliteConnection, Sqlite>,
+{
+    fn execute(query: Self, conn: &mut SqliteConnection) -> QueryResult<usize> {
+        if rows_share_columns(&query.records.values) {
+            let records = SqliteBatchInsertWrapper::new(query.records);
+            let statement =
+                InsertStatement::new(query.target, records, query.operator, query.returning);
+            return ExecuteDsl::execute(statement, conn);
+        }
+
+        conn.transaction(|conn| {
+            let mut result = 0;
+            for record in &query.records.values {
+                let statement =
+                    InsertStatement::new(query.target, record, query.operator, query.returning);
+                result += ExecuteDsl::execute(statement, conn)?;
+            }
+            Ok(result)
+        })
+    }
+}
+
+// diesel only implements `QueryFragment` for inserts that can be written with
+// the `DEFAULT` keyword. Batches without `RETURNING` stay without it, so that
+// their `ExecuteDsl` impl above can fall back to one statement per row.
+impl<V, T, QId, Op, Ret, const STATIC_QUERY_ID: bool> QueryFragment<Sqlite>
+    for BatchInsertStatement<T, V, QId, Op, ReturningClause<Ret>, STATIC_QUERY_ID>
+where
+    T: Table + QueryFragment<Sqlite>,
+    Op: QueryFragment<Sqlite>,
+    V: InsertValues<T, Sqlite> + LeftOutColumns,
+    ValuesClause<V, T>: QueryFragment<Sqlite>,
+    ReturningClause<Ret>: QueryFragment<Sqlite>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Sqlite>) -> QueryResult<()> {
+        if !STATIC_QUERY_ID {
+            out.unsafe_to_cache_prepared();
+        }
+
+        let rows = &self.records.values;
+        if rows.is_empty() {
+            out.push_sql("SELECT 1 FROM ");
+            self.target.walk_ast(out.reborrow())?;
+            out.push_sql(" WHERE 1=0");
+            return Ok(());
+        }
+        if !rows_share_columns(rows) {
+            return Err(Error::QueryBuilderError(
+                "SQLite can only return the rows of batch inserts whose rows insert the same \
+                 columns"
+                    .into(),
+            ));
+        }
+
+        self.operator.walk_ast(out.reborrow())?;
+        out.push_sql(" INTO ");
+        self.target.walk_ast(out.reborrow())?;
+        out.push_sql(" ");
+        walk_rows(rows, out.reborrow())?;
+        self.returning.walk_ast(out.reborrow())?;
+        Ok(())
+    }
+}
@@ -5,9 +5,15 @@ use crate::result::QueryResult;
 
 use super::Sqlite;
 
+mod insert_with_default;
 mod limit_offset;
 mod returning;
 
+#[cfg(target_arch = "wasm32")]
+pub(crate) use self::insert_with_default::{
+    rows_share_columns, LeftOutColumns, SqliteBatchInsertWrapper,
+};
+
 /// Constructs SQL queries for use with the SQLite backend
 #[allow(missing_debug_implementations)]
 #[derive(Default)]
@@ -0,0 +1,270 @@
+//! Running functions on a connection owned by another lunatic process
+//!
+//! SQLite connections are resources of the process that opened them, so other
+//! processes can only use them by asking the owner to run a function for
+//! them. Processes of an application share their code, which allows sending
+//! plain function pointers, like lunatic itself does for the handlers of
+//! `AbstractProcess` requests. Arguments and results travel bincode encoded.
+//!
+//! bincode is no self-describing format, so decoding a value of another type
+//! may succeed with garbage. Functions called by name are checked against the
+//! [`signature`] of the types the caller expects instead.
+
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error as StdError;
+use std::ffi::CString;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::QueryResult;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::SqliteConnection;
+
+/// A function that can be run by the process owning the connection
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(super) struct RemoteFunction {
+    trampoline: i32,
+    function: i32,
+    signature: u64,
+}
+
+impl RemoteFunction {
+    pub(super) fn new<A, R>(function: fn(&mut SqliteConnection, A) -> QueryResult<R>) -> Self
+    where
+        A: DeserializeOwned,
+        R: Serialize,
+    {
+        RemoteFunction {
+            trampoline: trampoline::<A, R> as *const () as usize as i32,
+            function: function as *const () as usize as i32,
+            signature: signature::<A, R>(),
+        }
+    }
+
+    /// The [`signature`] of the argument and result types of the function
+    pub(super) fn signature(self) -> u64 {
+        self.signature
+    }
+
+    /// Runs the function with an argument encoded by [`encode_argument`],
+    /// returning its encoded result
+    pub(super) fn call(self, connection: &mut SqliteConnection, argument: &[u8]) -> Vec<u8> {
+        let trampoline = unsafe {
+            std::mem::transmute::<i32, fn(&mut SqliteConnection, i32, &[u8]) -> Vec<u8>>(
+                self.trampoline,
+            )
+        };
+        trampoline(connection, self.function, argument)
+    }
+}
+
+/// A function call to run in the process owning the connection
+#[derive(Serialize, Deserialize)]
+pub(super) struct RemoteCall {
+    function: RemoteFunction,
+    argument: Vec<u8>,
+}
+
+impl RemoteCall {
+    pub(super) fn new<A, R>(
+        function: fn(&mut SqliteConnection, A) -> QueryResult<R>,
+        argument: &A,
+    ) -> Self
+    where
+        A: Serialize + DeserializeOwned,
+        R: Serialize,
+    {
+        RemoteCall {
+            function: RemoteFunction::new(function),
+            argument: encode_argument(argument),
+        }
+    }
+
+    pub(super) fn call(self, connection: &mut SqliteConnection) -> Vec<u8> {
+        self.function.call(connection, &self.argument)
+    }
+}
+
+/// A fingerprint of the argument and result types of a function
+///
+/// Type names are not unique across builds, but all processes of an
+/// application run the same module.
+pub(super) fn signature<A, R>() -> u64 {
+    let mut hasher = DefaultHasher::new();
+    std::any::type_name::<A>().hash(&mut hasher);
+    std::any::type_name::<R>().hash(&mut hasher);
+    hasher.finish()
+}
+
+pub(super) fn encode_argument<A: Serialize>(argument: &A) -> Vec<u8> {
+    bincode::serialize(argument).expect("failed to encode the argument")
+}
+
+/// The encoded result of a function that could not be called
+pub(super) fn encode_error(error: RemoteError) -> Vec<u8> {
+    // bincode encodes the error variant the same way for every `Ok` type
+    bincode::serialize(&Result::<(), _>::Err(error)).expect("failed to encode the error")
+}
+
+/// Decodes the result returned by [`RemoteFunction::call`]
+pub(super) fn decode_result<R: DeserializeOwned>(result: &[u8]) -> QueryResult<R> {
+    match bincode::deserialize::<Result<R, RemoteError>>(result) {
+        Ok(result) => result.map_err(Error::from),
+        Err(e) => Err(Error::DeserializationError(e)),
+    }
+}
+
+fn trampoline<A, R>(connection: &mut SqliteConnection, function: i32, argument: &[u8]) -> Vec<u8>
+where
+    A: DeserializeOwned,
+    R: Serialize,
+{
+    let function = unsafe {
+        std::mem::transmute::<i32, fn(&mut SqliteConnection, A) -> QueryResult<R>>(function)
+    };
+    let argument = match bincode::deserialize(argument) {
+        Ok(argument) => argument,
+        Err(e) => return encode_error(RemoteError::Deserialization(e.to_string())),
+    };
+    let result = function(connection, argument).map_err(RemoteError::from);
+    bincode::serialize(&result).expect("failed to encode the result")
+}
+
+/// diesel's `Error` in a form that can be sent between processes
+///
+/// Errors carrying boxed values are reduced to their message.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) enum RemoteError {
+    /// The bytes of the string containing a nul byte
+    InvalidCString(Vec<u8>),
+    Database(RemoteErrorKind, String),
+    NotFound,
+    QueryBuilder(String),
+    Deserialization(String),
+    Serialization(String),
+    RollbackErrorOnCommit {
+        rollback_error: Box<RemoteError>,
+        commit_error: Box<RemoteError>,
+    },
+    RollbackTransaction,
+    AlreadyInTransaction,
+    NotInTransaction,
+    BrokenTransactionManager,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) enum RemoteErrorKind {
+    UniqueViolation,
+    ForeignKeyViolation,
+    UnableToSendCommand,
+    SerializationFailure,
+    ReadOnlyTransaction,
+    NotNullViolation,
+    CheckViolation,
+    ClosedConnection,
+    Unknown,
+}
+
+/// The message of an error that was sent by another process
+#[derive(Debug)]
+struct RemoteMessage(String);
+
+impl fmt::Display for RemoteMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl StdError for RemoteMessage {}
+
+impl From<Error> for RemoteError {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::InvalidCString(e) => RemoteError::InvalidCString(e.into_vec()),
+            Error::DatabaseError(kind, info) => {
+                let kind = match kind {
+                    DatabaseErrorKind::UniqueViolation => RemoteErrorKind::UniqueViolation,
+                    DatabaseErrorKind::ForeignKeyViolation => RemoteErrorKind::ForeignKeyViolation,
+                    DatabaseErrorKind::UnableToSendCommand => RemoteErrorKind::UnableToSendCommand,
+                    DatabaseErrorKind::SerializationFailure => {
+                        RemoteErrorKind::SerializationFailure
+                    }
+                    DatabaseErrorKind::ReadOnlyTransaction => RemoteErrorKind::ReadOnlyTransaction,
+                    DatabaseErrorKind::NotNullViolation => RemoteErrorKind::NotNullViolation,
+                    DatabaseErrorKind::CheckViolation => RemoteErrorKind::CheckViolation,
+                    DatabaseErrorKind::ClosedConnection => RemoteErrorKind::ClosedConnection,
+                    _ => RemoteErrorKind::Unknown,
+                };
+                RemoteError::Database(kind, info.message().to_owned())
+            }
+            Error::NotFound => RemoteError::NotFound,
+            Error::QueryBuilderError(e) => RemoteError::QueryBuilder(e.to_string()),
+            Error::DeserializationError(e) => RemoteError::Deserialization(e.to_string()),
+            Error::SerializationError(e) => RemoteError::Serialization(e.to_string()),
+            Error::RollbackErrorOnCommit {
+                rollback_error,
+                commit_error,
+            } => RemoteError::RollbackErrorOnCommit {
+                rollback_error: Box::new(RemoteError::from(*rollback_error)),
+                commit_error: Box::new(RemoteError::from(*commit_error)),
+            },
+            Error::RollbackTransaction => RemoteError::RollbackTransaction,
+            Error::AlreadyInTransaction => RemoteError::AlreadyInTransaction,
+            Error::NotInTransaction => RemoteError::NotInTransaction,
+            Error::BrokenTransactionManager => RemoteError::BrokenTransactionManager,
+            // diesel 2.0 has no other variants, later ones are reported like
+            // unknown database errors, as diesel does for unknown error codes
+            e => RemoteError::Database(RemoteErrorKind::Unknown, e.to_string()),
+        }
+    }
+}
+
+impl From<RemoteError> for Error {
+    fn from(error: RemoteError) -> Self {
+        match error {
+            RemoteError::InvalidCString(bytes) => Error::InvalidCString(
+                CString::new(bytes).expect_err("the string contained a nul byte"),
+            ),
+            RemoteError::Database(kind, message) => {
+                let kind = match kind {
+                    RemoteErrorKind::UniqueViolation => DatabaseErrorKind::UniqueViolation,
+                    RemoteErrorKind::ForeignKeyViolation => DatabaseErrorKind::ForeignKeyViolation,
+                    RemoteErrorKind::UnableToSendCommand => DatabaseErrorKind::UnableToSendCommand,
+                    RemoteErrorKind::SerializationFailure => {
+                        DatabaseErrorKind::SerializationFailure
+                    }
+                    RemoteErrorKind::ReadOnlyTransaction => DatabaseErrorKind::ReadOnlyTransaction,
+                    RemoteErrorKind::NotNullViolation => DatabaseErrorKind::NotNullViolation,
+                    RemoteErrorKind::CheckViolation => DatabaseErrorKind::CheckViolation,
+                    RemoteErrorKind::ClosedConnection => DatabaseErrorKind::ClosedConnection,
+                    RemoteErrorKind::Unknown => DatabaseErrorKind::Unknown,
+                };
+                Error::DatabaseError(kind, Box::new(message))
+            }
+            RemoteError::NotFound => Error::NotFound,
+            RemoteError::QueryBuilder(message) => {
+                Error::QueryBuilderError(Box::new(RemoteMessage(message)))
+            }
+            RemoteError::Deserialization(message) => {
+                Error::DeserializationError(Box::new(RemoteMessage(message)))
+            }
+            RemoteError::Serialization(message) => {
+                Error::SerializationError(Box::new(RemoteMessage(message)))
+            }
+            RemoteError::RollbackErrorOnCommit {
+                rollback_error,
+                commit_error,
+            } => Error::RollbackErrorOnCommit {
+                rollback_error: Box::new(Error::from(*rollback_error)),
+                commit_error: Box::new(Error::from(*commit_error)),
+            },
+            RemoteError::RollbackTransaction => Error::RollbackTransaction,
+            RemoteError::AlreadyInTransaction => Error::AlreadyInTransaction,
+            RemoteError::NotInTransaction => Error::NotInTransaction,
+            RemoteError::BrokenTransactionManager => Error::BrokenTransactionManager,
+        }
+    }
+}
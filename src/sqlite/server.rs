@@ -0,0 +1,432 @@
+//! A lunatic process owning a SQLite connection, running the queries of
+//! other processes
+//!
+//! Processes can't share a connection, and connections of different
+//! processes to the same database wait on each other's write locks. A
+//! [`DbServer`] owns the only connection to a database instead. Other
+//! processes send it queries built with diesel's query builder, which are
+//! sent as SQL and bind values, and get the rows back to deserialize them
+//! into their own types. Queries run one after the other, so writes never
+//! wait for a lock.
+//!
+//! ```rust,ignore
+//! use diesel::sqlite::server::DbServerConfig;
+//!
+//! fn publish(connection: &mut SqliteConnection, id: i32) -> QueryResult<usize> {
+//!     diesel::update(posts::table.find(id))
+//!         .set(posts::published.eq(true))
+//!         .execute(connection)
+//! }
+//!
+//! let server = DbServerConfig::new("app.db")
+//!     .register("publish", publish)
+//!     .build()?;
+//! let titles = server.load::<String, _>(posts::table.select(posts::title))?;
+//! server.call::<_, usize>("publish", 1)?;
+//! ```
+//!
+//! [`DbServer`] handles can be cloned and sent to other processes. The server
+//! is an `AbstractProcess` ([`DbServerProcess`]) taking a [`DbServerConfig`],
+//! so it can be started as the child of a supervisor and looked up by name
+//! with [`DbServer::lookup`].
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use diesel::deserialize::FromSqlRow;
+use diesel::query_builder::{
+    AsQuery, BatchInsert, InsertStatement, QueryBuilder, QueryFragment, QueryId, ValuesClause,
+};
+use diesel::result::{ConnectionError, Error};
+use diesel::{Connection, ConnectionResult, QueryResult, Table};
+use lunatic::process::{AbstractProcess, ProcessRef, Request, RequestHandler, StartProcess};
+use lunatic_sqlite_api::wire_format::{BindKey, BindList, BindPair, SqliteRow as WireRow};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::bind_collector::SqliteBindCollector;
+use super::query_builder::{rows_share_columns, LeftOutColumns, SqliteBatchInsertWrapper};
+use super::remote::{self, RemoteCall, RemoteError, RemoteFunction};
+use super::{
+    ColumnNames, DeserializationMode, Sqlite, SqliteConnection, SqliteQueryBuilder, SqliteRow,
+};
+
+/// The settings of a [`DbServer`]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DbServerConfig {
+    database_url: String,
+    functions: HashMap<String, RemoteFunction>,
+}
+
+impl DbServerConfig {
+    /// A server owning a connection to `database_url`
+    pub fn new(database_url: impl Into<String>) -> Self {
+        DbServerConfig {
+            database_url: database_url.into(),
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Registers a function that other processes can call by its `name` with
+    /// [`DbServer::call`]
+    ///
+    /// Functions run with the connection of the server. Non-capturing
+    /// closures can be registered as well.
+    pub fn register<A, R>(
+        mut self,
+        name: impl Into<String>,
+        function: fn(&mut SqliteConnection, A) -> QueryResult<R>,
+    ) -> Self
+    where
+        A: DeserializeOwned,
+        R: Serialize,
+    {
+        self.functions
+            .insert(name.into(), RemoteFunction::new(function));
+        self
+    }
+
+    /// Starts the server process, linked to the calling process
+    ///
+    /// Fails if the connection cannot be established.
+    pub fn build(self) -> ConnectionResult<DbServer> {
+        let server = DbServer(DbServerProcess::start_link(self, None));
+        match server.0.request(InitError) {
+            None => Ok(server),
+            Some(message) => {
+                server.0.shutdown();
+                Err(ConnectionError::BadConnection(message))
+            }
+        }
+    }
+}
+
+/// A handle of a running [`DbServerProcess`]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DbServer(ProcessRef<DbServerProcess>);
+
+impl DbServer {
+    /// The server started with `name`, e.g. by a supervisor
+    pub fn lookup(name: &str) -> Option<Self> {
+        ProcessRef::lookup(name).map(DbServer)
+    }
+
+    /// The process of the server
+    pub fn process(&self) -> &ProcessRef<DbServerProcess> {
+        &self.0
+    }
+
+    /// Runs a query returning no rows, like `RunQueryDsl::execute`, and
+    /// returns the number of affected rows
+    ///
+    /// Batch inserts without a `RETURNING` clause are no single query, as
+    /// rows leaving out different columns are inserted one by one. They are
+    /// run with [`insert_batch`](Self::insert_batch) instead.
+    pub fn execute<Q>(&self, query: Q) -> QueryResult<usize>
+    where
+        Q: QueryFragment<Sqlite> + QueryId,
+    {
+        let query = SerializedQuery::new(&query)?;
+        self.0.request(Execute(query)).map_err(Error::from)
+    }
+
+    /// Runs a batch insert without a `RETURNING` clause, like
+    /// `RunQueryDsl::execute`, and returns the number of inserted rows
+    ///
+    /// The rows are inserted by a single statement if they all insert the
+    /// same columns, otherwise one by one inside of a transaction.
+    pub fn insert_batch<T, V, QId, Op, const STATIC_QUERY_ID: bool>(
+        &self,
+        query: BatchInsertStatement<T, V, QId, Op, STATIC_QUERY_ID>,
+    ) -> QueryResult<usize>
+    where
+        T: Table + Copy,
+        Op: Copy,
+        V: LeftOutColumns,
+        InsertStatement<
+            T,
+            SqliteBatchInsertWrapper<Vec<ValuesClause<V, T>>, T, QId, STATIC_QUERY_ID>,
+            Op,
+        >: QueryFragment<Sqlite>,
+        for<'a> InsertStatement<T, &'a ValuesClause<V, T>, Op>: QueryFragment<Sqlite>,
+    {
+        if rows_share_columns(&query.records.values) {
+            let records = SqliteBatchInsertWrapper::new(query.records);
+            let statement =
+                InsertStatement::new(query.target, records, query.operator, query.returning);
+            let query = SerializedQuery::new(&statement)?;
+            return self.0.request(Execute(query)).map_err(Error::from);
+        }
+
+        let queries = query
+            .records
+            .values
+            .iter()
+            .map(|record| {
+                let statement =
+                    InsertStatement::new(query.target, record, query.operator, query.returning);
+                SerializedQuery::new(&statement)
+            })
+            .collect::<QueryResult<_>>()?;
+        self.0
+            .request(ExecuteInTransaction(queries))
+            .map_err(Error::from)
+    }
+
+    /// Loads all rows of a query, like `RunQueryDsl::load`
+    pub fn load<U, Q>(&self, query: Q) -> QueryResult<Vec<U>>
+    where
+        Q: AsQuery,
+        Q::Query: QueryFragment<Sqlite> + QueryId,
+        U: FromSqlRow<Q::SqlType, Sqlite>,
+    {
+        let query = SerializedQuery::new(&query.as_query())?;
+        let rows = self.0.request(Load(query))?;
+        let field_names = Rc::new(ColumnNames::new(rows.column_names));
+        rows.rows
+            .into_iter()
+            .map(|inner_row| {
+                let row = SqliteRow {
+                    inner_row,
+                    field_names: Rc::clone(&field_names),
+                    deserialization_mode: rows.deserialization_mode,
+                };
+                U::build_from_row(&row).map_err(Error::DeserializationError)
+            })
+            .collect()
+    }
+
+    /// Loads the first row of a query, like `RunQueryDsl::get_result`
+    ///
+    /// Fails with `Error::NotFound` if there is none.
+    pub fn get_result<U, Q>(&self, query: Q) -> QueryResult<U>
+    where
+        Q: AsQuery,
+        Q::Query: QueryFragment<Sqlite> + QueryId,
+        U: FromSqlRow<Q::SqlType, Sqlite>,
+    {
+        self.load(query)?.into_iter().next().ok_or(Error::NotFound)
+    }
+
+    /// Calls the function registered as `name`
+    ///
+    /// `A` and `R` have to match the types of the registered function, a
+    /// mismatch is reported as `Error::DeserializationError` without calling
+    /// it. Names without a function are reported as `Error::QueryBuilderError`.
+    pub fn call<A, R>(&self, name: &str, argument: A) -> QueryResult<R>
+    where
+        A: Serialize,
+        R: DeserializeOwned,
+    {
+        let result = self.0.request(Call {
+            name: name.to_owned(),
+            signature: remote::signature::<A, R>(),
+            argument: remote::encode_argument(&argument),
+        });
+        remote::decode_result(&result)
+    }
+
+    /// Runs `function` with the connection of the server
+    ///
+    /// Like [`PooledConnection::run`](super::pool::PooledConnection::run),
+    /// for functions that are not registered.
+    pub fn run<A, R>(
+        &self,
+        argument: A,
+        function: fn(&mut SqliteConnection, A) -> QueryResult<R>,
+    ) -> QueryResult<R>
+    where
+        A: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned,
+    {
+        let result = self.0.request(Run(RemoteCall::new(function, &argument)));
+        remote::decode_result(&result)
+    }
+}
+
+/// A batch insert without a `RETURNING` clause
+type BatchInsertStatement<T, V, QId, Op, const STATIC_QUERY_ID: bool> =
+    InsertStatement<T, BatchInsert<Vec<ValuesClause<V, T>>, T, QId, STATIC_QUERY_ID>, Op>;
+
+/// The SQL and bind values of a query
+///
+/// The server caches the prepared statements of queries by their SQL, unless
+/// diesel deems the query unsafe to cache, e.g. for `IN` lists of varying
+/// length.
+#[derive(Serialize, Deserialize)]
+struct SerializedQuery {
+    sql: String,
+    binds: BindList,
+    is_safe_to_cache_prepared: bool,
+}
+
+impl SerializedQuery {
+    fn new<T>(query: &T) -> QueryResult<Self>
+    where
+        T: QueryFragment<Sqlite>,
+    {
+        let mut query_builder = SqliteQueryBuilder::new();
+        query.to_sql(&mut query_builder, &Sqlite)?;
+        let mut bind_collector = SqliteBindCollector::new();
+        query.collect_binds(&mut bind_collector, &mut (), &Sqlite)?;
+        let binds = (1..)
+            .zip(bind_collector.binds)
+            .map(|(idx, (value, _))| BindPair(BindKey::Numeric(idx), value.into_bind_value()))
+            .collect();
+        Ok(SerializedQuery {
+            sql: query_builder.finish(),
+            binds: BindList(binds),
+            is_safe_to_cache_prepared: query.is_safe_to_cache_prepared(&Sqlite)?,
+        })
+    }
+}
+
+/// The rows of a query, still to be deserialized
+#[derive(Serialize, Deserialize)]
+struct SerializedRows {
+    column_names: Vec<String>,
+    rows: Vec<WireRow>,
+    deserialization_mode: DeserializationMode,
+}
+
+/// The process of a server, usually used through [`DbServer`]
+pub struct DbServerProcess {
+    connection: Result<SqliteConnection, String>,
+    functions: HashMap<String, RemoteFunction>,
+}
+
+impl DbServerProcess {
+    fn connection(&mut self) -> &mut SqliteConnection {
+        self.connection
+            .as_mut()
+            .expect("the server failed to establish its connection")
+    }
+}
+
+impl AbstractProcess for DbServerProcess {
+    type Arg = DbServerConfig;
+    type State = Self;
+
+    fn init(_: ProcessRef<Self>, config: DbServerConfig) -> Self {
+        DbServerProcess {
+            connection: SqliteConnection::establish(&config.database_url)
+                .map_err(|e| e.to_string()),
+            functions: config.functions,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct InitError;
+
+impl RequestHandler<InitError> for DbServerProcess {
+    type Response = Option<String>;
+
+    fn handle(state: &mut Self, _: InitError) -> Option<String> {
+        state.connection.as_ref().err().cloned()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Execute(SerializedQuery);
+
+impl RequestHandler<Execute> for DbServerProcess {
+    type Response = Result<usize, RemoteError>;
+
+    fn handle(state: &mut Self, Execute(query): Execute) -> Result<usize, RemoteError> {
+        Ok(state.connection().execute_serialized(
+            &query.sql,
+            &query.binds,
+            query.is_safe_to_cache_prepared,
+        )?)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExecuteInTransaction(Vec<SerializedQuery>);
+
+impl RequestHandler<ExecuteInTransaction> for DbServerProcess {
+    type Response = Result<usize, RemoteError>;
+
+    fn handle(
+        state: &mut Self,
+        ExecuteInTransaction(queries): ExecuteInTransaction,
+    ) -> Result<usize, RemoteError> {
+        Ok(state.connection().transaction(|connection| {
+            queries
+                .iter()
+                .map(|query| {
+                    connection.execute_serialized(
+                        &query.sql,
+                        &query.binds,
+                        query.is_safe_to_cache_prepared,
+                    )
+                })
+                .sum::<QueryResult<usize>>()
+        })?)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Load(SerializedQuery);
+
+impl RequestHandler<Load> for DbServerProcess {
+    type Response = Result<SerializedRows, RemoteError>;
+
+    fn handle(state: &mut Self, Load(query): Load) -> Result<SerializedRows, RemoteError> {
+        let connection = state.connection();
+        let (column_names, rows) = connection.load_serialized(
+            &query.sql,
+            &query.binds,
+            query.is_safe_to_cache_prepared,
+        )?;
+        Ok(SerializedRows {
+            column_names,
+            rows,
+            deserialization_mode: connection.deserialization_mode(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Call {
+    name: String,
+    signature: u64,
+    argument: Vec<u8>,
+}
+
+impl RequestHandler<Call> for DbServerProcess {
+    type Response = Vec<u8>;
+
+    fn handle(
+        state: &mut Self,
+        Call {
+            name,
+            signature,
+            argument,
+        }: Call,
+    ) -> Vec<u8> {
+        match state.functions.get(&name) {
+            Some(function) if function.signature() != signature => {
+                remote::encode_error(RemoteError::Deserialization(format!(
+                    "`{name}` is registered with other argument or result types"
+                )))
+            }
+            Some(&function) => function.call(state.connection(), &argument),
+            None => remote::encode_error(RemoteError::QueryBuilder(format!(
+                "no function is registered as `{name}`"
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Run(RemoteCall);
+
+impl RequestHandler<Run> for DbServerProcess {
+    type Response = Vec<u8>;
+
+    fn handle(state: &mut Self, Run(call): Run) -> Vec<u8> {
+        call.call(state.connection())
+    }
+}
@@ -0,0 +1,384 @@
+//! The raw values `FromSql` impls of the SQLite backend deserialize from
+
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+use diesel::deserialize;
+use lunatic_sqlite_api::wire_format;
+use serde::{Deserialize, Serialize};
+
+use super::SqliteType;
+
+/// How values are converted into the Rust types requested for them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DeserializationMode {
+    /// Converts values between storage classes following SQLite's affinity
+    /// rules: the text `'42'` and the real `42.0` are read as the integer
+    /// `42`, integers are read as reals and numbers are read as their text.
+    /// Values too large for the requested type are truncated like with
+    /// SQLite's C API, e.g. `70000` is read as the `i16` `4464`.
+    #[default]
+    Tolerant,
+    /// Requires the storage class of a value to match the requested type,
+    /// e.g. integer types can only be read from `INTEGER` values, and the
+    /// value to fit into it: `70000` read as an `i16` is an error instead of
+    /// being truncated.
+    Strict,
+}
+
+/// A value that could not be converted into the requested Rust type
+///
+/// This is the error of diesel's `Error::DeserializationError` for the values
+/// of the SQLite backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqliteDeserializationError {
+    column_name: Option<String>,
+    message: String,
+}
+
+impl SqliteDeserializationError {
+    /// The name of the column the value was read from
+    ///
+    /// Arguments of custom SQL functions have no column name.
+    pub fn column_name(&self) -> Option<&str> {
+        self.column_name.as_deref()
+    }
+
+    /// A description of what went wrong
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for SqliteDeserializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.column_name {
+            Some(ref column_name) => write!(f, "column `{column_name}`: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl Error for SqliteDeserializationError {}
+
+/// Raw data of a non-`NULL` value of the SQLite backend, as given to `FromSql`
+///
+/// Besides the value sent by the host it knows the column the value was read
+/// from and the `DeserializationMode` of the connection, so that
+/// deserialization errors can point at the column.
+///
+/// Like the `SqliteValue` of diesel's own SQLite backend it can be inspected
+/// with [`value_type`](Self::value_type) and read with the infallible
+/// `read_*` methods, so that `FromSql` impls written against diesel compile
+/// here as well. Such impls should name the value as
+/// `diesel::backend::RawValue<'_, Sqlite>` or `SqliteValue<'_, '_, '_>`.
+///
+/// Values only borrow from the row they are read from, `'stmt` and `'query`
+/// just mirror the lifetimes of diesel's type.
+#[derive(Clone)]
+pub struct SqliteValue<'row, 'stmt, 'query> {
+    value: &'row wire_format::SqliteValue,
+    column_name: Option<&'row str>,
+    mode: DeserializationMode,
+    /// The text of a number, once `read_text` converted it
+    converted_text: OnceCell<String>,
+    _statement: PhantomData<(&'stmt (), &'query ())>,
+}
+
+impl<'row> SqliteValue<'row, '_, '_> {
+    pub(super) fn new(
+        value: &'row wire_format::SqliteValue,
+        column_name: Option<&'row str>,
+        mode: DeserializationMode,
+    ) -> Self {
+        Self {
+            value,
+            column_name,
+            mode,
+            converted_text: OnceCell::new(),
+            _statement: PhantomData,
+        }
+    }
+
+    /// The value as sent by the host
+    pub fn raw_value(&self) -> &'row wire_format::SqliteValue {
+        self.value
+    }
+
+    /// The name of the column the value was read from
+    pub fn column_name(&self) -> Option<&'row str> {
+        self.column_name
+    }
+
+    /// The mode used to convert the value into Rust types
+    pub fn mode(&self) -> DeserializationMode {
+        self.mode
+    }
+
+    /// The storage class of the value
+    ///
+    /// `INTEGER` values are reported as `SqliteType::Long` and `NULL` as
+    /// `None`, the same way diesel's SQLite backend does.
+    pub fn value_type(&self) -> Option<SqliteType> {
+        match *self.value {
+            wire_format::SqliteValue::Null => None,
+            wire_format::SqliteValue::Blob(_) => Some(SqliteType::Binary),
+            wire_format::SqliteValue::Text(_) => Some(SqliteType::Text),
+            wire_format::SqliteValue::Double(_) => Some(SqliteType::Double),
+            wire_format::SqliteValue::Integer(_) | wire_format::SqliteValue::I64(_) => {
+                Some(SqliteType::Long)
+            }
+        }
+    }
+
+    /// Reads the value as text, converting other storage classes the way
+    /// `sqlite3_value_text` does
+    ///
+    /// Numbers are read as their text and blobs as their bytes, replacing
+    /// invalid UTF-8. `NULL` is read as the empty string. Use
+    /// [`value_type`](Self::value_type) to find out how the value is stored.
+    pub fn read_text(&self) -> &str {
+        use self::wire_format::SqliteValue::*;
+
+        match self.value {
+            Text(text) => text,
+            Blob(bytes) => match std::str::from_utf8(bytes) {
+                Ok(text) => text,
+                Err(_) => self
+                    .converted_text
+                    .get_or_init(|| String::from_utf8_lossy(bytes).into_owned()),
+            },
+            &Integer(int) | &I64(int) => self.converted_text.get_or_init(|| int.to_string()),
+            &Double(double) => self.converted_text.get_or_init(|| double.to_string()),
+            Null => "",
+        }
+    }
+
+    /// Reads the value as bytes, converting other storage classes the way
+    /// `sqlite3_value_blob` does
+    ///
+    /// Text is read as its UTF-8 bytes, numbers as the bytes of their text and
+    /// `NULL` as an empty slice.
+    pub fn read_blob(&self) -> &[u8] {
+        match self.value {
+            wire_format::SqliteValue::Blob(bytes) => bytes,
+            _ => self.read_text().as_bytes(),
+        }
+    }
+
+    /// Reads the value as a 32 bit integer, keeping only the lower 32 bits of
+    /// [`read_long`](Self::read_long) like `sqlite3_value_int` does
+    pub fn read_integer(&self) -> i32 {
+        self.read_long() as i32
+    }
+
+    /// Reads the value as a 64 bit integer, converting other storage classes
+    /// the way `sqlite3_value_int64` does
+    ///
+    /// Reals are truncated towards zero and saturate at the bounds of `i64`.
+    /// Text and blobs are read up to the first character that is not part of
+    /// an integer, so `'12.5kg'` is read as `12`. `NULL` and text without a
+    /// leading integer are read as `0`.
+    pub fn read_long(&self) -> i64 {
+        use self::wire_format::SqliteValue::*;
+
+        match self.value {
+            &Integer(int) | &I64(int) => int,
+            &Double(double) => double as i64,
+            Text(text) => integer_prefix(text),
+            Blob(bytes) => integer_prefix(&String::from_utf8_lossy(bytes)),
+            Null => 0,
+        }
+    }
+
+    /// Reads the value as a real number, converting other storage classes the
+    /// way `sqlite3_value_double` does
+    ///
+    /// Text and blobs are read up to the first character that is not part of
+    /// a real number, so `'12.5kg'` is read as `12.5`. `NULL` and text without
+    /// a leading number are read as `0.0`.
+    pub fn read_double(&self) -> f64 {
+        use self::wire_format::SqliteValue::*;
+
+        match self.value {
+            &Double(double) => double,
+            &Integer(int) | &I64(int) => int as f64,
+            Text(text) => real_prefix(text),
+            Blob(bytes) => real_prefix(&String::from_utf8_lossy(bytes)),
+            Null => 0.0,
+        }
+    }
+
+    /// Creates a `SqliteDeserializationError` naming the column of the value
+    pub fn error(&self, message: impl Into<String>) -> Box<dyn Error + Send + Sync> {
+        Box::new(SqliteDeserializationError {
+            column_name: self.column_name.map(str::to_owned),
+            message: message.into(),
+        })
+    }
+
+    fn storage_class(&self) -> &'static str {
+        match *self.value {
+            wire_format::SqliteValue::Null => "NULL",
+            wire_format::SqliteValue::Blob(_) => "BLOB",
+            wire_format::SqliteValue::Text(_) => "TEXT",
+            wire_format::SqliteValue::Double(_) => "REAL",
+            wire_format::SqliteValue::Integer(_) | wire_format::SqliteValue::I64(_) => "INTEGER",
+        }
+    }
+
+    fn unexpected_storage_class(&self, expected: &str) -> Box<dyn Error + Send + Sync> {
+        self.error(format!(
+            "expected {expected}, found {} value {:?}",
+            self.storage_class(),
+            self.value
+        ))
+    }
+
+    /// Reads the value as an integer
+    pub(super) fn integer(&self) -> deserialize::Result<i64> {
+        use self::wire_format::SqliteValue::*;
+
+        match (self.mode, self.value) {
+            (_, &Integer(int) | &I64(int)) => Ok(int),
+            (DeserializationMode::Strict, _) => Err(self.unexpected_storage_class("an INTEGER")),
+            // like a column with `INTEGER` affinity, only take reals and text
+            // that can be converted without losing anything
+            (DeserializationMode::Tolerant, &Double(double)) => self.integral_double(double),
+            (DeserializationMode::Tolerant, Text(text)) => match text.trim().parse::<i64>() {
+                Ok(int) => Ok(int),
+                Err(_) => match text.trim().parse::<f64>() {
+                    Ok(double) => self.integral_double(double),
+                    Err(_) => Err(self.unexpected_storage_class("an integer")),
+                },
+            },
+            (DeserializationMode::Tolerant, _) => Err(self.unexpected_storage_class("an integer")),
+        }
+    }
+
+    fn integral_double(&self, double: f64) -> deserialize::Result<i64> {
+        // `i64::MAX as f64` is rounded up to 2^63, which is out of range
+        if double.fract() == 0.0 && double >= i64::MIN as f64 && double < i64::MAX as f64 {
+            Ok(double as i64)
+        } else {
+            Err(self.error(format!("{double} is not an integer in the range of i64")))
+        }
+    }
+
+    /// Reads the value as a real number
+    pub(super) fn double(&self) -> deserialize::Result<f64> {
+        use self::wire_format::SqliteValue::*;
+
+        match (self.mode, self.value) {
+            (_, &Double(double)) => Ok(double),
+            (DeserializationMode::Strict, _) => Err(self.unexpected_storage_class("a REAL")),
+            (DeserializationMode::Tolerant, &Integer(int) | &I64(int)) => Ok(int as f64),
+            (DeserializationMode::Tolerant, Text(text)) => match text.trim().parse::<f64>() {
+                Ok(double) if double.is_finite() => Ok(double),
+                _ => Err(self.unexpected_storage_class("a real number")),
+            },
+            (DeserializationMode::Tolerant, _) => {
+                Err(self.unexpected_storage_class("a real number"))
+            }
+        }
+    }
+
+    /// Reads the value as text
+    pub(super) fn text(&self) -> deserialize::Result<Cow<'row, str>> {
+        use self::wire_format::SqliteValue::*;
+
+        match (self.mode, self.value) {
+            (_, Text(text)) => Ok(Cow::Borrowed(text)),
+            (DeserializationMode::Strict, _) => Err(self.unexpected_storage_class("TEXT")),
+            (DeserializationMode::Tolerant, &Integer(int) | &I64(int)) => {
+                Ok(Cow::Owned(int.to_string()))
+            }
+            (DeserializationMode::Tolerant, &Double(double)) => Ok(Cow::Owned(double.to_string())),
+            (DeserializationMode::Tolerant, Blob(bytes)) => std::str::from_utf8(bytes)
+                .map(Cow::Borrowed)
+                .map_err(|_| self.error("the BLOB value is not valid UTF-8 text")),
+            (DeserializationMode::Tolerant, Null) => Err(self.unexpected_storage_class("text")),
+        }
+    }
+
+    /// Reads the value as bytes
+    pub(super) fn blob(&self) -> deserialize::Result<&'row [u8]> {
+        use self::wire_format::SqliteValue::*;
+
+        match (self.mode, self.value) {
+            (_, Blob(bytes)) => Ok(bytes),
+            (DeserializationMode::Strict, _) => Err(self.unexpected_storage_class("a BLOB")),
+            (DeserializationMode::Tolerant, Text(text)) => Ok(text.as_bytes()),
+            (DeserializationMode::Tolerant, _) => Err(self.unexpected_storage_class("bytes")),
+        }
+    }
+}
+
+/// Reads the integer at the start of `text` after leading whitespace,
+/// saturating at the bounds of `i64`
+fn integer_prefix(text: &str) -> i64 {
+    let text = text.trim_start();
+    let (negative, digits) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let mut int: i64 = 0;
+    for digit in digits.bytes().take_while(u8::is_ascii_digit) {
+        let digit = i64::from(digit - b'0');
+        int = if negative {
+            int.saturating_mul(10).saturating_sub(digit)
+        } else {
+            int.saturating_mul(10).saturating_add(digit)
+        };
+    }
+    int
+}
+
+/// Reads the real number at the start of `text` after leading whitespace
+fn real_prefix(text: &str) -> f64 {
+    let text = text.trim_start();
+    let bytes = text.as_bytes();
+    let digits_from = |mut end: usize| {
+        while bytes.get(end).is_some_and(u8::is_ascii_digit) {
+            end += 1;
+        }
+        end
+    };
+
+    let mut end = match bytes.first() {
+        Some(b'-' | b'+') => 1,
+        _ => 0,
+    };
+    let integer_end = digits_from(end);
+    let mut has_digits = integer_end > end;
+    end = integer_end;
+    if bytes.get(end) == Some(&b'.') {
+        let fraction_end = digits_from(end + 1);
+        if has_digits || fraction_end > end + 1 {
+            has_digits = true;
+            end = fraction_end;
+        }
+    }
+    if !has_digits {
+        return 0.0;
+    }
+    // an exponent only counts if it has digits, `'1e'` is read as `1`
+    if let Some(b'e' | b'E') = bytes.get(end) {
+        let sign = usize::from(matches!(bytes.get(end + 1), Some(b'-' | b'+')));
+        let exponent_end = digits_from(end + 1 + sign);
+        if exponent_end > end + 1 + sign {
+            end = exponent_end;
+        }
+    }
+    text[..end].parse().unwrap_or(0.0)
+}
+
+impl fmt::Debug for SqliteValue<'_, '_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
@@ -1,15 +1,14 @@
-use super::bind_collector::{InternalSqliteBindValue, SqliteBindCollector};
+use super::bind_collector::{InternalSqliteBindValue, SqliteBindCollector, WireBindList};
 use super::constants::*;
 use super::diesel_backend::SqliteType;
-use super::diesel_connection::RawConnection;
-// use super::bind_collector::{InternalSqliteBindValue, SqliteBindCollector};
-// use super::raw::RawConnection;
-// use super::sqlite_value::OwnedSqliteValue;
+use super::diesel_connection::{last_error, ColumnNames, RawConnection};
 use diesel::connection::statement_cache::{MaybeCached, PrepareForCache};
 use diesel::query_builder::{QueryFragment, QueryId};
-use diesel::result::Error::DatabaseError;
 use diesel::result::*;
+#[cfg(target_arch = "wasm32")]
+use lunatic_sqlite_api::wire_format::BindList;
 use std::ptr::NonNull;
+use std::rc::Rc;
 
 use super::{host_bindings as ffi, Sqlite};
 
@@ -25,86 +24,53 @@ impl Statement {
         sql: &str,
         _is_cached: PrepareForCache,
     ) -> QueryResult<Self> {
-        let (_, statement_id) = ffi::query_prepare(raw_connection.connection_id, sql);
+        let statement_id = ffi::query_prepare(raw_connection.connection_id, sql)
+            .map_err(|_| last_error(raw_connection.connection_id))?;
         Ok(Statement {
             statement_id,
             connection_id: raw_connection.connection_id,
         })
     }
 
-    // The caller of this function has to ensure that:
-    // * Any buffer provided as `SqliteBindValue::BorrowedBinary`, `SqliteBindValue::Binary`
-    // `SqliteBindValue::String` or `SqliteBindValue::BorrowedString` is valid
-    // till either a new value is bound to the same parameter or the underlying
-    // prepared statement is dropped.
-    unsafe fn bind(
-        &mut self,
-        _tpe: SqliteType,
-        value: InternalSqliteBindValue<'_>,
-        bind_index: i32,
-    ) -> QueryResult<Option<NonNull<[u8]>>> {
-        ffi::bind_value(
-            self.statement_id,
-            InternalSqliteBindValue::to_ffi_struct(bind_index, value),
-        );
-        // match ensure_sqlite_ok(result, self.raw_connection()) {
-        //     Ok(()) => Ok(ret_ptr),
-        //     Err(e) => {
-        //         if let Some(ptr) = ret_ptr {
-        //             // This is a `NonNul` ptr so it cannot be null
-        //             // It points to a slice internally as we did not apply
-        //             // any cast above.
-        //             std::mem::drop(Box::from_raw(ptr.as_ptr()))
-        //         }
-        //         Err(e)
-        //     }
-        // }
-        Ok(None)
+    // Binds all values with a single host call. The host copies the values
+    // into SQLite, so borrowed buffers only need to outlive this call.
+    fn bind(&mut self, binds: &[(InternalSqliteBindValue<'_>, SqliteType)]) -> QueryResult<()> {
+        let result = ffi::bind_values(self.statement_id, &WireBindList(binds));
+        self.ensure_sqlite_ok(result)
     }
 
-    fn reset(&mut self) {
-        ffi::sqlite3_reset(self.statement_id);
+    fn ensure_sqlite_ok(&self, result: u32) -> QueryResult<()> {
+        if result == SQLITE_OK {
+            Ok(())
+        } else {
+            Err(last_error(self.connection_id))
+        }
     }
-}
 
-fn last_error(connection_id: u64) -> Error {
-    let sqlite_error = ffi::last_error(connection_id).unwrap();
-    let error_kind = match sqlite_error.code {
-        Some(SQLITE_CONSTRAINT_UNIQUE) | Some(SQLITE_CONSTRAINT_PRIMARYKEY) => {
-            DatabaseErrorKind::UniqueViolation
-        }
-        Some(SQLITE_CONSTRAINT_FOREIGNKEY) => DatabaseErrorKind::ForeignKeyViolation,
-        Some(SQLITE_CONSTRAINT_NOTNULL) => DatabaseErrorKind::NotNullViolation,
-        Some(SQLITE_CONSTRAINT_CHECK) => DatabaseErrorKind::CheckViolation,
-        _ => DatabaseErrorKind::Unknown,
-    };
-    DatabaseError(
-        error_kind,
-        Box::new(
-            sqlite_error
-                .message
-                .unwrap_or_else(|| "Unknown error".to_string()),
-        ),
-    )
+    fn reset(&mut self) -> QueryResult<()> {
+        let result = ffi::sqlite3_reset(self.statement_id);
+        self.ensure_sqlite_ok(result)
+    }
 }
 
 impl Drop for Statement {
     fn drop(&mut self) {
-        // use std::thread::panicking;
-
-        ffi::sqlite3_finalize(self.statement_id);
-        // if let Err(e) = ensure_sqlite_ok(finalize_result, raw_connection) {
-        //     // if panicking() {
-        //     //     write!(
-        //     //         stderr(),
-        //     //         "Error finalizing SQLite prepared statement: {:?}",
-        //     //         e
-        //     //     )
-        //     //     .expect("Error writing to `stderr`");
-        //     // } else {
-        //     panic!("Error finalizing SQLite prepared statement: {:?}", e);
-        //     // }
-        // }
+        use std::io::{stderr, Write};
+        use std::thread::panicking;
+
+        let finalize_result = ffi::sqlite3_finalize(self.statement_id);
+        if let Err(e) = self.ensure_sqlite_ok(finalize_result) {
+            if panicking() {
+                write!(
+                    stderr(),
+                    "Error finalizing SQLite prepared statement: {:?}",
+                    e
+                )
+                .expect("Error writing to `stderr`");
+            } else {
+                panic!("Error finalizing SQLite prepared statement: {:?}", e);
+            }
+        }
     }
 }
 
@@ -125,10 +91,6 @@ pub(crate) struct BoundStatement<'stmt, 'query> {
     // generic type, we use NonNull to communicate
     // that this is a shared buffer
     query: Option<NonNull<dyn QueryFragment<Sqlite> + 'query>>,
-    // we need to store any owned bind values separately, as they are not
-    // contained in the query itself. We use NonNull to
-    // communicate that this is a shared buffer
-    binds_to_free: Vec<(i32, Option<NonNull<[u8]>>)>,
 }
 
 impl<'stmt, 'query> BoundStatement<'stmt, 'query> {
@@ -152,7 +114,6 @@ impl<'stmt, 'query> BoundStatement<'stmt, 'query> {
         let mut ret = BoundStatement {
             statement,
             query: None,
-            binds_to_free: Vec::new(),
         };
 
         ret.bind_buffers(binds)?;
@@ -170,76 +131,19 @@ impl<'stmt, 'query> BoundStatement<'stmt, 'query> {
         &mut self,
         binds: Vec<(InternalSqliteBindValue<'_>, SqliteType)>,
     ) -> QueryResult<()> {
-        // It is useful to preallocate `binds_to_free` because it
-        // - Guarantees that pushing inside it cannot panic, which guarantees the `Drop`
-        //   impl of `BoundStatement` will always re-`bind` as needed
-        // - Avoids reallocations
-        self.binds_to_free.reserve(
-            binds
-                .iter()
-                .filter(|&(b, _)| {
-                    matches!(
-                        b,
-                        InternalSqliteBindValue::BorrowedBinary(_)
-                            | InternalSqliteBindValue::BorrowedString(_)
-                            | InternalSqliteBindValue::String(_)
-                            | InternalSqliteBindValue::Binary(_)
-                    )
-                })
-                .count(),
-        );
-        for (bind_idx, (bind, tpe)) in (1..).zip(binds) {
-            if matches!(
-                bind,
-                InternalSqliteBindValue::BorrowedString(_)
-                    | InternalSqliteBindValue::BorrowedBinary(_)
-            ) {
-                // Store the id's of borrowed binds to unbind them on drop
-                self.binds_to_free.push((bind_idx, None));
-            }
-
-            // It's safe to call bind here as:
-            // * The type and value matches
-            // * We ensure that corresponding buffers lives long enough below
-            // * The statement is not used yet by `step` or anything else
-            let res = unsafe { self.statement.bind(tpe, bind, bind_idx) }?;
-            if let Some(ptr) = res {
-                // Store the id + pointer for a owned bind
-                // as we must unbind and free them on drop
-                self.binds_to_free.push((bind_idx, Some(ptr)));
-            }
+        if binds.is_empty() {
+            return Ok(());
         }
-        Ok(())
+        self.statement.bind(&binds)
     }
 }
 
 impl<'stmt, 'query> Drop for BoundStatement<'stmt, 'query> {
     fn drop(&mut self) {
-        // First reset the statement, otherwise the bind calls
-        // below will fails
-        self.statement.reset();
-
-        for (_idx, buffer) in std::mem::take(&mut self.binds_to_free) {
-            // unsafe {
-            //     // It's always safe to bind null values, as there is no buffer that needs to outlife something
-            //     self.statement
-            //         .bind(SqliteType::Text, InternalSqliteBindValue::Null, idx)
-            //         .expect(
-            //             "Binding a null value should never fail. \
-            //                  If you ever see this error message please open \
-            //                  an issue at diesels issue tracker containing \
-            //                  code how to trigger this message.",
-            //         );
-            // }
-
-            if let Some(buffer) = buffer {
-                unsafe {
-                    // Constructing the `Box` here is safe as we
-                    // got the pointer from a box + it is guaranteed to be not null.
-                    std::mem::drop(Box::from_raw(buffer.as_ptr()));
-                }
-            }
-        }
+        // Reset the statement so that it can be bound again.
+        // Resetting reports the error of the last step again, which
+        // was already returned to the caller, so it is ignored here
+        let _ = self.statement.reset();
 
         if let Some(query) = self.query {
             unsafe {
@@ -255,7 +159,7 @@ impl<'stmt, 'query> Drop for BoundStatement<'stmt, 'query> {
 #[allow(missing_debug_implementations)]
 pub struct StatementUse<'stmt, 'query> {
     pub(crate) statement: BoundStatement<'stmt, 'query>,
-    column_names: Vec<String>,
+    column_names: Option<Rc<ColumnNames>>,
 }
 
 impl<'stmt, 'query> StatementUse<'stmt, 'query> {
@@ -268,10 +172,19 @@ impl<'stmt, 'query> StatementUse<'stmt, 'query> {
     {
         Ok(Self {
             statement: BoundStatement::bind(statement, query)?,
-            column_names: vec![],
+            column_names: None,
         })
     }
 
+    /// Binds values that were collected by another process, for queries
+    /// without binds of their own
+    #[cfg(target_arch = "wasm32")]
+    pub(super) fn bind_list(&mut self, binds: &BindList) -> QueryResult<()> {
+        let statement = &self.statement.statement;
+        let result = ffi::bind_values(statement.statement_id, binds);
+        statement.ensure_sqlite_ok(result)
+    }
+
     pub(super) fn run(mut self) -> QueryResult<()> {
         unsafe {
             // This is safe as we pass `first_step = true`
@@ -294,38 +207,22 @@ impl<'stmt, 'query> StatementUse<'stmt, 'query> {
             _ => Err(last_error(self.statement.statement.connection_id)),
         };
         if first_step {
-            self.column_names = vec![];
+            self.column_names = None;
         }
         res
     }
 
-    // // The returned string pointer is valid until either the prepared statement is
-    // // destroyed by sqlite3_finalize() or until the statement is automatically
-    // // reprepared by the first call to sqlite3_step() for a particular run or
-    // // until the next call to sqlite3_column_name() or sqlite3_column_name16()
-    // // on the same column.
-    // //
-    // // https://sqlite.org/c3ref/column_name.html
-    // //
-    // // Note: This function is marked as unsafe, as calling it can invalidate
-    // // other existing column name pointers on the same column. To prevent that,
-    // // it should maximally be called once per column at all.
-    // fn column_name(&self, idx: i32) -> String {
-    //     let name = {
-    //         let column_name =
-    //             ffi::sqlite3_column_name(self.statement.statement.statement_id, idx as u32);
-    //         assert!(
-    //             !column_name.is_err(),
-    //             "The Sqlite documentation states that it only returns a \
-    //              null pointer here if we are in a OOM condition."
-    //         );
-    //         column_name
-    //     };
-    //     name.expect(
-    //         "The Sqlite documentation states that this is UTF8. \
-    //          If you see this error message something has gone \
-    //          horribly wrong. Please open an issue at the \
-    //          diesel repository.",
-    //     )
-    // }
+    /// Returns the column names of the current result set
+    ///
+    /// The names are only requested from the host for the first row,
+    /// all later rows share them.
+    pub(super) fn column_names(&mut self) -> QueryResult<Rc<ColumnNames>> {
+        if let Some(column_names) = &self.column_names {
+            return Ok(Rc::clone(column_names));
+        }
+        let names = ffi::column_names(self.statement.statement.statement_id)?;
+        let column_names = Rc::new(ColumnNames::new(names));
+        self.column_names = Some(Rc::clone(&column_names));
+        Ok(column_names)
+    }
 }
@@ -0,0 +1,116 @@
+//! Deserialization of values borrowing from the row they are read from
+//!
+//! `FromSql` cannot return values borrowing from the raw value it is given,
+//! so diesel always copies text and blobs into `String`s and `Vec`s. Rows of
+//! `SqliteConnection` are decoded into owned values anyway, which `FromSqlRef`
+//! hands out without another copy through [`SqliteRow::get_ref`].
+
+use std::borrow::Cow;
+
+use diesel::deserialize;
+use diesel::result::UnexpectedNullError;
+use diesel::sql_types::{Binary, Nullable, Text};
+
+use crate::sqlite::{SqliteRow, SqliteValue};
+
+/// Deserializes a value of a row without copying it
+///
+/// Implemented for `&str` and `Cow<str>` as `Text`, for `&[u8]` and
+/// `Cow<[u8]>` as `Binary`, and for `Option`s of them as `Nullable` types.
+/// `&str` can only borrow text, while `Cow<str>` also reads numbers in
+/// [`DeserializationMode::Tolerant`](super::super::DeserializationMode).
+pub trait FromSqlRef<'a, ST>: Sized {
+    /// Deserializes a non-`NULL` value
+    fn from_sql_ref(value: SqliteValue<'a, 'a, 'a>) -> deserialize::Result<Self>;
+
+    /// Deserializes a value that might be `NULL`, which is an error by default
+    fn from_nullable_sql_ref(value: Option<SqliteValue<'a, 'a, 'a>>) -> deserialize::Result<Self> {
+        match value {
+            Some(value) => Self::from_sql_ref(value),
+            None => Err(Box::new(UnexpectedNullError)),
+        }
+    }
+}
+
+impl<'a> FromSqlRef<'a, Text> for &'a str {
+    fn from_sql_ref(value: SqliteValue<'a, 'a, 'a>) -> deserialize::Result<Self> {
+        match value.text()? {
+            Cow::Borrowed(text) => Ok(text),
+            Cow::Owned(_) => Err(value.error(format!(
+                "{value:?} is not stored as text and cannot be borrowed as `&str`"
+            ))),
+        }
+    }
+}
+
+impl<'a> FromSqlRef<'a, Text> for Cow<'a, str> {
+    fn from_sql_ref(value: SqliteValue<'a, 'a, 'a>) -> deserialize::Result<Self> {
+        value.text()
+    }
+}
+
+impl<'a> FromSqlRef<'a, Binary> for &'a [u8] {
+    fn from_sql_ref(value: SqliteValue<'a, 'a, 'a>) -> deserialize::Result<Self> {
+        value.blob()
+    }
+}
+
+impl<'a> FromSqlRef<'a, Binary> for Cow<'a, [u8]> {
+    fn from_sql_ref(value: SqliteValue<'a, 'a, 'a>) -> deserialize::Result<Self> {
+        value.blob().map(Cow::Borrowed)
+    }
+}
+
+impl<'a, ST, T> FromSqlRef<'a, Nullable<ST>> for Option<T>
+where
+    T: FromSqlRef<'a, ST>,
+{
+    fn from_sql_ref(value: SqliteValue<'a, 'a, 'a>) -> deserialize::Result<Self> {
+        T::from_sql_ref(value).map(Some)
+    }
+
+    fn from_nullable_sql_ref(value: Option<SqliteValue<'a, 'a, 'a>>) -> deserialize::Result<Self> {
+        value.map(T::from_sql_ref).transpose()
+    }
+}
+
+/// Like diesel's `QueryableByName`, for structs with fields borrowing from the
+/// row they are built from
+///
+/// There is no derive for this trait. Borrowed fields are read with
+/// [`SqliteRow::get_ref`], all others with diesel's `NamedRow::get`. Queries
+/// are loaded into such structs with
+/// [`SqliteConnection::load_by_name_ref`](crate::sqlite::SqliteConnection::load_by_name_ref).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use diesel::row::NamedRow;
+/// use diesel::sql_types::{Integer, Text};
+/// use lunatic_diesel::sqlite::{QueryableByNameRef, SqliteRow};
+///
+/// struct PostRef<'a> {
+///     id: i32,
+///     title: &'a str,
+/// }
+///
+/// impl<'a> QueryableByNameRef<'a> for PostRef<'a> {
+///     fn build(row: &'a SqliteRow) -> deserialize::Result<Self> {
+///         Ok(PostRef {
+///             id: NamedRow::get::<Integer, _>(row, "id")?,
+///             title: row.get_ref::<Text, _, _>("title")?,
+///         })
+///     }
+/// }
+///
+/// let mut rows = Vec::new();
+/// let posts: Vec<PostRef<'_>> = connection
+///     .load_by_name_ref(diesel::sql_query("SELECT id, title FROM posts"), &mut rows)?;
+/// for post in posts {
+///     println!("{}: {}", post.id, post.title);
+/// }
+/// ```
+pub trait QueryableByNameRef<'a>: Sized {
+    /// Builds the struct from a row loaded by `SqliteConnection`
+    fn build(row: &'a SqliteRow) -> deserialize::Result<Self>;
+}
@@ -0,0 +1,186 @@
+//! Support for the date and time types of `chrono`
+//!
+//! SQLite has no storage class for dates and times. Values are written as text
+//! in the formats used by SQLite's date and time functions, but can be read
+//! from all forms those functions accept: text, the julian day as a real
+//! number and the seconds since the unix epoch as an integer.
+//!
+//! Unlike with diesel's own SQLite backend `chrono` values cannot be used as
+//! `TimestamptzSqlite` expressions in the query builder, as implementing
+//! `AsExpression` for them would overlap with diesel's impls. They can still
+//! be bound to raw queries with `sql_query(..).bind::<TimestamptzSqlite, _>(..)`
+//! and loaded from any query.
+
+use ::chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{Date, Time, Timestamp};
+use lunatic_sqlite_api::wire_format;
+
+use super::{julian_days_to_unix_millis, TimestamptzSqlite};
+use crate::sqlite::{Sqlite, SqliteValue};
+
+const SQLITE_DATE_FORMAT: &str = "%F";
+
+const SQLITE_TIME_FORMATS: &[&str] = &[
+    // Most likely
+    "%T%.f", // All other valid formats in order of documentation
+    "%R", "%RZ", "%T%.fZ", "%R%:z", "%T%.f%:z",
+];
+
+const SQLITE_DATETIME_FORMATS: &[&str] = &[
+    // Most likely format
+    "%F %T%.f",
+    // Other formats in order of appearance in docs
+    "%F %R",
+    "%F %RZ",
+    "%F %R%:z",
+    "%F %T%.fZ",
+    "%F %T%.f%:z",
+    "%FT%R",
+    "%FT%RZ",
+    "%FT%R%:z",
+    "%FT%T%.f",
+    "%FT%T%.fZ",
+    "%FT%T%.f%:z",
+];
+
+/// The formats of `SQLITE_DATETIME_FORMATS` that carry an offset
+const SQLITE_DATETIME_WITH_OFFSET_FORMATS: &[&str] =
+    &["%F %R%:z", "%F %T%.f%:z", "%FT%R%:z", "%FT%T%.f%:z"];
+
+fn from_julian_days(julian_days: f64) -> Option<NaiveDateTime> {
+    DateTime::from_timestamp_millis(julian_days_to_unix_millis(julian_days))
+        .map(|timestamp| timestamp.naive_utc())
+}
+
+fn from_unix_epoch(seconds: i64) -> Option<NaiveDateTime> {
+    DateTime::from_timestamp(seconds, 0).map(|timestamp| timestamp.naive_utc())
+}
+
+/// Reads a timestamp from any of the forms SQLite stores them in
+///
+/// Offsets of text values are ignored, like diesel does for `Timestamp`.
+fn read_datetime(value: &SqliteValue<'_, '_, '_>) -> deserialize::Result<NaiveDateTime> {
+    let timestamp = match *value.raw_value() {
+        wire_format::SqliteValue::Text(ref text) => SQLITE_DATETIME_FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+            .or_else(|| text.parse().ok().and_then(from_julian_days)),
+        wire_format::SqliteValue::Double(julian_days) => from_julian_days(julian_days),
+        wire_format::SqliteValue::Integer(seconds) | wire_format::SqliteValue::I64(seconds) => {
+            from_unix_epoch(seconds)
+        }
+        _ => None,
+    };
+    timestamp.ok_or_else(|| value.error(format!("Invalid datetime {value:?}")))
+}
+
+/// Reads a timestamp like `read_datetime`, but takes the offset of text values
+/// into account. Values without an offset are in UTC.
+fn read_datetime_with_offset(
+    value: &SqliteValue<'_, '_, '_>,
+) -> deserialize::Result<DateTime<FixedOffset>> {
+    if let wire_format::SqliteValue::Text(ref text) = *value.raw_value() {
+        let timestamp = SQLITE_DATETIME_WITH_OFFSET_FORMATS
+            .iter()
+            .find_map(|format| DateTime::parse_from_str(text, format).ok());
+        if let Some(timestamp) = timestamp {
+            return Ok(timestamp);
+        }
+    }
+    read_datetime(value).map(|timestamp| timestamp.and_utc().fixed_offset())
+}
+
+impl FromSql<Date, Sqlite> for NaiveDate {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        if let wire_format::SqliteValue::Text(ref text) = *value.raw_value() {
+            if let Ok(date) = Self::parse_from_str(text, SQLITE_DATE_FORMAT) {
+                return Ok(date);
+            }
+        }
+        read_datetime(&value)
+            .map(|timestamp| timestamp.date())
+            .map_err(|_| value.error(format!("Invalid date {value:?}")))
+    }
+}
+
+impl ToSql<Date, Sqlite> for NaiveDate {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.format(SQLITE_DATE_FORMAT).to_string());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Time, Sqlite> for NaiveTime {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        if let wire_format::SqliteValue::Text(ref text) = *value.raw_value() {
+            let time = SQLITE_TIME_FORMATS
+                .iter()
+                .find_map(|format| Self::parse_from_str(text, format).ok());
+            if let Some(time) = time {
+                return Ok(time);
+            }
+        }
+        read_datetime(&value)
+            .map(|timestamp| timestamp.time())
+            .map_err(|_| value.error(format!("Invalid time {value:?}")))
+    }
+}
+
+impl ToSql<Time, Sqlite> for NaiveTime {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.format("%T%.f").to_string());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Timestamp, Sqlite> for NaiveDateTime {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        read_datetime(&value)
+    }
+}
+
+impl ToSql<Timestamp, Sqlite> for NaiveDateTime {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.format("%F %T%.f").to_string());
+        Ok(IsNull::No)
+    }
+}
+
+/// Reads the timestamp in UTC
+impl FromSql<TimestamptzSqlite, Sqlite> for NaiveDateTime {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        read_datetime_with_offset(&value).map(|timestamp| timestamp.naive_utc())
+    }
+}
+
+/// Writes the timestamp as UTC
+impl ToSql<TimestamptzSqlite, Sqlite> for NaiveDateTime {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.format("%F %T%.f+00:00").to_string());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<TimestamptzSqlite, Sqlite> for DateTime<Utc> {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        read_datetime_with_offset(&value).map(|timestamp| timestamp.with_timezone(&Utc))
+    }
+}
+
+/// Keeps the offset the timestamp was stored with
+impl FromSql<TimestamptzSqlite, Sqlite> for DateTime<FixedOffset> {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        read_datetime_with_offset(&value)
+    }
+}
+
+/// Writes the timestamp converted to UTC, so that stored values compare
+/// correctly as text
+impl<Tz: TimeZone> ToSql<TimestamptzSqlite, Sqlite> for DateTime<Tz> {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.naive_utc().format("%F %T%.f+00:00").to_string());
+        Ok(IsNull::No)
+    }
+}
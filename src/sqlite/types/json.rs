@@ -0,0 +1,41 @@
+//! Support for storing `serde_json` values as `Json`
+//!
+//! SQLite keeps JSON as text. Columns declared as `JSON` have a numeric type
+//! affinity though, so documents consisting of a single number are stored
+//! as integer or real and are read from those storage classes as well.
+
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Json;
+use lunatic_sqlite_api::wire_format;
+
+use crate::sqlite::{Sqlite, SqliteValue};
+
+queryable_single_value!(serde_json::Value: Json);
+
+impl FromSql<Json, Sqlite> for serde_json::Value {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        match *value.raw_value() {
+            wire_format::SqliteValue::Text(ref text) => {
+                serde_json::from_str(text).map_err(|e| value.error(e.to_string()))
+            }
+            wire_format::SqliteValue::Blob(ref bytes) => {
+                serde_json::from_slice(bytes).map_err(|e| value.error(e.to_string()))
+            }
+            wire_format::SqliteValue::Integer(number) | wire_format::SqliteValue::I64(number) => {
+                Ok(number.into())
+            }
+            wire_format::SqliteValue::Double(number) => Ok(number.into()),
+            wire_format::SqliteValue::Null => {
+                Err(value.error("Unexpected null for non-null column"))
+            }
+        }
+    }
+}
+
+impl ToSql<Json, Sqlite> for serde_json::Value {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(serde_json::to_string(self)?);
+        Ok(IsNull::No)
+    }
+}
@@ -1,5 +1,3 @@
-use bigdecimal::BigDecimal;
-use bigdecimal::FromPrimitive;
 use diesel::deserialize;
 use diesel::deserialize::FromSql;
 use diesel::serialize;
@@ -7,11 +5,41 @@ use diesel::serialize::IsNull;
 use diesel::serialize::Output;
 use diesel::serialize::ToSql;
 use diesel::sql_types;
-use diesel::sql_types::Double;
-use diesel::sql_types::Numeric;
-use lunatic_sqlite_api::wire_format::SqliteValue;
 
-use super::Sqlite;
+use super::{DeserializationMode, Sqlite, SqliteValue};
+
+/// Loads values of a foreign type as single values of each of the given SQL
+/// types, like diesel's `#[derive(FromSqlRow)]` would
+///
+/// diesel derives this itself for the types of `time`, `uuid` and
+/// `serde_json` only with one of its own backends enabled.
+#[cfg(any(feature = "time", feature = "serde_json", feature = "uuid"))]
+macro_rules! queryable_single_value {
+    ($ty:ty: $($sql_type:ty),+) => {$(
+        impl diesel::Queryable<$sql_type, crate::sqlite::Sqlite> for $ty {
+            type Row = Self;
+
+            fn build(row: Self) -> diesel::deserialize::Result<Self> {
+                Ok(row)
+            }
+        }
+    )+};
+}
+
+mod borrowed;
+#[cfg(feature = "chrono")]
+mod chrono;
+#[cfg(feature = "serde_json")]
+mod json;
+mod numeric;
+#[cfg(feature = "time")]
+mod time;
+#[cfg(feature = "uuid")]
+mod uuid;
+
+pub use self::borrowed::{FromSqlRef, QueryableByNameRef};
+#[cfg(feature = "uuid")]
+pub use self::uuid::UuidSqlite;
 
 /// The returned pointer is *only* valid for the lifetime to the argument of
 /// `from_sql`. This impl is intended for uses where you want to write a new
@@ -19,10 +47,8 @@ use super::Sqlite;
 /// raw pointer instead of a reference with a lifetime due to the structure of
 /// `FromSql`
 impl FromSql<sql_types::VarChar, Sqlite> for String {
-    fn from_sql(value: &SqliteValue) -> deserialize::Result<Self> {
-        let text = value.read_text_string();
-        // Ok(text as *const _)
-        Ok(text)
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        Ok(value.text()?.into_owned())
     }
 }
 
@@ -32,45 +58,63 @@ impl FromSql<sql_types::VarChar, Sqlite> for String {
 /// raw pointer instead of a reference with a lifetime due to the structure of
 /// `FromSql`
 impl FromSql<sql_types::Binary, Sqlite> for *const [u8] {
-    fn from_sql(bytes: &SqliteValue) -> deserialize::Result<Self> {
-        let bytes = bytes.read_blob();
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let bytes = value.blob()?;
         Ok(bytes as *const _)
     }
 }
 
 impl FromSql<sql_types::SmallInt, Sqlite> for i16 {
-    fn from_sql(value: &SqliteValue) -> deserialize::Result<Self> {
-        Ok(value.read_integer() as i16)
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let int = value.integer()?;
+        match value.mode() {
+            DeserializationMode::Strict => i16::try_from(int)
+                .map_err(|_| value.error(format!("{int} is out of range for i16"))),
+            // keeps the lower bits like `read_integer` does
+            DeserializationMode::Tolerant => Ok(int as i16),
+        }
     }
 }
 
 impl FromSql<sql_types::Integer, Sqlite> for i32 {
-    fn from_sql(value: &SqliteValue) -> deserialize::Result<Self> {
-        Ok(value.read_integer())
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let int = value.integer()?;
+        match value.mode() {
+            DeserializationMode::Strict => i32::try_from(int)
+                .map_err(|_| value.error(format!("{int} is out of range for i32"))),
+            DeserializationMode::Tolerant => Ok(int as i32),
+        }
     }
 }
 
 impl FromSql<sql_types::Bool, Sqlite> for bool {
-    fn from_sql(value: &SqliteValue) -> deserialize::Result<Self> {
-        Ok(value.read_integer() != 0)
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        Ok(value.integer()? != 0)
     }
 }
 
 impl FromSql<sql_types::BigInt, Sqlite> for i64 {
-    fn from_sql(value: &SqliteValue) -> deserialize::Result<Self> {
-        Ok(value.read_long())
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        value.integer()
     }
 }
 
 impl FromSql<sql_types::Float, Sqlite> for f32 {
-    fn from_sql(value: &SqliteValue) -> deserialize::Result<Self> {
-        Ok(value.read_double() as f32)
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let double = value.double()?;
+        let float = double as f32;
+        // precision is expected to get lost, but not the magnitude
+        if value.mode() == DeserializationMode::Strict && float.is_infinite() && double.is_finite()
+        {
+            return Err(value.error(format!("{double} is out of range for f32")));
+        }
+        Ok(float)
     }
 }
 
 impl FromSql<sql_types::Double, Sqlite> for f64 {
-    fn from_sql(value: &SqliteValue) -> deserialize::Result<Self> {
-        Ok(value.read_double())
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        value.double()
     }
 }
 
@@ -131,7 +175,7 @@ impl ToSql<sql_types::Double, Sqlite> for f64 {
 }
 
 impl FromSql<sql_types::Date, Sqlite> for String {
-    fn from_sql(value: &SqliteValue) -> deserialize::Result<Self> {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
         FromSql::<sql_types::Text, Sqlite>::from_sql(value)
     }
 }
@@ -149,7 +193,7 @@ impl ToSql<sql_types::Date, Sqlite> for String {
 }
 
 impl FromSql<sql_types::Time, Sqlite> for String {
-    fn from_sql(value: &SqliteValue) -> deserialize::Result<Self> {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
         FromSql::<sql_types::Text, Sqlite>::from_sql(value)
     }
 }
@@ -167,7 +211,7 @@ impl ToSql<sql_types::Time, Sqlite> for String {
 }
 
 impl FromSql<sql_types::Timestamp, Sqlite> for String {
-    fn from_sql(value: &SqliteValue) -> deserialize::Result<Self> {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
         FromSql::<sql_types::Text, Sqlite>::from_sql(value)
     }
 }
@@ -185,7 +229,7 @@ impl ToSql<sql_types::Timestamp, Sqlite> for String {
 }
 
 impl FromSql<TimestamptzSqlite, Sqlite> for String {
-    fn from_sql(value: &SqliteValue) -> deserialize::Result<Self> {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
         FromSql::<sql_types::Text, Sqlite>::from_sql(value)
     }
 }
@@ -202,12 +246,18 @@ impl ToSql<TimestamptzSqlite, Sqlite> for String {
     }
 }
 
-impl FromSql<Numeric, Sqlite> for BigDecimal {
-    fn from_sql(bytes: &SqliteValue) -> deserialize::Result<Self> {
-        let x = <f64 as FromSql<Double, Sqlite>>::from_sql(bytes)?;
-        BigDecimal::from_f64(x).ok_or_else(|| format!("{x} is not valid decimal number ").into())
-    }
+/// Converts a julian day into milliseconds since the unix epoch, which is the
+/// precision SQLite uses for them
+#[cfg(any(feature = "chrono", feature = "time"))]
+fn julian_days_to_unix_millis(julian_days: f64) -> i64 {
+    const EPOCH_IN_JULIAN_DAYS: f64 = 2_440_587.5;
+    const MILLIS_IN_DAY: f64 = 86_400_000.0;
+    ((julian_days - EPOCH_IN_JULIAN_DAYS) * MILLIS_IN_DAY).round() as i64
 }
 
+/// A timestamp with a timezone
+///
+/// SQLite has no dedicated type for this, values are stored as text with an
+/// offset (`YYYY-MM-DD HH:MM:SS.SSS+HH:MM`) like for [`sql_types::Timestamp`].
 #[derive(Debug, Clone, Copy, Default, QueryId, SqlType)]
 pub struct TimestamptzSqlite;
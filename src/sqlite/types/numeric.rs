@@ -0,0 +1,47 @@
+//! Support for `Numeric` values as `BigDecimal`
+//!
+//! Decimals are written as text in their canonical notation, so that no digit
+//! is lost on the way to the database. Keep in mind that SQLite converts text
+//! inserted into a column with `NUMERIC` affinity (e.g. declared as `NUMERIC`
+//! or `DECIMAL(10, 2)`) to an integer or a real number, which only keeps 15
+//! significant digits. Columns that need to store every digit have to be
+//! declared with `TEXT` affinity.
+//!
+//! Text, integers and reals are read back without losing any precision. A real
+//! is converted through its shortest decimal representation, so `0.1` is read
+//! as `0.1` instead of the exact value of the closest double.
+
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Numeric;
+use lunatic_sqlite_api::wire_format;
+
+use crate::sqlite::{Sqlite, SqliteValue};
+
+impl FromSql<Numeric, Sqlite> for BigDecimal {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        match *value.raw_value() {
+            wire_format::SqliteValue::Text(ref text) => BigDecimal::from_str(text.trim())
+                .map_err(|_| value.error(format!("{text:?} is not a valid decimal number"))),
+            wire_format::SqliteValue::Integer(int) | wire_format::SqliteValue::I64(int) => {
+                Ok(BigDecimal::from(int))
+            }
+            // `Display` of `f64` writes the shortest representation that
+            // parses back into the same double, without an exponent
+            wire_format::SqliteValue::Double(double) if double.is_finite() => {
+                Ok(BigDecimal::from_str(&double.to_string())?)
+            }
+            _ => Err(value.error(format!("{value:?} is not a valid decimal number"))),
+        }
+    }
+}
+
+impl ToSql<Numeric, Sqlite> for BigDecimal {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.to_string());
+        Ok(IsNull::No)
+    }
+}
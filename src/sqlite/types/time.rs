@@ -0,0 +1,269 @@
+//! Support for the date and time types of `time`
+//!
+//! Values are stored the same way as with the `chrono` feature, so that both
+//! can be used on the same database: they are written as text in the formats
+//! used by SQLite's date and time functions, and can be read from text, the
+//! julian day as a real number and the seconds since the unix epoch as an
+//! integer.
+//!
+//! diesel only implements `AsExpression` for these types with one of its own
+//! backends enabled, and this crate can't add the impls itself: they would
+//! overlap with diesel's impl for all `Expression`s. So values of `time` are
+//! loaded like any other value, but only bound to raw queries, e.g. with
+//! `sql_query(..).bind::<Timestamp, _>(..)`, and not used in the query builder
+//! or in `Insertable` structs.
+
+use ::time::format_description::BorrowedFormatItem;
+use ::time::macros::format_description;
+use ::time::{Date as NaiveDate, OffsetDateTime, PrimitiveDateTime, Time as NaiveTime, UtcOffset};
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{Date, Time, Timestamp};
+use lunatic_sqlite_api::wire_format;
+
+use super::{julian_days_to_unix_millis, TimestamptzSqlite};
+use crate::sqlite::{Sqlite, SqliteValue};
+
+const DATE_FORMAT: &[BorrowedFormatItem<'_>] = format_description!("[year]-[month]-[day]");
+const ENCODE_TIME_FORMAT: &[BorrowedFormatItem<'_>] =
+    format_description!("[hour]:[minute]:[second]");
+
+const TIME_FORMATS: [&[BorrowedFormatItem<'_>]; 9] = [
+    // Most likely
+    format_description!("[hour]:[minute]:[second].[subsecond]"),
+    // All other valid formats in order of increasing specificity
+    format_description!("[hour]:[minute]"),
+    format_description!("[hour]:[minute]Z"),
+    format_description!("[hour]:[minute][offset_hour sign:mandatory]:[offset_minute]"),
+    format_description!("[hour]:[minute]:[second]"),
+    format_description!("[hour]:[minute]:[second]Z"),
+    format_description!("[hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]"),
+    // here is where the most likely format would go according to the pattern
+    format_description!("[hour]:[minute]:[second].[subsecond]Z"),
+    format_description!(
+        "[hour]:[minute]:[second].[subsecond][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+];
+
+const DATETIME_FORMATS: [&[BorrowedFormatItem<'_>]; 18] = [
+    // Most likely format
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond]"),
+    // Other formats in order of increasing specificity
+    format_description!("[year]-[month]-[day] [hour]:[minute]"),
+    format_description!("[year]-[month]-[day] [hour]:[minute]Z"),
+    format_description!(
+        "[year]-[month]-[day] [hour]:[minute][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"),
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second]Z"),
+    format_description!(
+        "[year]-[month]-[day] [hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+    // here is where the most likely format would go according to the pattern
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond]Z"),
+    format_description!(
+        "[year]-[month]-[day] [hour]:[minute]:[second].[subsecond][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+    // now the same thing, with T
+    format_description!("[year]-[month]-[day]T[hour]:[minute]"),
+    format_description!("[year]-[month]-[day]T[hour]:[minute]Z"),
+    format_description!(
+        "[year]-[month]-[day]T[hour]:[minute][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]"),
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]Z"),
+    format_description!(
+        "[year]-[month]-[day]T[hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond]"),
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond]Z"),
+    format_description!(
+        "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+];
+
+/// The formats of `DATETIME_FORMATS` that carry an offset
+const DATETIME_WITH_OFFSET_FORMATS: [&[BorrowedFormatItem<'_>]; 6] = [
+    format_description!(
+        "[year]-[month]-[day] [hour]:[minute][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+    format_description!(
+        "[year]-[month]-[day] [hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+    format_description!(
+        "[year]-[month]-[day] [hour]:[minute]:[second].[subsecond][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+    format_description!(
+        "[year]-[month]-[day]T[hour]:[minute][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+    format_description!(
+        "[year]-[month]-[day]T[hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+    format_description!(
+        "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond][offset_hour sign:mandatory]:[offset_minute]"
+    ),
+];
+
+/// Writes the fractional seconds like chrono's `%.f` does, with as many
+/// groups of three digits as needed and no fraction at all for whole seconds
+fn encode_time(time: NaiveTime) -> Result<String, ::time::error::Format> {
+    let whole_seconds = time.format(ENCODE_TIME_FORMAT)?;
+    Ok(match time.nanosecond() {
+        0 => whole_seconds,
+        nanos if nanos % 1_000_000 == 0 => format!("{whole_seconds}.{:03}", nanos / 1_000_000),
+        nanos if nanos % 1_000 == 0 => format!("{whole_seconds}.{:06}", nanos / 1_000),
+        nanos => format!("{whole_seconds}.{nanos:09}"),
+    })
+}
+
+fn encode_datetime(timestamp: PrimitiveDateTime) -> Result<String, ::time::error::Format> {
+    Ok(format!(
+        "{} {}",
+        timestamp.date().format(DATE_FORMAT)?,
+        encode_time(timestamp.time())?
+    ))
+}
+
+fn naive_utc(timestamp: OffsetDateTime) -> PrimitiveDateTime {
+    let timestamp = timestamp.to_offset(UtcOffset::UTC);
+    PrimitiveDateTime::new(timestamp.date(), timestamp.time())
+}
+
+fn from_julian_days(julian_days: f64) -> Option<PrimitiveDateTime> {
+    let nanos = i128::from(julian_days_to_unix_millis(julian_days)) * 1_000_000;
+    OffsetDateTime::from_unix_timestamp_nanos(nanos)
+        .ok()
+        .map(naive_utc)
+}
+
+fn from_unix_epoch(seconds: i64) -> Option<PrimitiveDateTime> {
+    OffsetDateTime::from_unix_timestamp(seconds)
+        .ok()
+        .map(naive_utc)
+}
+
+/// Reads a timestamp from any of the forms SQLite stores them in
+///
+/// Offsets of text values are ignored, like diesel does for `Timestamp`.
+fn read_datetime(value: &SqliteValue<'_, '_, '_>) -> deserialize::Result<PrimitiveDateTime> {
+    let timestamp = match *value.raw_value() {
+        wire_format::SqliteValue::Text(ref text) => DATETIME_FORMATS
+            .iter()
+            .find_map(|format| PrimitiveDateTime::parse(text, format).ok())
+            .or_else(|| text.parse().ok().and_then(from_julian_days)),
+        wire_format::SqliteValue::Double(julian_days) => from_julian_days(julian_days),
+        wire_format::SqliteValue::Integer(seconds) | wire_format::SqliteValue::I64(seconds) => {
+            from_unix_epoch(seconds)
+        }
+        _ => None,
+    };
+    timestamp.ok_or_else(|| value.error(format!("Invalid datetime {value:?}")))
+}
+
+/// Reads a timestamp like `read_datetime`, but takes the offset of text values
+/// into account. Values without an offset are in UTC.
+fn read_datetime_with_offset(
+    value: &SqliteValue<'_, '_, '_>,
+) -> deserialize::Result<OffsetDateTime> {
+    if let wire_format::SqliteValue::Text(ref text) = *value.raw_value() {
+        let timestamp = DATETIME_WITH_OFFSET_FORMATS
+            .iter()
+            .find_map(|format| OffsetDateTime::parse(text, format).ok());
+        if let Some(timestamp) = timestamp {
+            return Ok(timestamp);
+        }
+    }
+    read_datetime(value).map(PrimitiveDateTime::assume_utc)
+}
+
+queryable_single_value!(NaiveDate: Date);
+queryable_single_value!(NaiveTime: Time);
+queryable_single_value!(PrimitiveDateTime: Timestamp, TimestamptzSqlite);
+queryable_single_value!(OffsetDateTime: TimestamptzSqlite);
+
+impl FromSql<Date, Sqlite> for NaiveDate {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        if let wire_format::SqliteValue::Text(ref text) = *value.raw_value() {
+            if let Ok(date) = Self::parse(text, DATE_FORMAT) {
+                return Ok(date);
+            }
+        }
+        read_datetime(&value)
+            .map(|timestamp| timestamp.date())
+            .map_err(|_| value.error(format!("Invalid date {value:?}")))
+    }
+}
+
+impl ToSql<Date, Sqlite> for NaiveDate {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.format(DATE_FORMAT)?);
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Time, Sqlite> for NaiveTime {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        if let wire_format::SqliteValue::Text(ref text) = *value.raw_value() {
+            let time = TIME_FORMATS
+                .iter()
+                .find_map(|format| Self::parse(text, format).ok());
+            if let Some(time) = time {
+                return Ok(time);
+            }
+        }
+        read_datetime(&value)
+            .map(|timestamp| timestamp.time())
+            .map_err(|_| value.error(format!("Invalid time {value:?}")))
+    }
+}
+
+impl ToSql<Time, Sqlite> for NaiveTime {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(encode_time(*self)?);
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Timestamp, Sqlite> for PrimitiveDateTime {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        read_datetime(&value)
+    }
+}
+
+impl ToSql<Timestamp, Sqlite> for PrimitiveDateTime {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(encode_datetime(*self)?);
+        Ok(IsNull::No)
+    }
+}
+
+/// Reads the timestamp in UTC
+impl FromSql<TimestamptzSqlite, Sqlite> for PrimitiveDateTime {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        read_datetime_with_offset(&value).map(naive_utc)
+    }
+}
+
+/// Writes the timestamp as UTC
+impl ToSql<TimestamptzSqlite, Sqlite> for PrimitiveDateTime {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(encode_datetime(*self)? + "+00:00");
+        Ok(IsNull::No)
+    }
+}
+
+/// Keeps the offset the timestamp was stored with
+impl FromSql<TimestamptzSqlite, Sqlite> for OffsetDateTime {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        read_datetime_with_offset(&value)
+    }
+}
+
+/// Writes the timestamp converted to UTC, so that stored values compare
+/// correctly as text
+impl ToSql<TimestamptzSqlite, Sqlite> for OffsetDateTime {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(encode_datetime(naive_utc(*self))? + "+00:00");
+        Ok(IsNull::No)
+    }
+}
@@ -0,0 +1,75 @@
+//! Support for `uuid::Uuid` as `UuidSqlite`, `Binary` and `Text`
+//!
+//! SQLite has no type for UUIDs. As `UuidSqlite` and `Binary` they are written as
+//! their 16 raw bytes, as `Text` in the hyphenated form. All of them read
+//! either storage form, so that tables written by other tools load as well.
+//!
+//! diesel only implements `AsExpression` for `uuid::Uuid` with one of its own
+//! backends enabled, and this crate can't add the impl itself: it would
+//! overlap with diesel's impl for all `Expression`s. So UUIDs are loaded like
+//! any other value, but only bound to raw queries, e.g. with
+//! `sql_query(..).bind::<UuidSqlite, _>(..)`, and not used in the query
+//! builder or in `Insertable` structs.
+
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{Binary, Text};
+use lunatic_sqlite_api::wire_format;
+
+use crate::sqlite::{Sqlite, SqliteValue};
+
+/// A UUID
+///
+/// SQLite has no dedicated type for this, values are stored as their 16 raw
+/// bytes like for [`Binary`].
+#[derive(Debug, Clone, Copy, Default, QueryId, SqlType)]
+pub struct UuidSqlite;
+
+queryable_single_value!(uuid::Uuid: UuidSqlite, Binary, Text);
+
+/// Reads a UUID from its raw bytes or from any of its text forms
+fn read_uuid(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<uuid::Uuid> {
+    match *value.raw_value() {
+        wire_format::SqliteValue::Blob(ref bytes) => Ok(uuid::Uuid::from_slice(bytes)?),
+        wire_format::SqliteValue::Text(ref text) => Ok(uuid::Uuid::parse_str(text)?),
+        _ => Err(value.error(format!("{value:?} is not a valid UUID"))),
+    }
+}
+
+impl FromSql<UuidSqlite, Sqlite> for uuid::Uuid {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        read_uuid(value)
+    }
+}
+
+impl ToSql<UuidSqlite, Sqlite> for uuid::Uuid {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        <Self as ToSql<Binary, Sqlite>>::to_sql(self, out)
+    }
+}
+
+impl FromSql<Binary, Sqlite> for uuid::Uuid {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        read_uuid(value)
+    }
+}
+
+impl ToSql<Binary, Sqlite> for uuid::Uuid {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_bytes().as_slice());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for uuid::Uuid {
+    fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        read_uuid(value)
+    }
+}
+
+impl ToSql<Text, Sqlite> for uuid::Uuid {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.hyphenated().to_string());
+        Ok(IsNull::No)
+    }
+}